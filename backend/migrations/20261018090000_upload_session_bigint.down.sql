@@ -0,0 +1 @@
+ALTER TABLE upload_session ALTER COLUMN starting_byte_index TYPE INTEGER;
@@ -0,0 +1 @@
+DROP TABLE manifest_referrer;
@@ -0,0 +1,13 @@
+CREATE TABLE manifest_referrer (
+     manifest_id UUID PRIMARY KEY REFERENCES manifest(id),
+
+     subject_digest TEXT NOT NULL,
+     media_type TEXT NOT NULL,
+     artifact_type TEXT,
+     size BIGINT NOT NULL,
+     annotations JSONB,
+
+     created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX manifest_referrer_subject_digest_idx ON manifest_referrer(subject_digest);
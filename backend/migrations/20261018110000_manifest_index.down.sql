@@ -0,0 +1,5 @@
+DROP TABLE manifest_child;
+
+DELETE FROM manifest_referrer WHERE manifest_id IN (SELECT id FROM manifest WHERE blob_id IS NULL);
+DELETE FROM manifest WHERE blob_id IS NULL;
+ALTER TABLE manifest ALTER COLUMN blob_id SET NOT NULL;
@@ -0,0 +1,17 @@
+-- Image indexes don't have a config blob.
+ALTER TABLE manifest ALTER COLUMN blob_id DROP NOT NULL;
+
+CREATE TABLE manifest_child (
+     index_id UUID NOT NULL REFERENCES manifest(id),
+     child_id UUID NOT NULL REFERENCES manifest(id),
+
+     media_type TEXT NOT NULL,
+     size BIGINT NOT NULL,
+     platform JSONB,
+
+     created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+     PRIMARY KEY (index_id, child_id)
+);
+
+CREATE INDEX manifest_child_child_id_idx ON manifest_child(child_id);
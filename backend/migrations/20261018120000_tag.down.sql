@@ -0,0 +1,16 @@
+-- Manifests with several tags only keep their most recently updated one.
+ALTER TABLE manifest DROP CONSTRAINT manifest_repository_digest_key;
+ALTER TABLE manifest ADD COLUMN tag TEXT;
+
+UPDATE manifest m
+SET tag = (
+     SELECT t.name
+     FROM tag t
+     WHERE t.manifest_id = m.id
+     ORDER BY t.updated_at DESC
+     LIMIT 1
+);
+
+ALTER TABLE manifest ADD CONSTRAINT manifest_repository_tag_key UNIQUE NULLS NOT DISTINCT (repository, tag);
+
+DROP TABLE tag;
@@ -0,0 +1,75 @@
+CREATE TABLE tag (
+     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+
+     repository TEXT NOT NULL REFERENCES repository(namespace_name),
+     name TEXT NOT NULL,
+     manifest_id UUID NOT NULL REFERENCES manifest(id),
+
+     created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+     updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+     UNIQUE (repository, name)
+);
+
+CREATE INDEX tag_manifest_id_idx ON tag(manifest_id);
+
+-- Every manifest row with the same digest in a repository is merged into the oldest one.
+CREATE TEMPORARY TABLE manifest_canonical AS
+SELECT m.id, (
+     SELECT c.id
+     FROM manifest c
+     WHERE c.repository = m.repository AND c.digest = m.digest
+     ORDER BY c.created_at ASC, c.id ASC
+     LIMIT 1
+) AS canonical_id
+FROM manifest m;
+
+INSERT INTO tag(repository, name, manifest_id, created_at, updated_at)
+SELECT m.repository, m.tag, mc.canonical_id, m.created_at, m.created_at
+FROM manifest m
+JOIN manifest_canonical mc ON mc.id = m.id
+WHERE m.tag IS NOT NULL;
+
+INSERT INTO manifest_layer(manifest_id, blob_id, media_type, size, created_at)
+SELECT mc.canonical_id, l.blob_id, l.media_type, l.size, l.created_at
+FROM manifest_layer l
+JOIN manifest_canonical mc ON mc.id = l.manifest_id
+WHERE mc.id <> mc.canonical_id
+ON CONFLICT DO NOTHING;
+
+DELETE FROM manifest_layer l
+USING manifest_canonical mc
+WHERE mc.id = l.manifest_id AND mc.id <> mc.canonical_id;
+
+INSERT INTO manifest_referrer(manifest_id, subject_digest, media_type, artifact_type, size, annotations, created_at)
+SELECT mc.canonical_id, r.subject_digest, r.media_type, r.artifact_type, r.size, r.annotations, r.created_at
+FROM manifest_referrer r
+JOIN manifest_canonical mc ON mc.id = r.manifest_id
+WHERE mc.id <> mc.canonical_id
+ON CONFLICT DO NOTHING;
+
+DELETE FROM manifest_referrer r
+USING manifest_canonical mc
+WHERE mc.id = r.manifest_id AND mc.id <> mc.canonical_id;
+
+INSERT INTO manifest_child(index_id, child_id, media_type, size, platform, created_at)
+SELECT ci.canonical_id, cc.canonical_id, c.media_type, c.size, c.platform, c.created_at
+FROM manifest_child c
+JOIN manifest_canonical ci ON ci.id = c.index_id
+JOIN manifest_canonical cc ON cc.id = c.child_id
+WHERE ci.id <> ci.canonical_id OR cc.id <> cc.canonical_id
+ON CONFLICT DO NOTHING;
+
+DELETE FROM manifest_child c
+USING manifest_canonical ci, manifest_canonical cc
+WHERE ci.id = c.index_id AND cc.id = c.child_id
+  AND (ci.id <> ci.canonical_id OR cc.id <> cc.canonical_id);
+
+DELETE FROM manifest m
+USING manifest_canonical mc
+WHERE mc.id = m.id AND mc.id <> mc.canonical_id;
+
+DROP TABLE manifest_canonical;
+
+ALTER TABLE manifest DROP COLUMN tag;
+ALTER TABLE manifest ADD CONSTRAINT manifest_repository_digest_key UNIQUE (repository, digest);
@@ -0,0 +1,2 @@
+ALTER TABLE blob DROP COLUMN media_type;
+ALTER TABLE blob DROP COLUMN size;
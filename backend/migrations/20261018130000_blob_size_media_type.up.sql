@@ -0,0 +1,3 @@
+-- The size of existing blobs is filled in from their files when the registry starts.
+ALTER TABLE blob ADD COLUMN size BIGINT;
+ALTER TABLE blob ADD COLUMN media_type TEXT;
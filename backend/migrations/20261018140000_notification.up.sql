@@ -0,0 +1,25 @@
+CREATE TABLE notification_endpoint (
+     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+
+     repository TEXT NOT NULL REFERENCES repository(namespace_name),
+     url TEXT NOT NULL,
+
+     created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+     UNIQUE (repository, url)
+);
+
+-- Events waiting to be delivered to an endpoint, rows are removed once delivered.
+CREATE TABLE notification (
+     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+
+     endpoint TEXT NOT NULL,
+     payload JSONB NOT NULL,
+     attempts INTEGER NOT NULL DEFAULT 0,
+     next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+     last_error TEXT,
+
+     created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX notification_next_attempt_at_idx ON notification(next_attempt_at);
@@ -0,0 +1,2 @@
+-- Repositories are private unless their owner makes them public.
+ALTER TABLE repository ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,11 @@
+-- Users of the local auth service. Passwords are argon2 PHC strings, users imported from an
+-- htpasswd file keep their bcrypt hash until they first log in.
+CREATE TABLE local_user (
+     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+
+     username TEXT NOT NULL UNIQUE,
+     password_hash TEXT NOT NULL,
+
+     created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+     updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,19 @@
+-- The auth provider that a username belongs to. Usernames are bound to the first provider that
+-- authenticates them, so another provider can't hand out the same name and take over its
+-- repositories. Local users belong to the local provider, the owners that existed before are
+-- users of the accounts service, which used to be the only provider.
+CREATE TABLE auth_identity (
+     username TEXT PRIMARY KEY,
+     provider TEXT NOT NULL,
+
+     created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO auth_identity(username, provider)
+SELECT username, 'local'
+FROM local_user;
+
+INSERT INTO auth_identity(username, provider)
+SELECT username, 'accounts-rs'
+FROM owner
+ON CONFLICT (username) DO NOTHING;
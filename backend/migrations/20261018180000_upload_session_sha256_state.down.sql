@@ -0,0 +1 @@
+ALTER TABLE upload_session DROP COLUMN sha256_state;
@@ -0,0 +1 @@
+ALTER TABLE upload_session ADD COLUMN sha256_state BYTEA;
@@ -1 +0,0 @@
-pub mod accounts_rs;
@@ -3,10 +3,21 @@ use sqlx::Pool;
 use uuid::Uuid;
 
 use crate::{
-    api::container_spec::{Auth, DOCKER_UPLOAD_UUID_HEADER_NAME, RANGE_HEADER_NAME},
+    api::container_spec::{
+        errors::OCIErrorResponse, Auth, DOCKER_CONTENT_DIGEST_HEADER_NAME,
+        DOCKER_UPLOAD_UUID_HEADER_NAME, LOCATION_HEADER_NAME, RANGE_HEADER_NAME,
+    },
     db::DB,
     header, location,
-    services::upload_blob_service,
+    registry_error::RegistryError,
+    services::{authorization_service, upload_blob_service},
+    storage::Storage,
+    types::{
+        digest::Digest,
+        repository_name::RepositoryName,
+        resource_scope::{ResourceScope, PULL_ACTION},
+    },
+    upstream_registry::Proxy,
 };
 
 #[derive(Responder, Debug)]
@@ -17,26 +28,55 @@ pub struct CreateSessionResponseData<'a> {
     docker_upload_uuid: Header<'a>,
 }
 
+#[derive(Responder, Debug)]
+pub struct MountBlobResponseData<'a> {
+    response: &'a str,
+    location: Header<'a>,
+    docker_content_digest: Header<'a>,
+}
+
 #[derive(Responder)]
 pub enum CreateSessionResponse<'a> {
     #[response(status = 202)]
     Success(CreateSessionResponseData<'a>),
-    #[response(status = 500)]
-    Failure(&'a str),
+    #[response(status = 201)]
+    Mounted(MountBlobResponseData<'a>),
+    Error(OCIErrorResponse),
 }
 
-#[post("/v2/<name>/blobs/uploads")]
+// Ranked after the monolithic upload which also requires the `digest` parameter.
+#[post("/v2/<name>/blobs/uploads?<mount>&<from>", rank = 2)]
 pub async fn post_create_session<'a>(
     db_pool: &State<Pool<DB>>,
+    storage: &State<Storage>,
+    proxy: &State<Proxy>,
     auth: Auth,
-    name: &str,
+    name: Result<RepositoryName, RegistryError>,
+    mount: Option<&str>,
+    from: Option<&str>,
 ) -> CreateSessionResponse<'a> {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting upload session creation, err: {err:?}");
+            return CreateSessionResponse::Error(err.into());
+        }
+    };
+
+    if let (Some(digest), Some(from)) = (mount, from) {
+        if let Some(response) =
+            try_mount_blob(db_pool, storage, proxy, &auth, &name, from, digest).await
+        {
+            return response;
+        }
+    }
+
     let initial_session_id: Uuid =
-        match upload_blob_service::create_session(db_pool, &auth.username, name).await {
+        match upload_blob_service::create_session(db_pool, &auth.username, &name).await {
             Ok(id) => id.into(),
             Err(e) => {
                 error!("Failed to create upload session, err: {e:?}");
-                return CreateSessionResponse::Failure("Failed to ceate session");
+                return CreateSessionResponse::Error(e.into());
             }
         };
 
@@ -50,3 +90,57 @@ pub async fn post_create_session<'a>(
         ),
     })
 }
+
+/// Mounting is only an optimisation, whenever it isn't possible the client is given a regular
+/// upload session instead. That includes blobs from repositories the user may not pull from.
+async fn try_mount_blob<'a>(
+    db_pool: &Pool<DB>,
+    storage: &Storage,
+    proxy: &Proxy,
+    auth: &Auth,
+    name: &RepositoryName,
+    from: &str,
+    digest: &str,
+) -> Option<CreateSessionResponse<'a>> {
+    let from = match RepositoryName::parse(from) {
+        Ok(from) => from,
+        Err(err) => {
+            warn!("Not mounting blob from invalid repository, err: {err:?}");
+            return None;
+        }
+    };
+
+    let from_scope = ResourceScope::repository(&from, &[PULL_ACTION]);
+    if let Err(err) =
+        authorization_service::authorize(db_pool, proxy, Some(&auth.username), &from_scope).await
+    {
+        warn!("Not mounting blob from {from}, err: {err:?}");
+        return None;
+    }
+
+    let digest = match Digest::parse(digest) {
+        Ok(digest) => digest,
+        Err(err) => {
+            warn!("Not mounting blob with invalid digest, err: {err:?}");
+            return None;
+        }
+    };
+
+    match upload_blob_service::mount_blob(db_pool, storage, &auth.username, name, &from, &digest)
+        .await
+    {
+        Ok(Some(blob)) => Some(CreateSessionResponse::Mounted(MountBlobResponseData {
+            response: "Blob mounted successfully",
+            location: header!(
+                LOCATION_HEADER_NAME,
+                format!("/v2/{name}/blobs/{}", blob.digest)
+            ),
+            docker_content_digest: header!(DOCKER_CONTENT_DIGEST_HEADER_NAME, blob.digest),
+        })),
+        Ok(None) => None,
+        Err(err) => {
+            error!("Failed to mount blob {digest} from {from}, err: {err:?}");
+            None
+        }
+    }
+}
@@ -1,41 +1,78 @@
 use rocket::State;
 use sqlx::Pool;
 
+use crate::api::container_spec::errors::OCIErrorResponse;
 use crate::api::container_spec::Auth;
+use crate::config::Config;
+use crate::db::DB;
 use crate::registry_error::RegistryError;
-use crate::services::delete_blob_service;
-use crate::{config::Config, db::DB};
+use crate::services::{delete_blob_service, notification_service};
+use crate::storage::Storage;
+use crate::types::{
+    digest::Digest,
+    notification_event::{EventAction, EventRequest, EventTarget},
+    repository_name::RepositoryName,
+};
 
 #[derive(Responder)]
 pub enum DeleteBlobResponse {
     #[response(status = 202)]
     Success(()),
-    #[response(status = 404)]
-    NotFound(()),
-    #[response(status = 500)]
-    Failure(()),
+    Error(OCIErrorResponse),
 }
 
 #[delete("/v2/<name>/blobs/<digest>")]
 pub async fn delete_blob(
     db_pool: &State<Pool<DB>>,
     config: &State<Config>,
-    _auth: Auth,
-    name: &str,
-    digest: &str,
+    storage: &State<Storage>,
+    auth: Auth,
+    event_request: EventRequest,
+    name: Result<RepositoryName, RegistryError>,
+    digest: Result<Digest, RegistryError>,
 ) -> DeleteBlobResponse {
-    if let Err(err) = delete_blob_service::delete_blob(db_pool, config, name, digest).await {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting blob deletion, err: {err:?}");
+            return DeleteBlobResponse::Error(err.into());
+        }
+    };
+
+    let digest = match digest {
+        Ok(digest) => digest,
+        Err(err) => {
+            warn!("Rejecting blob deletion, err: {err:?}");
+            return DeleteBlobResponse::Error(err.into());
+        }
+    };
+
+    if let Err(err) = delete_blob_service::delete_blob(db_pool, storage, &name, &digest).await {
         match err {
             RegistryError::BlobNotFound => {
                 warn!("Request to delete blob that could not be found {name} ({digest})");
-                return DeleteBlobResponse::NotFound(());
             }
-            err => {
+            ref err => {
                 error!("Failed to delete blob, err: {err:?}");
-                return DeleteBlobResponse::Failure(());
             }
         }
+        return DeleteBlobResponse::Error(err.into());
     }
 
+    let target = EventTarget {
+        digest: Some(digest.to_string()),
+        repository: name.to_string(),
+        ..Default::default()
+    };
+    notification_service::notify(
+        db_pool,
+        config,
+        EventAction::Delete,
+        target,
+        &event_request,
+        Some(&auth.username),
+    )
+    .await;
+
     DeleteBlobResponse::Success(())
 }
@@ -0,0 +1,52 @@
+use rocket::State;
+use sqlx::Pool;
+
+use crate::api::container_spec::errors::OCIErrorResponse;
+use crate::api::container_spec::Auth;
+use crate::config::Config;
+use crate::db::DB;
+use crate::registry_error::{RegistryError, RegistryResult};
+use crate::services::delete_upload_session_service;
+use crate::types::{repository_name::RepositoryName, session_id::SessionId};
+
+#[derive(Responder)]
+pub enum DeleteUploadSessionResponse {
+    #[response(status = 204)]
+    Success(()),
+    Error(OCIErrorResponse),
+}
+
+#[delete("/v2/<name>/blobs/uploads/<session_id>")]
+pub async fn delete_upload_session(
+    db_pool: &State<Pool<DB>>,
+    config: &State<Config>,
+    _auth: Auth,
+    name: Result<RepositoryName, RegistryError>,
+    session_id: &str,
+) -> DeleteUploadSessionResponse {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting upload cancellation, err: {err:?}");
+            return DeleteUploadSessionResponse::Error(err.into());
+        }
+    };
+
+    if let Err(err) = cancel_upload(db_pool, config, &name, session_id).await {
+        warn!("Failed to cancel upload session, err: {err:?}");
+        return DeleteUploadSessionResponse::Error(err.into());
+    }
+
+    DeleteUploadSessionResponse::Success(())
+}
+
+async fn cancel_upload(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    name: &str,
+    session_id: &str,
+) -> RegistryResult<()> {
+    let session_id = SessionId::parse(session_id)?;
+
+    delete_upload_session_service::cancel_upload(db_pool, config, name, session_id).await
+}
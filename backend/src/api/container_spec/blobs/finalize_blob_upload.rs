@@ -1,15 +1,17 @@
 use rocket::{http::Header, State};
 use sqlx::Pool;
 
+use crate::api::container_spec::errors::OCIErrorResponse;
 use crate::api::container_spec::Auth;
 use crate::header;
 use crate::{
     api::container_spec::{blobs::utils::octet_stream::OctetStream, LOCATION_HEADER_NAME},
     config::Config,
     db::DB,
-    registry_error::RegistryResult,
+    registry_error::{RegistryError, RegistryResult},
     services::upload_blob_service,
-    types::session_id::SessionId,
+    storage::Storage,
+    types::{digest::Digest, repository_name::RepositoryName, session_id::SessionId},
 };
 
 use super::utils::content_length::ContentLength;
@@ -24,36 +26,44 @@ pub struct FinishBlobUploadResponseData<'a> {
 pub enum FinishBlobUploadResponse<'a> {
     #[response(status = 201)]
     Success(FinishBlobUploadResponseData<'a>),
-    #[response(status = 500)]
-    Failure(&'a str),
-    #[response(status = 400)]
-    InvalidSessionId(&'a str),
+    Error(OCIErrorResponse),
 }
 
+#[allow(clippy::too_many_arguments)]
 #[put("/v2/<name>/blobs/uploads/<session_id>?<digest>", data = "<blob>")]
 pub async fn put_upload_blob<'a>(
     _auth: Auth,
-    name: &str,
+    name: Result<RepositoryName, RegistryError>,
     session_id: &'a str,
     digest: &'a str,
     content_length: ContentLength,
-    blob: Option<OctetStream>,
+    blob: Option<OctetStream<'_>>,
     config: &State<Config>,
+    storage: &State<Storage>,
     db_pool: &State<Pool<DB>>,
 ) -> FinishBlobUploadResponse<'a> {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting blob upload finalization, err: {err:?}");
+            return FinishBlobUploadResponse::Error(err.into());
+        }
+    };
+
     if let Err(err) = finalize_blob_upload(
         db_pool,
         config,
+        storage,
         content_length,
         session_id,
-        name,
+        &name,
         blob,
         digest,
     )
     .await
     {
         warn!("Failed to finalize blob upload due to error: {err:?}");
-        return FinishBlobUploadResponse::Failure("Failed to finalize blob upload");
+        return FinishBlobUploadResponse::Error(err.into());
     };
 
     FinishBlobUploadResponse::Success(FinishBlobUploadResponseData {
@@ -62,41 +72,60 @@ pub async fn put_upload_blob<'a>(
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn finalize_blob_upload(
     db_pool: &Pool<DB>,
     config: &Config,
+    storage: &Storage,
     content_length: ContentLength,
     session_id: &str,
     name: &str,
-    blob: Option<OctetStream>,
+    blob: Option<OctetStream<'_>>,
     digest: &str,
 ) -> RegistryResult<()> {
     let session_id = SessionId::parse(session_id)?;
+    let digest = Digest::parse(digest)?;
 
-    let final_session_id = if let Some(blob) = blob {
-        let blob = blob.data;
-
-        content_length.validate_data_length(blob.len())?;
+    // Docker finalizes chunked uploads with an empty body which shouldn't count as a chunk.
+    let blob = match blob {
+        Some(mut blob) => (!blob.is_empty().await).then_some(blob),
+        None => None,
+    };
 
-        upload_blob_service::upload_blob(db_pool, name, session_id, config, blob, None)
-            .await
-            .map_err(|err| {
-                error!("Failed to upload final blob section, err {err:?}");
-                err
-            })?
-            .id
-            .into()
+    let final_session_id = if let Some(blob) = blob {
+        upload_blob_service::upload_blob(
+            db_pool,
+            name,
+            session_id,
+            config,
+            blob.into_stream(),
+            None,
+            content_length.length,
+        )
+        .await
+        .map_err(|err| {
+            error!("Failed to upload final blob section, err {err:?}");
+            err
+        })?
+        .id
+        .into()
     } else {
         session_id
     };
 
-    let _blob_id =
-        upload_blob_service::finish_blob_upload(db_pool, config, name, final_session_id, digest)
-            .await
-            .map_err(|err| {
-                error!("Failed to convert blob parts to finalized blob, err {err:?}");
-                err
-            })?;
+    let _blob_id = upload_blob_service::finish_blob_upload(
+        db_pool,
+        config,
+        storage,
+        name,
+        final_session_id,
+        &digest,
+    )
+    .await
+    .map_err(|err| {
+        error!("Failed to convert blob parts to finalized blob, err {err:?}");
+        err
+    })?;
 
     Ok(())
 }
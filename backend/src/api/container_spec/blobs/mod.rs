@@ -1,5 +1,6 @@
 pub mod create_session;
 pub mod delete_blob;
+pub mod delete_session;
 pub mod finalize_blob_upload;
 pub mod read_blob;
 pub mod read_session;
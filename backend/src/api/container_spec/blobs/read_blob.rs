@@ -1,55 +1,295 @@
+use std::io::Cursor;
+
 use rocket::{
-    fs::NamedFile,
     http::{ContentType, Header},
-    State,
+    response::{self, Responder},
+    tokio::io::AsyncReadExt,
+    Request, Response, State,
 };
 use sqlx::Pool;
 
 use crate::{
-    api::container_spec::DOCKER_CONTENT_DIGEST_HEADER_NAME, config::Config, db::DB,
-    services::get_blob_service,
+    api::container_spec::{
+        errors::{OCIError, OCIErrorResponse},
+        OptionalAuth, ACCEPT_RANGES_HEADER_NAME, CONTENT_LENGTH_HEADER_NAME,
+        CONTENT_RANGE_HEADER_NAME, DOCKER_CONTENT_DIGEST_HEADER_NAME,
+    },
+    config::Config,
+    db::DB,
+    header,
+    models::blob::Blob,
+    registry_error::{RegistryError, RegistryResult},
+    services::{get_blob_service, proxy_service},
+    storage::{Storage, StorageMetadata, StorageReader},
+    types::{digest::Digest, repository_name::RepositoryName},
+    upstream_registry::Proxy,
 };
 
+use super::utils::range::Range;
+
+const BYTES_RANGE_UNIT: &str = "bytes";
+
 #[derive(Responder)]
 pub struct GetBlobResponseData<'a> {
-    file: NamedFile,
+    body: BlobBody,
+    content_type: ContentType,
+    digest: Header<'a>,
+    accept_ranges: Header<'a>,
+}
+
+#[derive(Responder)]
+pub struct GetPartialBlobResponseData<'a> {
+    body: BlobBody,
     content_type: ContentType,
+    content_range: Header<'a>,
     digest: Header<'a>,
+    accept_ranges: Header<'a>,
+}
+
+#[derive(Responder)]
+pub struct RangeNotSatisfiableResponseData<'a> {
+    error: OCIErrorResponse,
+    content_range: Header<'a>,
+}
+
+/// A section of a blob, the reader must already be positioned at the start of the section.
+/// Without a reader only the length is sent, which is all a HEAD request needs.
+pub struct BlobBody {
+    reader: Option<StorageReader>,
+    length: u64,
+}
+
+impl<'r> Responder<'r, 'static> for BlobBody {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        match self.reader {
+            Some(reader) => Response::build()
+                .raw_header(CONTENT_LENGTH_HEADER_NAME, self.length.to_string())
+                .streamed_body(reader.take(self.length))
+                .ok(),
+            // Rocket strips the body of HEAD responses but keeps its declared size as the
+            // `Content-Length`, so the empty body is never read.
+            None => Response::build()
+                .sized_body(self.length as usize, Cursor::new(vec![]))
+                .ok(),
+        }
+    }
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Responder)]
 pub enum GetBlobResponse<'a> {
     #[response(status = 200)]
     Found(GetBlobResponseData<'a>),
-    #[response(status = 404)]
-    NotFound(()),
-    #[response(status = 500)]
-    Err(String),
+    #[response(status = 206)]
+    PartialContent(GetPartialBlobResponseData<'a>),
+    RangeNotSatisfiable(RangeNotSatisfiableResponseData<'a>),
+    Error(OCIErrorResponse),
 }
 
+#[allow(clippy::too_many_arguments)]
 #[get("/v2/<name>/blobs/<digest>")]
 pub async fn get_blob<'a>(
-    name: &str,
-    digest: &str,
+    name: Result<RepositoryName, RegistryError>,
+    digest: Result<Digest, RegistryError>,
+    range: Result<Range, String>,
     db_pool: &State<Pool<DB>>,
+    storage: &State<Storage>,
     config: &State<Config>,
+    proxy: &State<Proxy>,
+    _auth: OptionalAuth,
 ) -> GetBlobResponse<'a> {
-    match get_blob_service::find_blob_by_digest(db_pool, config, name, digest).await {
-        Ok(Some((blob, file))) => {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting blob lookup, err: {err:?}");
+            return GetBlobResponse::Error(err.into());
+        }
+    };
+
+    let digest = match digest {
+        Ok(digest) => digest,
+        Err(err) => {
+            warn!("Rejecting blob lookup, err: {err:?}");
+            return GetBlobResponse::Error(err.into());
+        }
+    };
+
+    // Only requests for the whole blob can be answered whilst it's pulled from the upstream.
+    let stream = matches!(range, Ok(Range { spec: None }));
+    match proxy_service::cache_blob(db_pool, config, storage, proxy, &name, &digest, stream).await {
+        Ok(Some(upstream_blob)) => {
+            info!("Streaming blob {digest} from upstream");
+            return GetBlobResponse::Found(GetBlobResponseData {
+                body: BlobBody {
+                    reader: Some(upstream_blob.reader),
+                    length: upstream_blob.size,
+                },
+                content_type: content_type(&upstream_blob.blob),
+                digest: header!(DOCKER_CONTENT_DIGEST_HEADER_NAME, upstream_blob.blob.digest),
+                accept_ranges: header!(ACCEPT_RANGES_HEADER_NAME, BYTES_RANGE_UNIT),
+            });
+        }
+        Ok(None) => {}
+        Err(e) => {
+            error!("Failed to pull blob {digest} from upstream, err: {e:?}");
+            return GetBlobResponse::Error(e.into());
+        }
+    }
+
+    match get_blob_service::find_blob_by_digest(db_pool, storage, &name, &digest).await {
+        Ok(Some((blob, metadata))) => {
+            info!("Blob exists {}", blob.digest);
+            blob_response(storage, &digest, blob, metadata, range)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to read blob range, err: {e:?}");
+                    GetBlobResponse::Error(e.into())
+                })
+        }
+        Ok(None) => {
+            info!("Blob does not exist {digest}");
+            GetBlobResponse::Error(OCIError::BlobUnknown.into())
+        }
+        Err(e) => {
+            error!("Failed to find blob, err: {e:?}");
+            GetBlobResponse::Error(e.into())
+        }
+    }
+}
+
+async fn blob_response<'a>(
+    storage: &Storage,
+    digest: &Digest,
+    blob: Blob,
+    metadata: StorageMetadata,
+    range: Result<Range, String>,
+) -> RegistryResult<GetBlobResponse<'a>> {
+    let size = metadata.size;
+
+    let spec = match range {
+        Ok(Range { spec: None }) => {
+            let reader = get_blob_service::read_blob(storage, digest, 0).await?;
+            return Ok(GetBlobResponse::Found(GetBlobResponseData {
+                body: BlobBody {
+                    reader: Some(reader),
+                    length: size,
+                },
+                content_type: content_type(&blob),
+                digest: header!(DOCKER_CONTENT_DIGEST_HEADER_NAME, blob.digest),
+                accept_ranges: header!(ACCEPT_RANGES_HEADER_NAME, BYTES_RANGE_UNIT),
+            }));
+        }
+        Ok(Range { spec: Some(spec) }) => spec,
+        Err(err) => {
+            warn!(
+                "Rejecting range request for blob {}, err: {err}",
+                blob.digest
+            );
+            return Ok(range_not_satisfiable(size));
+        }
+    };
+
+    let Some((start, end)) = spec.resolve(size) else {
+        warn!(
+            "Range {spec:?} can't be satisfied for blob {} of size {size}",
+            blob.digest
+        );
+        return Ok(range_not_satisfiable(size));
+    };
+
+    info!("Serving bytes {start}-{end} of blob {}", blob.digest);
+    let reader = get_blob_service::read_blob(storage, digest, start).await?;
+
+    Ok(GetBlobResponse::PartialContent(
+        GetPartialBlobResponseData {
+            body: BlobBody {
+                reader: Some(reader),
+                length: end - start + 1,
+            },
+            content_type: content_type(&blob),
+            content_range: header!(
+                CONTENT_RANGE_HEADER_NAME,
+                format!("{BYTES_RANGE_UNIT} {start}-{end}/{size}")
+            ),
+            digest: header!(DOCKER_CONTENT_DIGEST_HEADER_NAME, blob.digest),
+            accept_ranges: header!(ACCEPT_RANGES_HEADER_NAME, BYTES_RANGE_UNIT),
+        },
+    ))
+}
+
+/// Blobs are served with the media type of the descriptor that referred to them, blobs that no
+/// manifest refers to yet are served as opaque bytes.
+fn content_type(blob: &Blob) -> ContentType {
+    blob.media_type
+        .as_deref()
+        .and_then(ContentType::parse_flexible)
+        .unwrap_or(ContentType::Binary)
+}
+
+fn range_not_satisfiable<'a>(size: u64) -> GetBlobResponse<'a> {
+    GetBlobResponse::RangeNotSatisfiable(RangeNotSatisfiableResponseData {
+        error: RegistryError::RangeNotSatisfiable.into(),
+        content_range: header!(
+            CONTENT_RANGE_HEADER_NAME,
+            format!("{BYTES_RANGE_UNIT} */{size}")
+        ),
+    })
+}
+
+/// Only the metadata of the blob is looked up, its content is never read.
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum HeadBlobResponse<'a> {
+    #[response(status = 200)]
+    Found(GetBlobResponseData<'a>),
+    Error(OCIErrorResponse),
+}
+
+#[head("/v2/<name>/blobs/<digest>")]
+pub async fn head_blob<'a>(
+    name: Result<RepositoryName, RegistryError>,
+    digest: Result<Digest, RegistryError>,
+    db_pool: &State<Pool<DB>>,
+    storage: &State<Storage>,
+    proxy: &State<Proxy>,
+    _auth: OptionalAuth,
+) -> HeadBlobResponse<'a> {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting blob lookup, err: {err:?}");
+            return HeadBlobResponse::Error(err.into());
+        }
+    };
+
+    let digest = match digest {
+        Ok(digest) => digest,
+        Err(err) => {
+            warn!("Rejecting blob lookup, err: {err:?}");
+            return HeadBlobResponse::Error(err.into());
+        }
+    };
+
+    match proxy_service::stat_blob(db_pool, storage, proxy, &name, &digest).await {
+        Ok(Some((blob, size))) => {
             info!("Blob exists {}", blob.digest);
-            GetBlobResponse::Found(GetBlobResponseData {
-                file,
-                content_type: ContentType::GZIP,
-                digest: Header::new(DOCKER_CONTENT_DIGEST_HEADER_NAME, blob.digest),
+            HeadBlobResponse::Found(GetBlobResponseData {
+                body: BlobBody {
+                    reader: None,
+                    length: size,
+                },
+                content_type: content_type(&blob),
+                digest: header!(DOCKER_CONTENT_DIGEST_HEADER_NAME, blob.digest),
+                accept_ranges: header!(ACCEPT_RANGES_HEADER_NAME, BYTES_RANGE_UNIT),
             })
         }
         Ok(None) => {
             info!("Blob does not exist {digest}");
-            GetBlobResponse::NotFound(())
+            HeadBlobResponse::Error(OCIError::BlobUnknown.into())
         }
         Err(e) => {
             error!("Failed to find blob, err: {e:?}");
-            GetBlobResponse::Err("Something went wrong whilst looking for blob".to_string())
+            HeadBlobResponse::Error(e.into())
         }
     }
 }
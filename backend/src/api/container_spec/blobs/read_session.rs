@@ -1,12 +1,13 @@
 use rocket::{http::Header, State};
 use sqlx::Pool;
 
+use crate::api::container_spec::errors::OCIErrorResponse;
 use crate::api::container_spec::Auth;
 use crate::db::DB;
 use crate::models::upload_session::UploadSession;
-use crate::registry_error::RegistryResult;
+use crate::registry_error::{RegistryError, RegistryResult};
 use crate::services::get_upload_session_service;
-use crate::types::session_id::SessionId;
+use crate::types::{repository_name::RepositoryName, session_id::SessionId};
 use crate::{header, location, range};
 
 #[derive(Responder, Debug)]
@@ -20,22 +21,29 @@ pub struct GetUploadSessionResponseData<'a> {
 pub enum GetUploadSessionResponse<'a> {
     #[response(status = 204)]
     Success(GetUploadSessionResponseData<'a>),
-    #[response(status = 500)]
-    Failure(&'a str),
+    Error(OCIErrorResponse),
 }
 
 #[get("/v2/<name>/blobs/uploads/<session_id>")]
 pub async fn get_upload_session<'a>(
     db_pool: &State<Pool<DB>>,
     _auth: Auth,
-    name: &str,
+    name: Result<RepositoryName, RegistryError>,
     session_id: &str,
 ) -> GetUploadSessionResponse<'a> {
-    let latest_session = match handle_get_upload_session(db_pool, name, session_id).await {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting upload session lookup, err: {err:?}");
+            return GetUploadSessionResponse::Error(err.into());
+        }
+    };
+
+    let latest_session = match handle_get_upload_session(db_pool, &name, session_id).await {
         Ok(v) => v,
         Err(err) => {
             warn!("Failed to retrieve upload session, due to err: {err:?}");
-            return GetUploadSessionResponse::Failure("Failed to retrieve upload session");
+            return GetUploadSessionResponse::Error(err.into());
         }
     };
 
@@ -2,15 +2,17 @@ use rocket::{http::Header, State};
 use sqlx::Pool;
 
 use crate::{
-    api::container_spec::{Auth, LOCATION_HEADER_NAME},
+    api::container_spec::{errors::OCIErrorResponse, Auth, LOCATION_HEADER_NAME},
     config::Config,
     db::DB,
     header,
-    registry_error::RegistryResult,
+    registry_error::{RegistryError, RegistryResult},
     services::upload_blob_service,
+    storage::Storage,
+    types::{digest::Digest, repository_name::RepositoryName},
 };
 
-use super::utils::octet_stream::OctetStream;
+use super::utils::{content_length::ContentLength, octet_stream::OctetStream};
 
 #[derive(Responder, Debug)]
 pub struct MonolithicUploadResponseData<'a> {
@@ -22,22 +24,43 @@ pub struct MonolithicUploadResponseData<'a> {
 pub enum MonolithicUploadResponse<'a> {
     #[response(status = 201)]
     Success(MonolithicUploadResponseData<'a>),
-    #[response(status = 500)]
-    Failure(&'a str),
+    Error(OCIErrorResponse),
 }
 
+#[allow(clippy::too_many_arguments)]
 #[post("/v2/<name>/blobs/uploads?<digest>", data = "<blob>")]
 pub async fn post_monolithic_upload<'a>(
     db_pool: &State<Pool<DB>>,
     config: &State<Config>,
+    storage: &State<Storage>,
     auth: Auth,
-    name: &str,
-    blob: OctetStream,
+    name: Result<RepositoryName, RegistryError>,
+    content_length: ContentLength,
+    blob: OctetStream<'_>,
     digest: &str,
 ) -> MonolithicUploadResponse<'a> {
-    if let Err(err) = upload_blob(db_pool, config, auth, name, blob, digest).await {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting monolithic blob upload, err: {err:?}");
+            return MonolithicUploadResponse::Error(err.into());
+        }
+    };
+
+    if let Err(err) = upload_blob(
+        db_pool,
+        config,
+        storage,
+        auth,
+        &name,
+        content_length,
+        blob,
+        digest,
+    )
+    .await
+    {
         warn!("Failed to monolithicly upload blob due to error: {err:?}");
-        return MonolithicUploadResponse::Failure("Failed to upload blob");
+        return MonolithicUploadResponse::Error(err.into());
     };
 
     MonolithicUploadResponse::Success(MonolithicUploadResponseData {
@@ -46,33 +69,46 @@ pub async fn post_monolithic_upload<'a>(
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn upload_blob(
     db_pool: &Pool<DB>,
     config: &Config,
+    storage: &Storage,
     auth: Auth,
     name: &str,
-    blob: OctetStream,
+    content_length: ContentLength,
+    blob: OctetStream<'_>,
     digest: &str,
 ) -> RegistryResult<()> {
+    let digest = Digest::parse(digest)?;
+
     let session_id = upload_blob_service::create_session(db_pool, &auth.username, name)
         .await
         .map_err(|err| {
             error!("Failed to initialize monolithic blob upload, err {err:?}");
             err
         })?;
-    let upload_session =
-        upload_blob_service::upload_blob(db_pool, name, session_id, config, blob.data, None)
-            .await
-            .map_err(|err| {
-                error!("Failed to monolithicly upload blob, err: {err:?}");
-                err
-            })?;
+    let upload_session = upload_blob_service::upload_blob(
+        db_pool,
+        name,
+        session_id,
+        config,
+        blob.into_stream(),
+        None,
+        content_length.length,
+    )
+    .await
+    .map_err(|err| {
+        error!("Failed to monolithicly upload blob, err: {err:?}");
+        err
+    })?;
     let _blob_id = upload_blob_service::finish_blob_upload(
         db_pool,
         config,
+        storage,
         name,
         upload_session.id.into(),
-        digest,
+        &digest,
     )
     .await
     .map_err(|err| {
@@ -1,9 +1,11 @@
 use rocket::{http::Header, State};
 use sqlx::Pool;
 
+use crate::api::container_spec::errors::OCIErrorResponse;
 use crate::api::container_spec::{Auth, DOCKER_UPLOAD_UUID_HEADER_NAME};
 use crate::range;
 use crate::registry_error::RegistryError;
+use crate::types::repository_name::RepositoryName;
 use crate::{
     config::Config, db::DB, header, location, models::upload_session::UploadSession,
     registry_error::RegistryResult, services::upload_blob_service, types::session_id::SessionId,
@@ -25,14 +27,10 @@ pub struct UploadBlobResponseData<'a> {
 pub enum UploadBlobResponse<'a> {
     #[response(status = 202)]
     Success(UploadBlobResponseData<'a>),
-    #[response(status = 416)]
-    OutOfOrder(()),
-    #[response(status = 416)]
-    AlreadyUploaded(()),
-    #[response(status = 500)]
-    Failure(&'a str),
+    Error(OCIErrorResponse),
 }
 
+#[allow(clippy::too_many_arguments)]
 #[patch("/v2/<name>/blobs/uploads/<session_id>", data = "<blob>")]
 pub async fn patch_upload_blob<'a>(
     db_pool: &State<Pool<DB>>,
@@ -40,15 +38,23 @@ pub async fn patch_upload_blob<'a>(
     _auth: Auth,
     content_length: ContentLength,
     content_range: Option<ContentRange>,
-    name: &str,
+    name: Result<RepositoryName, RegistryError>,
     session_id: &str,
-    blob: OctetStream,
+    blob: OctetStream<'_>,
 ) -> UploadBlobResponse<'a> {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting chunked blob upload, err: {err:?}");
+            return UploadBlobResponse::Error(err.into());
+        }
+    };
+
     let next_session = match handle_chunked_upload(
         db_pool,
         config,
         session_id,
-        name,
+        &name,
         blob,
         content_length,
         content_range,
@@ -56,17 +62,9 @@ pub async fn patch_upload_blob<'a>(
     .await
     {
         Ok(next_session) => next_session,
-        Err(RegistryError::BlobPartAlreadyUploaded) => {
-            warn!("The request blob part has already been uploaeded!");
-            return UploadBlobResponse::AlreadyUploaded(());
-        }
-        Err(RegistryError::InvalidStartIndex) => {
-            warn!("Received invalid start index of content range");
-            return UploadBlobResponse::OutOfOrder(());
-        }
         Err(err) => {
             warn!("Failed to upload blob due to err {err:?}");
-            return UploadBlobResponse::Failure("Failed to upload blob");
+            return UploadBlobResponse::Error(err.into());
         }
     };
 
@@ -83,7 +81,7 @@ async fn handle_chunked_upload(
     config: &Config,
     session_id: &str,
     name: &str,
-    blob: OctetStream,
+    blob: OctetStream<'_>,
     content_length: ContentLength,
     content_range: Option<ContentRange>,
 ) -> RegistryResult<UploadSession> {
@@ -93,15 +91,14 @@ async fn handle_chunked_upload(
         content_range.validate(&content_length)?;
     }
 
-    content_length.validate_data_length(blob.data.len())?;
-
     let new_session = upload_blob_service::upload_blob(
         db_pool,
         name,
         session_id,
         config,
-        blob.data,
+        blob.into_stream(),
         content_range.map(|o| o.range_start),
+        content_length.length,
     )
     .await
     .map_err(|err| {
@@ -3,3 +3,4 @@ pub mod content_range;
 pub mod content_type;
 pub mod macros;
 pub mod octet_stream;
+pub mod range;
@@ -1,17 +1,20 @@
 use rocket::{
-    data::{self, FromData},
+    data::{self, ByteUnit, DataStream, FromData, ToByteUnit},
     http::Status,
     Data, Request,
 };
 
 use crate::api::container_spec::{APPLICATION_TYPE_OCTET_STREAM, CONTENT_TYPE_HEADER_NAME};
 
-pub struct OctetStream {
-    pub data: Vec<u8>,
+const BLOB_LIMIT_NAME: &str = "blob";
+
+pub struct OctetStream<'r> {
+    data: Data<'r>,
+    limit: ByteUnit,
 }
 
 #[rocket::async_trait]
-impl<'r> FromData<'r> for OctetStream {
+impl<'r> FromData<'r> for OctetStream<'r> {
     type Error = String;
 
     async fn from_data(
@@ -27,18 +30,22 @@ impl<'r> FromData<'r> for OctetStream {
             warn!("Missing content type header");
         }
 
-        let bytes = match Vec::<u8>::from_data(req, data).await {
-            data::Outcome::Success(d) => d,
-            data::Outcome::Forward(resp) => return data::Outcome::Forward(resp),
-            rocket::outcome::Outcome::Error((_, err)) => {
-                error!("Failed to read body as Vec<u8>, err: {err:?}");
-                return rocket::outcome::Outcome::Error((
-                    Status::BadRequest,
-                    "Invalid request body".to_string(),
-                ));
-            }
-        };
+        // Blobs are streamed to disk so they are allowed to be a lot larger than other bodies.
+        let limit = req
+            .limits()
+            .get(BLOB_LIMIT_NAME)
+            .unwrap_or_else(|| 10.gibibytes());
+
+        data::Outcome::Success(OctetStream { data, limit })
+    }
+}
+
+impl<'r> OctetStream<'r> {
+    pub async fn is_empty(&mut self) -> bool {
+        self.data.peek(1).await.is_empty()
+    }
 
-        data::Outcome::Success(OctetStream { data: bytes })
+    pub fn into_stream(self) -> DataStream<'r> {
+        self.data.open(self.limit)
     }
 }
@@ -0,0 +1,164 @@
+use rocket::{
+    http::Status,
+    request::{self, FromRequest},
+    Request,
+};
+
+use crate::api::container_spec::RANGE_HEADER_NAME;
+
+const BYTES_UNIT_PREFIX: &str = "bytes=";
+
+/// The `Range` header of a blob request, only a single byte range is supported.
+pub struct Range {
+    pub spec: Option<RangeSpec>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeSpec {
+    /// `bytes=<start>-<end>`
+    Bounded { start: u64, end: u64 },
+    /// `bytes=<start>-`
+    From { start: u64 },
+    /// `bytes=-<length>`
+    Suffix { length: u64 },
+}
+
+impl Range {
+    /// Parses the value of a `Range` header. Unsupported units and invalid ranges are ignored,
+    /// multiple ranges are rejected.
+    pub fn parse(range: &str) -> Result<Self, String> {
+        let Some(ranges) = range.trim().strip_prefix(BYTES_UNIT_PREFIX) else {
+            // Unknown range units should be ignored and the full content returned.
+            warn!("Ignoring range header with unsupported unit: {range}");
+            return Ok(Range { spec: None });
+        };
+
+        if ranges.contains(',') {
+            warn!("Rejecting multi-range request: {range}");
+            return Err("Multiple ranges are not supported".to_string());
+        }
+
+        let spec = parse_spec(ranges.trim());
+        if spec.is_none() {
+            warn!("Ignoring invalid range header: {range}");
+        }
+
+        Ok(Range { spec })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Range {
+    type Error = String;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let Some(range) = req.headers().get_one(RANGE_HEADER_NAME) else {
+            return request::Outcome::Success(Range { spec: None });
+        };
+
+        match Range::parse(range) {
+            Ok(range) => request::Outcome::Success(range),
+            Err(err) => request::Outcome::Error((Status::RangeNotSatisfiable, err)),
+        }
+    }
+}
+
+fn parse_spec(spec: &str) -> Option<RangeSpec> {
+    let (start, end) = spec.split_once('-')?;
+
+    match (start, end) {
+        ("", length) => Some(RangeSpec::Suffix {
+            length: length.parse().ok()?,
+        }),
+        (start, "") => Some(RangeSpec::From {
+            start: start.parse().ok()?,
+        }),
+        (start, end) => {
+            let start = start.parse().ok()?;
+            let end = end.parse().ok()?;
+            (start <= end).then_some(RangeSpec::Bounded { start, end })
+        }
+    }
+}
+
+impl RangeSpec {
+    /// Returns the inclusive `(start, end)` byte positions of the range within content of the
+    /// given size, or `None` if the range can't be satisfied.
+    pub fn resolve(&self, size: u64) -> Option<(u64, u64)> {
+        let last = size.checked_sub(1)?;
+
+        match *self {
+            RangeSpec::Bounded { start, end } if start <= last => Some((start, end.min(last))),
+            RangeSpec::From { start } if start <= last => Some((start, last)),
+            RangeSpec::Suffix { length } if length > 0 => Some((size - length.min(size), last)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(range: &str) -> Option<RangeSpec> {
+        Range::parse(range)
+            .expect("a single range is accepted")
+            .spec
+    }
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        assert_eq!(
+            spec("bytes=2-5"),
+            Some(RangeSpec::Bounded { start: 2, end: 5 })
+        );
+        assert_eq!(spec("bytes=2-"), Some(RangeSpec::From { start: 2 }));
+        assert_eq!(spec("bytes=-5"), Some(RangeSpec::Suffix { length: 5 }));
+    }
+
+    #[test]
+    fn ignores_invalid_ranges_and_unsupported_units() {
+        assert_eq!(spec("bytes=5-2"), None);
+        assert_eq!(spec("bytes=a-b"), None);
+        assert_eq!(spec("bytes=-"), None);
+        assert_eq!(spec("items=0-1"), None);
+    }
+
+    #[test]
+    fn rejects_multiple_ranges() {
+        assert!(Range::parse("bytes=0-1,3-4").is_err());
+    }
+
+    #[test]
+    fn resolves_ranges_within_the_size() {
+        assert_eq!(
+            RangeSpec::Bounded { start: 2, end: 5 }.resolve(10),
+            Some((2, 5))
+        );
+        assert_eq!(RangeSpec::From { start: 2 }.resolve(10), Some((2, 9)));
+        assert_eq!(RangeSpec::Suffix { length: 3 }.resolve(10), Some((7, 9)));
+    }
+
+    #[test]
+    fn clamps_ranges_to_the_size() {
+        assert_eq!(
+            RangeSpec::Bounded { start: 2, end: 50 }.resolve(10),
+            Some((2, 9))
+        );
+        assert_eq!(RangeSpec::Suffix { length: 50 }.resolve(10), Some((0, 9)));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        assert_eq!(RangeSpec::Bounded { start: 10, end: 12 }.resolve(10), None);
+        assert_eq!(RangeSpec::From { start: 10 }.resolve(10), None);
+        assert_eq!(RangeSpec::Suffix { length: 0 }.resolve(10), None);
+    }
+
+    #[test]
+    fn rejects_every_range_of_empty_content() {
+        assert_eq!(RangeSpec::Bounded { start: 0, end: 0 }.resolve(0), None);
+        assert_eq!(RangeSpec::From { start: 0 }.resolve(0), None);
+        assert_eq!(RangeSpec::Suffix { length: 1 }.resolve(0), None);
+    }
+}
@@ -0,0 +1,66 @@
+use rocket::{http::Header, serde::json::Json, State};
+use serde::Serialize;
+use sqlx::Pool;
+
+use crate::{db::DB, header, services::get_catalog_service, upstream_registry::Proxy};
+
+use super::{errors::OCIErrorResponse, OptionalAuth, LINK_HEADER_NAME};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogResponseData {
+    repositories: Vec<String>,
+}
+
+#[derive(Responder, Debug)]
+pub struct PaginatedCatalogResponseData<'a> {
+    inner: Json<CatalogResponseData>,
+    link: Header<'a>,
+}
+
+#[derive(Responder)]
+pub enum CatalogResponse<'a> {
+    #[response(status = 200)]
+    Success(Json<CatalogResponseData>),
+    #[response(status = 200)]
+    Paginated(PaginatedCatalogResponseData<'a>),
+    Error(OCIErrorResponse),
+}
+
+#[get("/v2/_catalog?<n>&<last>")]
+pub async fn get_catalog<'a>(
+    db_pool: &State<Pool<DB>>,
+    proxy: &State<Proxy>,
+    auth: OptionalAuth,
+    n: Option<usize>,
+    last: Option<&str>,
+) -> CatalogResponse<'a> {
+    let page =
+        match get_catalog_service::get_catalog(db_pool, proxy, auth.username.as_deref(), n, last)
+            .await
+        {
+            Ok(page) => page,
+            Err(err) => {
+                error!("Failed to retrieve catalog, err: {err:?}");
+                return CatalogResponse::Error(err.into());
+            }
+        };
+
+    let next_link = match (n, page.repositories.last()) {
+        (Some(n), Some(last)) if page.has_more => Some(header!(
+            LINK_HEADER_NAME,
+            format!(r#"</v2/_catalog?n={n}&last={last}>; rel="next""#)
+        )),
+        _ => None,
+    };
+
+    let data = Json(CatalogResponseData {
+        repositories: page.repositories,
+    });
+
+    match next_link {
+        Some(link) => {
+            CatalogResponse::Paginated(PaginatedCatalogResponseData { inner: data, link })
+        }
+        None => CatalogResponse::Success(data),
+    }
+}
@@ -1,9 +1,14 @@
-use rocket::{http::Header, serde::json::Json};
+use rocket::{
+    http::{Header, Status},
+    response::{self, Responder},
+    serde::json::Json,
+    Request,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
+use crate::{config::Config, registry_error::RegistryError, types::resource_scope::ResourceScope};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum OCIError {
     BlobUnknown,
@@ -12,6 +17,7 @@ pub enum OCIError {
     DigestInvalid,
     ManifestBlobUnknown,
     ManifestInvalid,
+    ManifestUnknown,
     ManifestUnverified,
     NameInvalid,
     NameUnknown,
@@ -20,25 +26,81 @@ pub enum OCIError {
     Unauthorized,
     Denied,
     Unsupported,
+    /// Not part of the spec, used by the reference registry implementation for unsatisfiable
+    /// ranges.
+    RangeInvalid,
+    /// Not part of the spec, used by the reference registry implementation for internal errors.
+    Unknown,
 }
 
 impl OCIError {
     pub fn to_response(self) -> ContainerSpecError {
         let (message, detail) = match self {
-            OCIError::BlobUnknown => todo!(),
-            OCIError::BlobUploadInvalid => todo!(),
-            OCIError::BlobUploadUnknown => todo!(),
-            OCIError::DigestInvalid => todo!(),
-            OCIError::ManifestBlobUnknown => todo!(),
-            OCIError::ManifestInvalid => todo!(),
-            OCIError::ManifestUnverified => todo!(),
-            OCIError::NameInvalid => todo!(),
-            OCIError::NameUnknown => todo!(),
-            OCIError::SizeInvalid => todo!(),
-            OCIError::TagInvalid => todo!(),
+            OCIError::BlobUnknown => (
+                "blob unknown to registry",
+                "The referenced blob could not be found in the repository",
+            ),
+            OCIError::BlobUploadInvalid => (
+                "blob upload invalid",
+                "The blob upload encountered an error and can no longer proceed",
+            ),
+            OCIError::BlobUploadUnknown => (
+                "blob upload unknown to registry",
+                "The upload session could not be found, it may have been finished or cancelled",
+            ),
+            OCIError::DigestInvalid => (
+                "provided digest did not match uploaded content",
+                "The digest is either malformed, uses an unsupported algorithm or does not match the uploaded content",
+            ),
+            OCIError::ManifestBlobUnknown => (
+                "manifest references a manifest or blob unknown to registry",
+                "All blobs referenced by a manifest must be uploaded before the manifest",
+            ),
+            OCIError::ManifestInvalid => (
+                "manifest invalid",
+                "The manifest could not be parsed or did not pass validation",
+            ),
+            OCIError::ManifestUnknown => (
+                "manifest unknown to registry",
+                "The referenced manifest could not be found in the repository",
+            ),
+            OCIError::ManifestUnverified => (
+                "manifest failed signature verification",
+                "The manifest signature could not be verified",
+            ),
+            OCIError::NameInvalid => (
+                "invalid repository name",
+                r"The repository name must match [a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*(/[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*)*",
+            ),
+            OCIError::NameUnknown => (
+                "repository name not known to registry",
+                "The repository could not be found",
+            ),
+            OCIError::SizeInvalid => (
+                "provided length did not match content length",
+                "The length of the received content did not match the expected length",
+            ),
+            OCIError::TagInvalid => (
+                "manifest tag did not match URI",
+                "The tag is not valid for this manifest",
+            ),
             OCIError::Unauthorized => ("access to the requested resource is not authorized", "Unable to authorize client, please follow indicated authorization steps before proceeding"),
-            OCIError::Denied => todo!(),
-            OCIError::Unsupported => todo!(),
+            OCIError::Denied => (
+                "requested access to the resource is denied",
+                "The client is not allowed to perform the requested operation",
+            ),
+            OCIError::Unsupported => (
+                "the operation is unsupported",
+                "The registry does not support the requested operation",
+            ),
+            OCIError::RangeInvalid => (
+                "invalid content range",
+                "The requested range can't be satisfied for the content",
+            ),
+            OCIError::Unknown => (
+                "unknown error",
+                "An internal error occurred whilst handling the request",
+            ),
         };
 
         ContainerSpecError {
@@ -47,6 +109,77 @@ impl OCIError {
             detail: detail.to_string(),
         }
     }
+
+    pub fn status(&self) -> Status {
+        match self {
+            OCIError::BlobUnknown => Status::NotFound,
+            OCIError::BlobUploadInvalid => Status::BadRequest,
+            OCIError::BlobUploadUnknown => Status::NotFound,
+            OCIError::DigestInvalid => Status::BadRequest,
+            OCIError::ManifestBlobUnknown => Status::BadRequest,
+            OCIError::ManifestInvalid => Status::BadRequest,
+            OCIError::ManifestUnknown => Status::NotFound,
+            OCIError::ManifestUnverified => Status::BadRequest,
+            OCIError::NameInvalid => Status::BadRequest,
+            OCIError::NameUnknown => Status::NotFound,
+            OCIError::SizeInvalid => Status::BadRequest,
+            OCIError::TagInvalid => Status::BadRequest,
+            OCIError::Unauthorized => Status::Unauthorized,
+            OCIError::Denied => Status::Forbidden,
+            OCIError::Unsupported => Status::MethodNotAllowed,
+            OCIError::RangeInvalid => Status::RangeNotSatisfiable,
+            OCIError::Unknown => Status::InternalServerError,
+        }
+    }
+}
+
+impl From<&RegistryError> for OCIError {
+    fn from(value: &RegistryError) -> Self {
+        match value {
+            RegistryError::SqlxError(_)
+            | RegistryError::IOError(_)
+            | RegistryError::InvalidState
+            | RegistryError::FailedToDeleteTag
+            | RegistryError::ManifestFileCorrupted(_)
+            | RegistryError::StorageError(_)
+            | RegistryError::UpstreamError(_)
+            | RegistryError::InvalidUsername(_)
+            | RegistryError::InvalidHtpasswdEntry(_)
+            | RegistryError::PasswordHashError(_)
+            | RegistryError::InvalidNotificationEndpoint(_) => OCIError::Unknown,
+            RegistryError::SessionNotFound | RegistryError::InvalidSessionId => {
+                OCIError::BlobUploadUnknown
+            }
+            RegistryError::InvalidContentLength | RegistryError::BlobTooLarge => {
+                OCIError::SizeInvalid
+            }
+            RegistryError::UnsupportedDigest | RegistryError::InvalidDigest => {
+                OCIError::DigestInvalid
+            }
+            RegistryError::UnsupportedManifestType
+            | RegistryError::InvalidManifestSchema(_)
+            | RegistryError::ManifestBlobSizeMismatch(_)
+            | RegistryError::SerdeJsonError(_) => OCIError::ManifestInvalid,
+            RegistryError::InvalidContentRange
+            | RegistryError::InvalidStartIndex
+            | RegistryError::BlobPartAlreadyUploaded => OCIError::BlobUploadInvalid,
+            RegistryError::BlobNotFound | RegistryError::BlobFileNotFound => OCIError::BlobUnknown,
+            RegistryError::ManifestNotFound
+            | RegistryError::ManifestFileNotFound
+            | RegistryError::ManifestNotAcceptable(_) => OCIError::ManifestUnknown,
+            RegistryError::BlobManifestStillExists | RegistryError::ManifestStillReferenced => {
+                OCIError::Denied
+            }
+            RegistryError::ManifestBlobUnknown(_) => OCIError::ManifestBlobUnknown,
+            RegistryError::InvalidName(_) => OCIError::NameInvalid,
+            RegistryError::InvalidTag(_) => OCIError::TagInvalid,
+            RegistryError::RepositoryNotFound => OCIError::NameUnknown,
+            RegistryError::InvalidToken(_) => OCIError::Unauthorized,
+            RegistryError::AccountsServiceError(_) => OCIError::Unknown,
+            RegistryError::NotRepositoryOwner | RegistryError::AccessDenied(_) => OCIError::Denied,
+            RegistryError::RangeNotSatisfiable => OCIError::RangeInvalid,
+        }
+    }
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -54,6 +187,14 @@ pub struct ContainerSpecErrorResponse {
     errors: Vec<ContainerSpecError>,
 }
 
+impl From<OCIError> for ContainerSpecErrorResponse {
+    fn from(value: OCIError) -> Self {
+        Self {
+            errors: vec![value.to_response()],
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct ContainerSpecError {
     code: OCIError,
@@ -61,6 +202,79 @@ pub struct ContainerSpecError {
     detail: String,
 }
 
+/// An OCI error body together with the status it should be returned with.
+#[derive(Debug, Clone)]
+pub struct OCIErrorResponse {
+    status: Status,
+    body: ContainerSpecErrorResponse,
+}
+
+impl OCIErrorResponse {
+    pub fn with_status(code: OCIError, status: Status) -> Self {
+        Self {
+            status,
+            body: code.into(),
+        }
+    }
+}
+
+impl From<OCIError> for OCIErrorResponse {
+    fn from(value: OCIError) -> Self {
+        Self::with_status(value, value.status())
+    }
+}
+
+impl From<RegistryError> for OCIErrorResponse {
+    fn from(value: RegistryError) -> Self {
+        let code = OCIError::from(&value);
+        let status = match value {
+            RegistryError::InvalidContentRange
+            | RegistryError::InvalidStartIndex
+            | RegistryError::BlobPartAlreadyUploaded => Status::RangeNotSatisfiable,
+            RegistryError::BlobTooLarge => Status::PayloadTooLarge,
+            RegistryError::ManifestNotAcceptable(_) => Status::NotAcceptable,
+            RegistryError::UpstreamError(_) => Status::BadGateway,
+            _ => code.status(),
+        };
+
+        // Every unknown blob is reported as a separate error, with its digest as the detail.
+        if let RegistryError::ManifestBlobUnknown(digests) = &value {
+            let errors = digests
+                .iter()
+                .map(|digest| {
+                    let mut error = code.to_response();
+                    error.detail = digest.clone();
+                    error
+                })
+                .collect();
+
+            return Self {
+                status,
+                body: ContainerSpecErrorResponse { errors },
+            };
+        }
+
+        let mut error = code.to_response();
+        // Internal errors are described generically, everything else can tell the client more.
+        if !matches!(code, OCIError::Unknown) {
+            error.detail = value.to_string();
+        }
+
+        Self {
+            status,
+            body: ContainerSpecErrorResponse {
+                errors: vec![error],
+            },
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for OCIErrorResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        (self.status, Json(self.body)).respond_to(request)
+    }
+}
+
 #[derive(Responder, Debug, Clone)]
 #[response(status = 401, content_type = "json")]
 pub struct UnauthorizedResponse {
@@ -69,18 +283,25 @@ pub struct UnauthorizedResponse {
 }
 
 impl UnauthorizedResponse {
-    pub fn new(config: &Config) -> Self {
+    /// Challenges the client to get a token for `scope` from the token endpoint. `error` tells
+    /// the client why the token it sent was not accepted.
+    pub fn new(config: &Config, scope: Option<&ResourceScope>, error: Option<&str>) -> Self {
+        let mut challenge = format!(
+            r#"Bearer realm="{}",service="{}""#,
+            config.token_realm, config.token_service
+        );
+        if let Some(scope) = scope {
+            challenge.push_str(&format!(r#",scope="{scope}""#));
+        }
+        if let Some(error) = error {
+            challenge.push_str(&format!(r#",error="{error}""#));
+        }
+
         Self {
             inner: Json(ContainerSpecErrorResponse {
                 errors: vec![OCIError::Unauthorized.to_response()],
             }),
-            www_authenticate: Header::new(
-                "www-authenticate",
-                format!(
-                    r#"Bearer realm="{}", service="{}", scope=""#,
-                    config.accounts_rs_auth_endpoint, config.auth_service
-                ),
-            ),
+            www_authenticate: Header::new("www-authenticate", challenge),
         }
     }
 }
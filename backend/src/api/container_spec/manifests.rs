@@ -1,71 +1,195 @@
 use rocket::{
-    fs::NamedFile,
     http::{ContentType, Header},
     State,
 };
 use sqlx::Pool;
-use uuid::Uuid;
 
 use crate::{
     config::Config,
     db::DB,
     header,
     registry_error::{RegistryError, RegistryResult},
-    services::{self, delete_manifest_service, get_manifest_service},
+    services::{
+        self, delete_manifest_service,
+        get_manifest_service::{self, ManifestInfo},
+        notification_service, proxy_service,
+    },
+    storage::Storage,
+    types::{
+        accepted_media_types::AcceptedMediaTypes,
+        digest::Digest,
+        manifest::media_type,
+        notification_event::{EventAction, EventRequest, EventTarget},
+        reference::Reference,
+        repository_name::RepositoryName,
+    },
+    upstream_registry::Proxy,
 };
 
 use super::{
-    blobs::utils::content_length::ContentLength, Auth, DOCKER_CONTENT_DIGEST_HEADER_NAME,
-    LOCATION_HEADER_NAME, OCI_SUBJECT_HEADER_NAME,
+    blobs::utils::content_length::ContentLength,
+    errors::{OCIError, OCIErrorResponse},
+    Auth, OptionalAuth, DOCKER_CONTENT_DIGEST_HEADER_NAME, LOCATION_HEADER_NAME,
+    OCI_SUBJECT_HEADER_NAME,
 };
 
 #[derive(Responder, Debug)]
 pub struct GetManifestResponseData<'a> {
-    file: NamedFile,
+    body: Vec<u8>,
     content_type: ContentType,
     docker_digest: Header<'a>,
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Responder, Debug)]
 pub enum GetManifestResponse<'a> {
     #[response(status = 200)]
     Success(GetManifestResponseData<'a>),
-    #[response(status = 404)]
-    FileNotFound(&'a str),
-    #[response(status = 500)]
-    Failure(&'a str),
+    Error(OCIErrorResponse),
 }
 
+#[allow(clippy::too_many_arguments)]
 #[get("/v2/<name>/manifests/<reference>")]
 pub async fn get_manifest<'a>(
-    name: &str,
-    reference: &str,
+    name: Result<RepositoryName, RegistryError>,
+    reference: Result<Reference, RegistryError>,
+    accepted: AcceptedMediaTypes,
+    event_request: EventRequest,
     db_pool: &State<Pool<DB>>,
     config: &State<Config>,
+    storage: &State<Storage>,
+    proxy: &State<Proxy>,
+    auth: OptionalAuth,
 ) -> GetManifestResponse<'a> {
-    match get_manifest_service::find_manifest(db_pool, name, reference, config).await {
+    let (name, reference, manifest_info) =
+        match find_manifest(name, reference, &accepted, db_pool, config, storage, proxy).await {
+            Ok(found) => found,
+            Err(err) => return GetManifestResponse::Error(err),
+        };
+
+    notification_service::notify(
+        db_pool,
+        config,
+        EventAction::Pull,
+        manifest_target(&name, &reference, &manifest_info),
+        &event_request,
+        auth.username.as_deref(),
+    )
+    .await;
+
+    GetManifestResponse::Success(manifest_response(manifest_info))
+}
+
+/// Looks up the manifest of a GET or HEAD request, pulling it from the upstream first if the
+/// repository is proxied.
+async fn find_manifest(
+    name: Result<RepositoryName, RegistryError>,
+    reference: Result<Reference, RegistryError>,
+    accepted: &AcceptedMediaTypes,
+    db_pool: &Pool<DB>,
+    config: &Config,
+    storage: &Storage,
+    proxy: &Proxy,
+) -> Result<(RepositoryName, Reference, ManifestInfo), OCIErrorResponse> {
+    let name = name.map_err(|err| {
+        warn!("Rejecting manifest lookup, err: {err:?}");
+        OCIErrorResponse::from(err)
+    })?;
+
+    let reference = reference.map_err(|err| {
+        warn!("Rejecting manifest lookup, err: {err:?}");
+        OCIErrorResponse::from(err)
+    })?;
+
+    if let Err(e) = proxy_service::cache_manifest(db_pool, storage, proxy, &name, &reference).await
+    {
+        error!("Failed to pull manifest {name}/{reference} from upstream, err: {e:?}");
+        return Err(e.into());
+    }
+
+    match get_manifest_service::find_manifest(
+        db_pool,
+        &name,
+        &reference,
+        accepted,
+        &config.default_platform,
+        storage,
+    )
+    .await
+    {
         Ok(Some(manifest_info)) => {
             info!("Manifest found for {name}/{reference}");
-            GetManifestResponse::Success(GetManifestResponseData {
-                file: manifest_info.named_file,
-                content_type: ContentType::new(
-                    manifest_info.manifest.content_type_top,
-                    manifest_info.manifest.content_type_sub,
-                ),
-                docker_digest: Header::new(
-                    DOCKER_CONTENT_DIGEST_HEADER_NAME,
-                    manifest_info.manifest.digest,
-                ),
-            })
+            Ok((name, reference, manifest_info))
         }
         Ok(None) => {
             warn!("Failed to find manifest {name}/{reference}");
-            GetManifestResponse::FileNotFound("File not found")
+            Err(OCIError::ManifestUnknown.into())
         }
         Err(e) => {
             error!("Failed to get manifest, err: {e:?}");
-            GetManifestResponse::Failure("An error occurred")
+            Err(e.into())
+        }
+    }
+}
+
+fn manifest_response<'a>(manifest_info: ManifestInfo) -> GetManifestResponseData<'a> {
+    GetManifestResponseData {
+        body: manifest_info.data,
+        content_type: ContentType::new(
+            manifest_info.manifest.content_type_top,
+            manifest_info.manifest.content_type_sub,
+        ),
+        docker_digest: Header::new(
+            DOCKER_CONTENT_DIGEST_HEADER_NAME,
+            manifest_info.manifest.digest,
+        ),
+    }
+}
+
+fn manifest_target(name: &str, reference: &Reference, manifest_info: &ManifestInfo) -> EventTarget {
+    let manifest = &manifest_info.manifest;
+    EventTarget {
+        media_type: Some(format!(
+            "{}/{}",
+            manifest.content_type_top, manifest.content_type_sub
+        )),
+        size: Some(manifest_info.data.len() as i64),
+        length: Some(manifest_info.data.len() as i64),
+        digest: Some(manifest.digest.clone()),
+        repository: name.to_string(),
+        tag: match reference {
+            Reference::Tag(tag) => Some(tag.clone()),
+            Reference::Digest(_) => None,
+        },
+    }
+}
+
+/// The body is stripped by Rocket for HEAD requests, leaving only the headers and the length.
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder, Debug)]
+pub enum HeadManifestResponse<'a> {
+    #[response(status = 200)]
+    Success(GetManifestResponseData<'a>),
+    Error(OCIErrorResponse),
+}
+
+#[allow(clippy::too_many_arguments)]
+#[head("/v2/<name>/manifests/<reference>")]
+pub async fn head_manifest<'a>(
+    name: Result<RepositoryName, RegistryError>,
+    reference: Result<Reference, RegistryError>,
+    accepted: AcceptedMediaTypes,
+    db_pool: &State<Pool<DB>>,
+    config: &State<Config>,
+    storage: &State<Storage>,
+    proxy: &State<Proxy>,
+    _auth: OptionalAuth,
+) -> HeadManifestResponse<'a> {
+    match find_manifest(name, reference, &accepted, db_pool, config, storage, proxy).await {
+        Ok((_, _, manifest_info)) => {
+            HeadManifestResponse::Success(manifest_response(manifest_info))
         }
+        Err(err) => HeadManifestResponse::Error(err),
     }
 }
 
@@ -81,39 +205,83 @@ pub struct PutManifestResponseData<'a> {
 pub enum PutManifestResponse<'a> {
     #[response(status = 201)]
     Success(PutManifestResponseData<'a>),
-    #[response(status = 400)]
-    BadRequest(String),
-    #[response(status = 500)]
-    Failure(&'a str),
+    Error(OCIErrorResponse),
 }
 
+#[allow(clippy::too_many_arguments)]
 #[put("/v2/<name>/manifests/<reference>", data = "<data>")]
 pub async fn put_manifest<'a>(
     db_pool: &State<Pool<DB>>,
     config: &State<Config>,
-    _auth: Auth,
-    name: &str,
-    reference: &str,
+    storage: &State<Storage>,
+    auth: Auth,
+    event_request: EventRequest,
+    name: Result<RepositoryName, RegistryError>,
+    reference: Result<Reference, RegistryError>,
     content_length: ContentLength,
     content_type: &ContentType,
     data: Vec<u8>,
 ) -> PutManifestResponse<'a> {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting manifest upload, err: {err:?}");
+            return PutManifestResponse::Error(err.into());
+        }
+    };
+
+    let reference = match reference {
+        Ok(reference) => reference,
+        Err(err) => {
+            warn!("Rejecting manifest upload, err: {err:?}");
+            return PutManifestResponse::Error(err.into());
+        }
+    };
+
+    let size = data.len() as i64;
     match upload_manifest(
         db_pool,
-        config,
-        name,
-        reference,
+        storage,
+        &name,
+        &reference,
         content_type,
         content_length,
         data,
     )
     .await
     {
-        Ok((manifest_id, digest, subject_digest)) => {
+        Ok((digest, subject_digest)) => {
+            let target = EventTarget {
+                media_type: Some(media_type(content_type)),
+                size: Some(size),
+                length: Some(size),
+                digest: Some(digest.to_string()),
+                repository: name.to_string(),
+                tag: match &reference {
+                    Reference::Tag(tag) => Some(tag.clone()),
+                    Reference::Digest(_) => None,
+                },
+            };
+            notification_service::notify(
+                db_pool,
+                config,
+                EventAction::Push,
+                target,
+                &event_request,
+                Some(&auth.username),
+            )
+            .await;
+
             PutManifestResponse::Success(PutManifestResponseData {
                 response: "Upload manifest successful",
-                location: header!(LOCATION_HEADER_NAME, format!("/{manifest_id}")),
-                docker_content_digest: header!(DOCKER_CONTENT_DIGEST_HEADER_NAME, digest),
+                location: header!(
+                    LOCATION_HEADER_NAME,
+                    format!("/v2/{}/manifests/{digest}", &*name)
+                ),
+                docker_content_digest: header!(
+                    DOCKER_CONTENT_DIGEST_HEADER_NAME,
+                    digest.to_string()
+                ),
                 oci_subject: header!(
                     OCI_SUBJECT_HEADER_NAME,
                     subject_digest.unwrap_or(String::new())
@@ -122,25 +290,25 @@ pub async fn put_manifest<'a>(
         }
         Err(e) => {
             error!("Failed to upload manifest {e:?}");
-            PutManifestResponse::Failure("Failed to upload manifest")
+            PutManifestResponse::Error(e.into())
         }
     }
 }
 
 async fn upload_manifest(
     db_pool: &Pool<DB>,
-    config: &Config,
+    storage: &Storage,
     name: &str,
-    reference: &str,
+    reference: &Reference,
     manifest_type: &ContentType,
     content_length: ContentLength,
     data: Vec<u8>,
-) -> RegistryResult<(Uuid, String, Option<String>)> {
+) -> RegistryResult<(Digest, Option<String>)> {
     content_length.validate_data_length(data.len())?;
 
-    let (id, digest, subject_digest) = services::upload_manifest_service::upload_manifest(
+    let (digest, subject_digest) = services::upload_manifest_service::upload_manifest(
         db_pool,
-        config,
+        storage,
         name,
         reference,
         manifest_type,
@@ -148,49 +316,76 @@ async fn upload_manifest(
     )
     .await?;
 
-    Ok((id, digest, subject_digest))
+    Ok((digest, subject_digest))
 }
 
 #[derive(Responder)]
 pub enum DeleteManifestResponse {
     #[response(status = 202)]
     Success(()),
-    #[response(status = 404)]
-    NotFound(()),
-    #[response(status = 500)]
-    Failure(()),
+    Error(OCIErrorResponse),
 }
 
 #[delete("/v2/<name>/manifests/<reference>")]
 pub async fn delete_manifest(
     db_pool: &State<Pool<DB>>,
     config: &State<Config>,
-    _auth: Auth,
-    name: &str,
-    reference: &str,
+    storage: &State<Storage>,
+    auth: Auth,
+    event_request: EventRequest,
+    name: Result<RepositoryName, RegistryError>,
+    reference: Result<Reference, RegistryError>,
 ) -> DeleteManifestResponse {
-    if reference.starts_with("sha256:") {
-        info!("Reference understood to be digest {reference}");
-        if let Err(err) =
-            delete_manifest_service::delete_manifest(db_pool, config, name, reference).await
-        {
-            match err {
-                RegistryError::ManifestNotFound => {
-                    return DeleteManifestResponse::NotFound(());
-                }
-                err => {
-                    error!("Failed to delete manifest, err: {err:?}");
-                    return DeleteManifestResponse::Failure(());
-                }
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting manifest deletion, err: {err:?}");
+            return DeleteManifestResponse::Error(err.into());
+        }
+    };
+
+    let reference = match reference {
+        Ok(reference) => reference,
+        Err(err) => {
+            warn!("Rejecting manifest deletion, err: {err:?}");
+            return DeleteManifestResponse::Error(err.into());
+        }
+    };
+
+    let mut target = EventTarget {
+        repository: name.to_string(),
+        ..Default::default()
+    };
+    match reference {
+        Reference::Digest(digest) => {
+            info!("Reference identified as digest {digest}");
+            if let Err(err) =
+                delete_manifest_service::delete_manifest(db_pool, storage, &name, &digest).await
+            {
+                error!("Failed to delete manifest, err: {err:?}");
+                return DeleteManifestResponse::Error(err.into());
             }
+            target.digest = Some(digest.to_string());
         }
-    } else {
-        info!("Reference understood to be tag {reference}");
-        if let Err(err) = delete_manifest_service::delete_tag(db_pool, name, reference).await {
-            error!("Failed to delete tag, err: {err:?}");
-            return DeleteManifestResponse::Failure(());
+        Reference::Tag(tag) => {
+            info!("Reference identified as tag {tag}");
+            if let Err(err) = delete_manifest_service::delete_tag(db_pool, &name, &tag).await {
+                error!("Failed to delete tag, err: {err:?}");
+                return DeleteManifestResponse::Error(err.into());
+            }
+            target.tag = Some(tag);
         }
     }
 
+    notification_service::notify(
+        db_pool,
+        config,
+        EventAction::Delete,
+        target,
+        &event_request,
+        Some(&auth.username),
+    )
+    .await;
+
     DeleteManifestResponse::Success(())
 }
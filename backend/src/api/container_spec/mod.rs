@@ -1,104 +1,254 @@
 use rocket::{
-    http::Status,
+    http::{Method, Status},
     request::{self, FromRequest},
     Request, State,
 };
 
+use sqlx::Pool;
+
 use crate::{
-    api::container_spec::auth_service::accounts_rs::AccountsRsUserResponse, config::Config,
+    auth_provider::AuthProviders,
+    config::Config,
+    db::DB,
+    registry_error::RegistryError,
+    services::{authorization_service, token_service},
+    types::{
+        credentials::Credentials,
+        repository_name::RepositoryName,
+        resource_scope::{ResourceScope, DELETE_ACTION, PULL_ACTION, PUSH_ACTION},
+    },
+    upstream_registry::Proxy,
 };
 
-use self::errors::UnauthorizedResponse;
+use self::errors::{OCIErrorResponse, UnauthorizedResponse};
 
-pub mod auth_service;
 pub mod blobs;
+pub mod catalog;
 pub mod errors;
 pub mod manifests;
+pub mod name_rewrite;
+pub mod referrers;
 pub mod tags;
+pub mod token;
 
 const CONTENT_TYPE_HEADER_NAME: &str = "Content-Type";
 const CONTENT_RANGE_HEADER_NAME: &str = "Content-Range";
 const CONTENT_LENGTH_HEADER_NAME: &str = "Content-Length";
 const LOCATION_HEADER_NAME: &str = "Location";
+const LINK_HEADER_NAME: &str = "Link";
 const RANGE_HEADER_NAME: &str = "Range";
+const ACCEPT_RANGES_HEADER_NAME: &str = "Accept-Ranges";
 const DOCKER_UPLOAD_UUID_HEADER_NAME: &str = "Docker-Upload-UUID";
 const DOCKER_CONTENT_DIGEST_HEADER_NAME: &str = "Docker-Content-Digest";
 const APPLICATION_TYPE_OCTET_STREAM: &str = "application/octet-stream";
 const OCI_SUBJECT_HEADER_NAME: &str = "OCI-Subject";
+const OCI_FILTERS_APPLIED_HEADER_NAME: &str = "OCI-Filters-Applied";
 
 pub struct Auth {
-    username: String,
+    pub username: String,
+}
+
+/// Like [`Auth`], but requests without a user are let through as anonymous, for the routes that
+/// serve public repositories to everyone.
+pub struct OptionalAuth {
+    pub username: Option<String>,
 }
 
 #[derive(Responder, Debug, Clone)]
 pub enum AuthFailure {
     Unauthorized(UnauthorizedResponse),
+    Denied(OCIErrorResponse),
     InternalServerError(String),
 }
 
+const UPLOADS_PATH_SEGMENT: &str = "/blobs/uploads";
+const INVALID_TOKEN_ERROR: &str = "invalid_token";
+const INSUFFICIENT_SCOPE_ERROR: &str = "insufficient_scope";
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Auth {
     type Error = AuthFailure;
 
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        let config = match req.guard::<&State<Config>>().await {
-            rocket::outcome::Outcome::Success(s) => s,
-            _ => {
-                return request::Outcome::Error((
-                    Status::InternalServerError,
-                    AuthFailure::InternalServerError("Failed to retrieve config!".to_string()),
-                ))
-            }
-        };
+        authenticate(req, false)
+            .await
+            .and_then(|username| match username {
+                Some(username) => request::Outcome::Success(Auth { username }),
+                None => internal_failure("Anonymous user passed authentication"),
+            })
+    }
+}
 
-        let Some(auth_header) = req.headers().get_one("authorization") else {
-            warn!("Request missing authorization header");
-            return auth_failure(req, config);
-        };
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OptionalAuth {
+    type Error = AuthFailure;
 
-        if !auth_header.starts_with("Bearer ") {
-            error!("Auth header doesn't start with 'Bearer '?");
-            return auth_failure(req, config);
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        authenticate(req, true)
+            .await
+            .map(|username| OptionalAuth { username })
+    }
+}
+
+/// Accepts the tokens issued by the token endpoint, see [`token_service`], and credentials of the
+/// auth providers that don't call out to another service, see [`AuthProviders`]. Tokens are
+/// verified locally and must grant the scope the route requires, which the user must also be
+/// permitted to access, see [`authorization_service`]. Anonymous users either send no token or one
+/// without a subject, they are challenged to authenticate when they are denied.
+async fn authenticate(
+    req: &Request<'_>,
+    allow_anonymous: bool,
+) -> request::Outcome<Option<String>, AuthFailure> {
+    let config = match req.guard::<&State<Config>>().await {
+        rocket::outcome::Outcome::Success(s) => s,
+        _ => {
+            return request::Outcome::Error((
+                Status::InternalServerError,
+                AuthFailure::InternalServerError("Failed to retrieve config!".to_string()),
+            ))
         }
+    };
 
-        let client = reqwest::Client::new();
-        let resp = match client
-            .get(&config.accounts_rs_me_endpoint)
-            .header("Authorization", auth_header)
-            .send()
-            .await
-        {
-            Ok(resp) => resp,
-            Err(e) => {
-                error!("Failed to send user request to accounts service, err: {e:?}");
-                return auth_failure(req, config);
-            }
-        };
+    let (Some(db_pool), Some(proxy), Some(auth_providers)) = (
+        req.guard::<&State<Pool<DB>>>().await.succeeded(),
+        req.guard::<&State<Proxy>>().await.succeeded(),
+        req.guard::<&State<AuthProviders>>().await.succeeded(),
+    ) else {
+        return internal_failure("Failed to retrieve database pool, proxy or auth providers!");
+    };
+
+    let scope = required_scope(req);
+
+    let credentials = req
+        .headers()
+        .get_one("authorization")
+        .map(Credentials::parse);
+    let (username, claims) = match credentials {
+        Some(Some(credentials)) => {
+            let verified = match &credentials {
+                Credentials::Bearer(token) => Some(token_service::verify_token(config, token)),
+                Credentials::Basic { .. } => None,
+            };
 
-        let resp_status = resp.status();
-        if !resp_status.is_success() {
-            error!("Got error response (status {resp_status}) from accounts service");
-            return auth_failure(req, config);
+            match verified {
+                Some(Ok(claims)) => (
+                    Some(claims.sub.clone()).filter(|sub| !sub.is_empty()),
+                    Some(claims),
+                ),
+                // Not a token of the token endpoint, e.g. an API token or a local password.
+                token_error => match auth_providers.authenticate_request(&credentials).await {
+                    Ok(Some(identity)) => (Some(identity.username), None),
+                    Ok(None) => match token_error {
+                        Some(Err(err)) => {
+                            warn!("Rejecting token, err: {err:?}");
+                            return auth_failure(req, config, scope, Some(INVALID_TOKEN_ERROR));
+                        }
+                        _ => {
+                            warn!("Rejecting credentials");
+                            return auth_failure(req, config, scope, None);
+                        }
+                    },
+                    Err(err) => {
+                        error!("Failed to authenticate request, err: {err:?}");
+                        return internal_failure("Failed to authenticate request");
+                    }
+                },
+            }
+        }
+        Some(None) => {
+            warn!("Auth header contains neither a bearer token nor basic credentials");
+            return auth_failure(req, config, scope, Some(INVALID_TOKEN_ERROR));
         }
+        None if allow_anonymous => (None, None),
+        None => {
+            warn!("Request missing authorization header");
+            return auth_failure(req, config, scope, None);
+        }
+    };
+
+    if username.is_none() && !allow_anonymous {
+        warn!("Anonymous user on a route that requires a user");
+        return auth_failure(req, config, scope, None);
+    }
 
-        let user_info: AccountsRsUserResponse = match resp.json().await {
-            Ok(u) => u,
-            Err(e) => {
-                error!("Failed to deserialize user request to accounts service, err: {e:?}");
-                return auth_failure(req, config);
+    if let Some(required) = &scope {
+        if let Some(claims) = &claims {
+            if !claims.grants(required) {
+                warn!("Token of {:?} does not grant {required}", claims.sub);
+                return auth_failure(req, config, scope, Some(INSUFFICIENT_SCOPE_ERROR));
             }
-        };
+        }
+
+        if let Err(err) =
+            authorization_service::authorize(db_pool, proxy, username.as_deref(), required).await
+        {
+            return match err {
+                RegistryError::AccessDenied(_) if username.is_none() => {
+                    auth_failure(req, config, scope, None)
+                }
+                RegistryError::AccessDenied(_) => denied_failure(req, err),
+                err => {
+                    error!("Failed to authorize {username:?}, err: {err:?}");
+                    internal_failure("Failed to authorize request")
+                }
+            };
+        }
+    }
+
+    request::Outcome::Success(username)
+}
 
-        request::Outcome::Success(Auth {
-            username: user_info.success.email,
-        })
+/// The scope a token needs for the matched route: pushing covers everything that is part of an
+/// upload, other routes on a repository either pull or delete.
+fn required_scope(req: &Request) -> Option<ResourceScope> {
+    let route = req.route()?;
+    let path = route.uri.path();
+
+    if path.starts_with("/v2/_catalog") {
+        return Some(ResourceScope::catalog());
+    }
+
+    if !path.starts_with("/v2/<name>/") {
+        return None;
     }
+
+    let name = req.param::<RepositoryName>(1)?.ok()?;
+    let actions: &[&str] = if path.contains(UPLOADS_PATH_SEGMENT) {
+        &[PULL_ACTION, PUSH_ACTION]
+    } else {
+        match req.method() {
+            Method::Get | Method::Head => &[PULL_ACTION],
+            Method::Delete => &[DELETE_ACTION],
+            _ => &[PULL_ACTION, PUSH_ACTION],
+        }
+    };
+
+    Some(ResourceScope::repository(&name, actions))
 }
 
-fn auth_failure<'r>(request: &'r Request, config: &Config) -> request::Outcome<Auth, AuthFailure> {
-    let auth_failure = AuthFailure::Unauthorized(UnauthorizedResponse::new(config));
+fn auth_failure<T>(
+    request: &Request,
+    config: &Config,
+    scope: Option<ResourceScope>,
+    error: Option<&str>,
+) -> request::Outcome<T, AuthFailure> {
+    let auth_failure =
+        AuthFailure::Unauthorized(UnauthorizedResponse::new(config, scope.as_ref(), error));
     request.local_cache(|| auth_failure.clone());
-    return request::Outcome::Error((Status::Unauthorized, auth_failure));
+    request::Outcome::Error((Status::Unauthorized, auth_failure))
+}
+
+fn denied_failure<T>(request: &Request, err: RegistryError) -> request::Outcome<T, AuthFailure> {
+    let auth_failure = AuthFailure::Denied(err.into());
+    request.local_cache(|| auth_failure.clone());
+    request::Outcome::Error((Status::Forbidden, auth_failure))
+}
+
+fn internal_failure<T>(message: &str) -> request::Outcome<T, AuthFailure> {
+    request::Outcome::Error((
+        Status::InternalServerError,
+        AuthFailure::InternalServerError(message.to_string()),
+    ))
 }
 
 #[derive(Responder)]
@@ -107,6 +257,7 @@ pub enum SpecComplianceResponse {
     Ok(()),
     #[response(status = 401)]
     Unauthorized(UnauthorizedResponse),
+    Denied(OCIErrorResponse),
     #[response(status = 500)]
     InternalServerError(()),
 }
@@ -116,6 +267,7 @@ pub fn get_spec_compliance(auth: Result<Auth, AuthFailure>) -> SpecComplianceRes
     match auth {
         Ok(_) => SpecComplianceResponse::Ok(()),
         Err(AuthFailure::Unauthorized(resp)) => SpecComplianceResponse::Unauthorized(resp),
+        Err(AuthFailure::Denied(resp)) => SpecComplianceResponse::Denied(resp),
         Err(AuthFailure::InternalServerError(err)) => {
             error!("Internal server error {err:?}");
             SpecComplianceResponse::InternalServerError(())
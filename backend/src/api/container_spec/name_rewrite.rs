@@ -0,0 +1,132 @@
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::uri::Origin,
+    Data, Request,
+};
+
+const ENCODED_NAME_SEPARATOR: &str = "%2F";
+
+/// Rocket can only capture a single path segment in the middle of a route, but repository
+/// names may consist of several segments (e.g. `team/service/api`).
+/// This fairing percent-encodes the slashes of the name so that it is routed as one segment.
+pub struct RepositoryNameRewrite;
+
+#[rocket::async_trait]
+impl Fairing for RepositoryNameRewrite {
+    fn info(&self) -> Info {
+        Info {
+            name: "Multi-segment repository name rewrite",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let Some(path) = rewrite_path(req.uri().path().as_str()) else {
+            return;
+        };
+
+        let uri = match req.uri().query() {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        };
+
+        match Origin::parse_owned(uri) {
+            Ok(origin) => req.set_uri(origin),
+            Err(err) => error!("Failed to parse rewritten request uri, err: {err:?}"),
+        }
+    }
+}
+
+fn rewrite_path(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("/v2/")?;
+    let segments = rest
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>();
+
+    let name_length = name_length(&segments)?;
+    if name_length < 2 {
+        return None;
+    }
+
+    let (name, endpoint) = segments.split_at(name_length);
+    Some(format!(
+        "/v2/{}/{}",
+        name.join(ENCODED_NAME_SEPARATOR),
+        endpoint.join("/")
+    ))
+}
+
+/// Returns how many of the segments belong to the repository name by matching the known
+/// endpoints from the end of the path.
+fn name_length(segments: &[&str]) -> Option<usize> {
+    let name = match segments {
+        [name @ .., "blobs", "uploads", _] => name,
+        [name @ .., "blobs", _] => name,
+        [name @ .., "manifests", _] => name,
+        [name @ .., "tags", "list"] => name,
+        [name @ .., "referrers", _] => name,
+        _ => return None,
+    };
+
+    Some(name.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_multi_segment_names_of_every_endpoint() {
+        for (path, rewritten) in [
+            (
+                "/v2/team/api/blobs/uploads/",
+                "/v2/team%2Fapi/blobs/uploads",
+            ),
+            (
+                "/v2/team/api/blobs/uploads/1234",
+                "/v2/team%2Fapi/blobs/uploads/1234",
+            ),
+            (
+                "/v2/team/api/blobs/sha256:abcd",
+                "/v2/team%2Fapi/blobs/sha256:abcd",
+            ),
+            (
+                "/v2/team/service/api/manifests/latest",
+                "/v2/team%2Fservice%2Fapi/manifests/latest",
+            ),
+            ("/v2/team/api/tags/list", "/v2/team%2Fapi/tags/list"),
+            (
+                "/v2/team/api/referrers/sha256:abcd",
+                "/v2/team%2Fapi/referrers/sha256:abcd",
+            ),
+        ] {
+            assert_eq!(rewrite_path(path).as_deref(), Some(rewritten), "{path}");
+        }
+    }
+
+    #[test]
+    fn leaves_single_segment_names_and_other_paths_alone() {
+        for path in [
+            "/v2/",
+            "/v2/_catalog",
+            "/v2/alpine/manifests/latest",
+            "/v2/alpine/blobs/uploads/",
+            "/v2/team/api/unknown",
+            "/api/repositories/team/api",
+            "/token",
+        ] {
+            assert_eq!(rewrite_path(path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn matches_endpoints_from_the_end_of_the_path() {
+        assert_eq!(name_length(&["a", "b", "blobs", "uploads", "id"]), Some(2));
+        assert_eq!(name_length(&["a", "b", "c", "blobs", "digest"]), Some(3));
+        assert_eq!(name_length(&["manifests", "manifests", "latest"]), Some(1));
+        assert_eq!(name_length(&["a", "tags", "list"]), Some(1));
+        assert_eq!(name_length(&["a", "referrers", "digest"]), Some(1));
+        assert_eq!(name_length(&["a", "tags"]), None);
+    }
+}
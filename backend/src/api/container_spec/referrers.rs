@@ -0,0 +1,132 @@
+use rocket::{
+    http::{ContentType, Header},
+    serde::json::Json,
+    State,
+};
+use serde::Serialize;
+use sqlx::Pool;
+
+use crate::{
+    db::DB,
+    header,
+    models::manifest_referrer::Referrer,
+    registry_error::RegistryError,
+    services::get_referrers_service,
+    types::{digest::Digest, manifest::FAT_MANIFEST_CONTENT_TYPE, repository_name::RepositoryName},
+};
+
+use super::{errors::OCIErrorResponse, OptionalAuth, OCI_FILTERS_APPLIED_HEADER_NAME};
+
+const ARTIFACT_TYPE_FILTER: &str = "artifactType";
+
+#[derive(FromForm, Debug)]
+pub struct ReferrersFilter<'r> {
+    #[field(name = "artifactType")]
+    artifact_type: Option<&'r str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageIndex {
+    schema_version: i32,
+    media_type: &'static str,
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    media_type: String,
+    size: i64,
+    digest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<serde_json::Value>,
+}
+
+impl From<Referrer> for Descriptor {
+    fn from(value: Referrer) -> Self {
+        Self {
+            media_type: value.media_type,
+            size: value.size,
+            digest: value.digest,
+            artifact_type: value.artifact_type,
+            annotations: value.annotations,
+        }
+    }
+}
+
+#[derive(Responder, Debug)]
+pub struct ReferrersResponseData {
+    index: Json<ImageIndex>,
+    content_type: ContentType,
+}
+
+#[derive(Responder, Debug)]
+pub struct FilteredReferrersResponseData<'a> {
+    inner: ReferrersResponseData,
+    filters_applied: Header<'a>,
+}
+
+#[derive(Responder)]
+pub enum ReferrersResponse<'a> {
+    #[response(status = 200)]
+    Success(ReferrersResponseData),
+    #[response(status = 200)]
+    Filtered(FilteredReferrersResponseData<'a>),
+    Error(OCIErrorResponse),
+}
+
+#[get("/v2/<name>/referrers/<digest>?<filter..>")]
+pub async fn get_referrers<'a>(
+    db_pool: &State<Pool<DB>>,
+    _auth: OptionalAuth,
+    name: Result<RepositoryName, RegistryError>,
+    digest: Result<Digest, RegistryError>,
+    filter: ReferrersFilter<'_>,
+) -> ReferrersResponse<'a> {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting referrers listing, err: {err:?}");
+            return ReferrersResponse::Error(err.into());
+        }
+    };
+
+    let digest = match digest {
+        Ok(digest) => digest,
+        Err(err) => {
+            warn!("Rejecting referrers listing, err: {err:?}");
+            return ReferrersResponse::Error(err.into());
+        }
+    };
+
+    let referrers =
+        match get_referrers_service::get_referrers(db_pool, &name, &digest, filter.artifact_type)
+            .await
+        {
+            Ok(referrers) => referrers,
+            Err(err) => {
+                error!("Failed to retrieve referrers, err: {err:?}");
+                return ReferrersResponse::Error(err.into());
+            }
+        };
+
+    let data = ReferrersResponseData {
+        index: Json(ImageIndex {
+            schema_version: 2,
+            media_type: FAT_MANIFEST_CONTENT_TYPE,
+            manifests: referrers.into_iter().map(Descriptor::from).collect(),
+        }),
+        content_type: ContentType::new("application", "vnd.oci.image.index.v1+json"),
+    };
+
+    match filter.artifact_type {
+        Some(_) => ReferrersResponse::Filtered(FilteredReferrersResponseData {
+            inner: data,
+            filters_applied: header!(OCI_FILTERS_APPLIED_HEADER_NAME, ARTIFACT_TYPE_FILTER),
+        }),
+        None => ReferrersResponse::Success(data),
+    }
+}
@@ -2,7 +2,12 @@ use rocket::{serde::json::Json, State};
 use serde::Serialize;
 use sqlx::Pool;
 
-use crate::{db::DB, services::get_tags_service};
+use crate::{
+    db::DB, registry_error::RegistryError, services::get_tags_service,
+    types::repository_name::RepositoryName,
+};
+
+use super::{errors::OCIErrorResponse, OptionalAuth};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct TagsResponseData {
@@ -14,22 +19,30 @@ pub struct TagsResponseData {
 pub enum TagsResponse {
     #[response(status = 200)]
     Success(Json<TagsResponseData>),
-    #[response(status = 500)]
-    Failure(()),
+    Error(OCIErrorResponse),
 }
 
 #[get("/v2/<name>/tags/list?<n>&<last>")]
 pub async fn get_tags(
     db_pool: &State<Pool<DB>>,
-    name: &str,
+    _auth: OptionalAuth,
+    name: Result<RepositoryName, RegistryError>,
     n: Option<usize>,
     last: Option<String>,
 ) -> TagsResponse {
-    let tags = match get_tags_service::get_tags(db_pool, name, n, last).await {
+    let name = match name {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("Rejecting tag listing, err: {err:?}");
+            return TagsResponse::Error(err.into());
+        }
+    };
+
+    let tags = match get_tags_service::get_tags(db_pool, &name, n, last).await {
         Ok(tags) => tags,
         Err(err) => {
             error!("Failed to retrieve tags, err: {err:?}");
-            return TagsResponse::Failure(());
+            return TagsResponse::Error(err.into());
         }
     };
 
@@ -0,0 +1,164 @@
+use rocket::{http::Header, serde::json::Json, State};
+use serde::Serialize;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    Pool,
+};
+
+use crate::{
+    auth_provider::AuthProviders,
+    config::Config,
+    db::DB,
+    registry_error::RegistryResult,
+    services::{authorization_service, token_service},
+    types::{credentials::Credentials, resource_scope::ResourceScope},
+    upstream_registry::Proxy,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenResponseData {
+    token: String,
+    access_token: String,
+    expires_in: u64,
+    issued_at: DateTime<Utc>,
+}
+
+#[derive(Responder, Debug)]
+#[response(status = 401)]
+pub struct TokenUnauthorizedResponseData {
+    message: String,
+    www_authenticate: Header<'static>,
+}
+
+#[derive(Responder, Debug)]
+pub enum TokenResponse {
+    #[response(status = 200)]
+    Success(Json<TokenResponseData>),
+    Unauthorized(TokenUnauthorizedResponseData),
+    #[response(status = 400)]
+    BadRequest(String),
+    #[response(status = 500)]
+    Failure(String),
+}
+
+/// The credentials sent to the token endpoint, if any.
+pub struct TokenCredentials(Option<Credentials>);
+
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for TokenCredentials {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(
+        req: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        rocket::request::Outcome::Success(TokenCredentials(
+            req.headers()
+                .get_one("authorization")
+                .and_then(Credentials::parse),
+        ))
+    }
+}
+
+/// Issues tokens following the Docker registry token authentication flow. Clients authenticate
+/// once per token with credentials of one of the auth providers, requests without credentials get
+/// an anonymous token. Tokens carry the part of the requested scopes that the user is permitted,
+/// which is checked again on every request, see [`crate::services::authorization_service`].
+#[get("/token?<service>&<scope>")]
+pub async fn get_token(
+    config: &State<Config>,
+    db_pool: &State<Pool<DB>>,
+    proxy: &State<Proxy>,
+    auth_providers: &State<AuthProviders>,
+    credentials: TokenCredentials,
+    service: Option<&str>,
+    scope: Vec<String>,
+) -> TokenResponse {
+    if service.is_some_and(|service| service != config.token_service) {
+        warn!("Token requested for unknown service {service:?}");
+        return TokenResponse::BadRequest(format!("Unknown service {}", service.unwrap_or("")));
+    }
+
+    let scopes = scope
+        .iter()
+        .flat_map(|scope| scope.split(' '))
+        .filter(|scope| !scope.is_empty())
+        .map(|scope| ResourceScope::parse(scope).ok_or(scope))
+        .collect::<Result<Vec<_>, _>>();
+    let scopes = match scopes {
+        Ok(scopes) => scopes,
+        Err(scope) => {
+            warn!("Token requested with invalid scope {scope}");
+            return TokenResponse::BadRequest(format!("Invalid scope {scope}"));
+        }
+    };
+
+    let username = match &credentials.0 {
+        Some(credentials) => match auth_providers.authenticate(credentials).await {
+            Ok(Some(identity)) => Some(identity.username),
+            Ok(None) => {
+                return TokenResponse::Unauthorized(TokenUnauthorizedResponseData {
+                    message: "Invalid credentials".to_string(),
+                    www_authenticate: Header::new(
+                        "www-authenticate",
+                        format!(r#"Basic realm="{}""#, config.token_realm),
+                    ),
+                })
+            }
+            Err(err) => {
+                error!("Failed to authenticate token request, err: {err:?}");
+                return TokenResponse::Failure("Failed to authenticate".to_string());
+            }
+        },
+        None => None,
+    };
+
+    let scopes = match permitted_scopes(db_pool, proxy, username.as_deref(), scopes).await {
+        Ok(scopes) => scopes,
+        Err(err) => {
+            error!("Failed to authorize token request, err: {err:?}");
+            return TokenResponse::Failure("Failed to authorize".to_string());
+        }
+    };
+
+    let granted = scopes.iter().map(ToString::to_string).collect::<Vec<_>>();
+    match token_service::issue_token(config, username.as_deref(), scopes) {
+        Ok(issued) => {
+            info!(
+                "Issued token for {} with scopes {granted:?}, requested {scope:?}",
+                username.as_deref().unwrap_or("anonymous user")
+            );
+            TokenResponse::Success(Json(TokenResponseData {
+                access_token: issued.token.clone(),
+                token: issued.token,
+                expires_in: issued.expires_in,
+                issued_at: issued.issued_at,
+            }))
+        }
+        Err(err) => {
+            error!("Failed to issue token, err: {err:?}");
+            TokenResponse::Failure("Failed to issue token".to_string())
+        }
+    }
+}
+
+/// Scopes that aren't permitted at all are left out of the token, clients find out once they use
+/// it, as the spec asks.
+async fn permitted_scopes(
+    db_pool: &Pool<DB>,
+    proxy: &Proxy,
+    username: Option<&str>,
+    requested: Vec<ResourceScope>,
+) -> RegistryResult<Vec<ResourceScope>> {
+    let mut permitted = vec![];
+    for scope in requested.iter() {
+        match authorization_service::permitted_scope(db_pool, proxy, username, scope).await? {
+            Some(scope) => permitted.push(scope),
+            None => info!(
+                "{} is not permitted {scope}",
+                username.unwrap_or("Anonymous user")
+            ),
+        }
+    }
+
+    Ok(permitted)
+}
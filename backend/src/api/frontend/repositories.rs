@@ -1,17 +1,22 @@
 use rocket::{serde::json::Json, State};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{
     types::chrono::{DateTime, Utc},
     Pool,
 };
 
 use crate::{
+    api::container_spec::{Auth, OptionalAuth},
     db::DB,
     models::repository::ViewableRepository,
+    registry_error::RegistryError,
     services::{
         get_all_repositories_service,
         get_repository_service::{self, RepositoryInfo},
+        update_repository_service,
     },
+    types::visibility::Visibility,
+    upstream_registry::Proxy,
 };
 
 #[derive(Responder, Debug)]
@@ -32,6 +37,7 @@ pub struct GetRepositoriesResponseData {
 pub struct Repository {
     name: String,
     author: String,
+    visibility: Visibility,
     last_modified: DateTime<Utc>,
 }
 
@@ -40,20 +46,29 @@ impl From<ViewableRepository> for Repository {
         Self {
             name: value.namespace_name,
             author: value.username,
+            visibility: value.is_public.into(),
             last_modified: value.created_at,
         }
     }
 }
 
 #[get("/repositories")]
-pub async fn get_all_repositories(db_pool: &State<Pool<DB>>) -> GetRepositoriesResponse {
-    let repos = match get_all_repositories_service::get_all_repositories(db_pool).await {
-        Ok(repos) => repos,
-        Err(err) => {
-            error!("Failed to retrieve all repositories, err: {err:?}");
-            return GetRepositoriesResponse::Failure("Failed to retrieve repositories".to_string());
-        }
-    };
+pub async fn get_all_repositories(
+    db_pool: &State<Pool<DB>>,
+    auth: OptionalAuth,
+) -> GetRepositoriesResponse {
+    let repos =
+        match get_all_repositories_service::get_all_repositories(db_pool, auth.username.as_deref())
+            .await
+        {
+            Ok(repos) => repos,
+            Err(err) => {
+                error!("Failed to retrieve all repositories, err: {err:?}");
+                return GetRepositoriesResponse::Failure(
+                    "Failed to retrieve repositories".to_string(),
+                );
+            }
+        };
 
     GetRepositoriesResponse::Success(Json(GetRepositoriesResponseData {
         repositories: repos
@@ -78,6 +93,7 @@ pub enum GetRepositoryResponse {
 pub struct GetRepositoryResponseData {
     name: String,
     author: String,
+    visibility: Visibility,
     tags: Vec<Tag>,
 }
 
@@ -86,12 +102,13 @@ impl From<RepositoryInfo> for GetRepositoryResponseData {
         Self {
             name: value.name,
             author: value.owner_username,
+            visibility: value.visibility,
             tags: value
                 .tags
                 .into_iter()
-                .map(|manifest| Tag {
-                    name: manifest.tag.unwrap_or("latest".to_string()),
-                    created_at: manifest.created_at,
+                .map(|tag| Tag {
+                    name: tag.name,
+                    created_at: tag.updated_at,
                 })
                 .collect::<Vec<Tag>>(),
         }
@@ -106,15 +123,75 @@ pub struct Tag {
 }
 
 #[get("/repositories/<repository>")]
-pub async fn get_repository(db_pool: &State<Pool<DB>>, repository: &str) -> GetRepositoryResponse {
-    let repository = match get_repository_service::get_repository(db_pool, repository).await {
+pub async fn get_repository(
+    db_pool: &State<Pool<DB>>,
+    proxy: &State<Proxy>,
+    auth: OptionalAuth,
+    repository: &str,
+) -> GetRepositoryResponse {
+    let repository = match get_repository_service::get_repository(
+        db_pool,
+        proxy,
+        auth.username.as_deref(),
+        repository,
+    )
+    .await
+    {
         Ok(data) => data,
+        Err(RegistryError::RepositoryNotFound) => {
+            return GetRepositoryResponse::RepositoryNotFound("Repository not found".to_string());
+        }
         Err(err) => {
-            // TODO: Handle not found
             warn!("Failed to retrieve repository, err: {err:?}");
             return GetRepositoryResponse::Failure("Failed to retrieve repository".to_string());
         }
     };
 
-    return GetRepositoryResponse::Success(Json(repository.into()));
+    GetRepositoryResponse::Success(Json(repository.into()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryVisibility {
+    visibility: Visibility,
+}
+
+#[derive(Responder, Debug)]
+pub enum SetVisibilityResponse {
+    #[response(status = 200)]
+    Success(Json<RepositoryVisibility>),
+    #[response(status = 403)]
+    Forbidden(String),
+    #[response(status = 404)]
+    RepositoryNotFound(String),
+    #[response(status = 500)]
+    Failure(String),
+}
+
+#[put("/repositories/<repository>/visibility", data = "<request>")]
+pub async fn put_visibility(
+    db_pool: &State<Pool<DB>>,
+    auth: Auth,
+    repository: &str,
+    request: Json<RepositoryVisibility>,
+) -> SetVisibilityResponse {
+    match update_repository_service::set_visibility(
+        db_pool,
+        &auth.username,
+        repository,
+        request.visibility,
+    )
+    .await
+    {
+        Ok(()) => SetVisibilityResponse::Success(request),
+        Err(RegistryError::RepositoryNotFound) => {
+            SetVisibilityResponse::RepositoryNotFound("Repository not found".to_string())
+        }
+        Err(RegistryError::NotRepositoryOwner) => SetVisibilityResponse::Forbidden(
+            "Only the owner of the repository can change its visibility".to_string(),
+        ),
+        Err(err) => {
+            error!("Failed to set repository visibility, err: {err:?}");
+            SetVisibilityResponse::Failure("Failed to set repository visibility".to_string())
+        }
+    }
 }
@@ -0,0 +1,123 @@
+use rocket::{serde::json::Json, State};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    Pool,
+};
+use uuid::Uuid;
+
+use crate::{
+    api::container_spec::Auth, db::DB, models::notification_endpoint::NotificationEndpoint,
+    registry_error::RegistryError, services::notification_service,
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    id: Uuid,
+    url: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<NotificationEndpoint> for Webhook {
+    fn from(value: NotificationEndpoint) -> Self {
+        Self {
+            id: value.id,
+            url: value.url,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetWebhooksResponseData {
+    webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateWebhookRequest {
+    url: String,
+}
+
+#[derive(Responder, Debug)]
+pub enum WebhookResponse<T> {
+    #[response(status = 200)]
+    Success(Json<T>),
+    #[response(status = 201)]
+    Created(Json<T>),
+    #[response(status = 204)]
+    Deleted(()),
+    #[response(status = 400)]
+    BadRequest(String),
+    #[response(status = 403)]
+    Forbidden(String),
+    #[response(status = 404)]
+    NotFound(String),
+    #[response(status = 500)]
+    Failure(String),
+}
+
+impl<T> From<RegistryError> for WebhookResponse<T> {
+    fn from(value: RegistryError) -> Self {
+        match value {
+            RegistryError::RepositoryNotFound => Self::NotFound("Repository not found".to_string()),
+            RegistryError::NotRepositoryOwner => {
+                Self::Forbidden("Only the owner of the repository can manage its webhooks".into())
+            }
+            RegistryError::InvalidNotificationEndpoint(url) => {
+                Self::BadRequest(format!("Invalid webhook url {url}"))
+            }
+            err => {
+                error!("Failed to manage webhooks, err: {err:?}");
+                Self::Failure("Failed to manage webhooks".to_string())
+            }
+        }
+    }
+}
+
+#[get("/repositories/<repository>/webhooks")]
+pub async fn get_webhooks(
+    db_pool: &State<Pool<DB>>,
+    auth: Auth,
+    repository: &str,
+) -> WebhookResponse<GetWebhooksResponseData> {
+    match notification_service::get_endpoints(db_pool, &auth.username, repository).await {
+        Ok(endpoints) => WebhookResponse::Success(Json(GetWebhooksResponseData {
+            webhooks: endpoints.into_iter().map(|e| e.into()).collect(),
+        })),
+        Err(err) => err.into(),
+    }
+}
+
+#[post("/repositories/<repository>/webhooks", data = "<request>")]
+pub async fn post_webhook(
+    db_pool: &State<Pool<DB>>,
+    auth: Auth,
+    repository: &str,
+    request: Json<CreateWebhookRequest>,
+) -> WebhookResponse<Webhook> {
+    match notification_service::add_endpoint(db_pool, &auth.username, repository, &request.url)
+        .await
+    {
+        Ok(endpoint) => WebhookResponse::Created(Json(endpoint.into())),
+        Err(err) => err.into(),
+    }
+}
+
+#[delete("/repositories/<repository>/webhooks/<id>")]
+pub async fn delete_webhook(
+    db_pool: &State<Pool<DB>>,
+    auth: Auth,
+    repository: &str,
+    id: &str,
+) -> WebhookResponse<()> {
+    let Ok(id) = Uuid::parse_str(id) else {
+        return WebhookResponse::NotFound("Webhook not found".to_string());
+    };
+
+    match notification_service::delete_endpoint(db_pool, &auth.username, repository, id).await {
+        Ok(true) => WebhookResponse::Deleted(()),
+        Ok(false) => WebhookResponse::NotFound("Webhook not found".to_string()),
+        Err(err) => err.into(),
+    }
+}
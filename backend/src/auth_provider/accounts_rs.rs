@@ -0,0 +1,117 @@
+use serde::Deserialize;
+
+use crate::{
+    config::AccountsRsConfig,
+    registry_error::{RegistryError, RegistryResult},
+    types::credentials::Credentials,
+};
+
+use super::{AuthProvider, Identity};
+
+#[derive(Deserialize, Clone)]
+pub struct AccountsRsUserResponse {
+    pub success: AccountsRsUserInfo,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AccountsRsUserInfo {
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AccountsRsTokenResponse {
+    pub access_token: Option<String>,
+    pub token: Option<String>,
+}
+
+/// Users of an accounts-rs instance, identified by their email address. A username and password
+/// are first exchanged for an accounts-rs token, which identifies the user.
+pub struct AccountsRsProvider {
+    client: reqwest::Client,
+    config: AccountsRsConfig,
+    service: String,
+}
+
+impl AccountsRsProvider {
+    pub fn new(config: AccountsRsConfig, service: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+            service: service.to_string(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl AuthProvider for AccountsRsProvider {
+    fn name(&self) -> &'static str {
+        "accounts-rs"
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    async fn authenticate(&self, credentials: &Credentials) -> RegistryResult<Option<Identity>> {
+        let access_token = match credentials {
+            Credentials::Bearer(token) => token.clone(),
+            Credentials::Basic { username, password } => {
+                let response = self
+                    .client
+                    .get(&self.config.auth_endpoint)
+                    .basic_auth(username, Some(password))
+                    .query(&[("service", &self.service)])
+                    .send()
+                    .await
+                    .map_err(accounts_service_error)?;
+
+                if !response.status().is_success() {
+                    warn!(
+                        "Accounts service rejected the password of {username} (status {})",
+                        response.status()
+                    );
+                    return Ok(None);
+                }
+
+                let body: AccountsRsTokenResponse =
+                    response.json().await.map_err(accounts_service_error)?;
+                let Some(token) = body.access_token.or(body.token) else {
+                    return Err(accounts_service_error("token response without a token"));
+                };
+                token
+            }
+        };
+
+        let response = self
+            .client
+            .get(&self.config.me_endpoint)
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .map_err(accounts_service_error)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            warn!("Got error response (status {status}) from accounts service");
+            return Ok(None);
+        }
+
+        let user_info: AccountsRsUserResponse =
+            response.json().await.map_err(accounts_service_error)?;
+
+        Ok(Some(Identity {
+            display_name: format!(
+                "{} {}",
+                user_info.success.first_name, user_info.success.last_name
+            ),
+            username: user_info.success.email,
+            groups: vec![],
+        }))
+    }
+}
+
+fn accounts_service_error(err: impl ToString) -> RegistryError {
+    RegistryError::AccountsServiceError(err.to_string())
+}
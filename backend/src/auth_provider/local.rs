@@ -0,0 +1,42 @@
+use sqlx::Pool;
+
+use crate::{
+    db::DB, registry_error::RegistryResult, services::local_user_service,
+    types::credentials::Credentials,
+};
+
+use super::{AuthProvider, Identity};
+
+/// Users stored by the registry, they authenticate with a username and password.
+pub struct LocalProvider {
+    db_pool: Pool<DB>,
+}
+
+impl LocalProvider {
+    pub fn new(db_pool: Pool<DB>) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[rocket::async_trait]
+impl AuthProvider for LocalProvider {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn authenticate(&self, credentials: &Credentials) -> RegistryResult<Option<Identity>> {
+        let Credentials::Basic { username, password } = credentials else {
+            return Ok(None);
+        };
+
+        if !local_user_service::verify_password(&self.db_pool, username, password).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(Identity {
+            username: username.clone(),
+            display_name: username.clone(),
+            groups: vec![],
+        }))
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use sqlx::Pool;
+
+use crate::{
+    config::{AuthProviderConfig, Config},
+    db::DB,
+    registry_error::{RegistryError, RegistryResult},
+    services::auth_identity_service,
+    types::credentials::Credentials,
+};
+
+use self::{
+    accounts_rs::AccountsRsProvider, local::LocalProvider, oidc::OidcProvider,
+    static_tokens::StaticTokensProvider,
+};
+
+pub mod accounts_rs;
+pub mod local;
+pub mod oidc;
+pub mod static_tokens;
+
+/// The user behind a set of credentials.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// Owns repositories and is the subject of issued tokens.
+    pub username: String,
+    pub display_name: String,
+    pub groups: Vec<String>,
+}
+
+/// A source of identities, such as an external accounts service or the users stored by the
+/// registry.
+#[rocket::async_trait]
+pub trait AuthProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Remote providers call out to another service for every authentication, they are only
+    /// asked by the token endpoint so registry requests don't depend on them.
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    /// Returns `None` if the credentials are rejected or not meant for this provider, so the
+    /// next provider can try them.
+    async fn authenticate(&self, credentials: &Credentials) -> RegistryResult<Option<Identity>>;
+}
+
+/// The configured providers in the order they are tried, shared by all requests. Every username
+/// belongs to a single provider, see [`auth_identity_service::bind`].
+#[derive(Clone)]
+pub struct AuthProviders {
+    providers: Arc<Vec<Box<dyn AuthProvider>>>,
+    db_pool: Pool<DB>,
+}
+
+impl AuthProviders {
+    pub fn new(config: &Config, db_pool: &Pool<DB>) -> RegistryResult<Self> {
+        let mut providers: Vec<Box<dyn AuthProvider>> = vec![];
+        for provider_config in config.auth_providers.iter() {
+            let provider: Box<dyn AuthProvider> = match provider_config {
+                AuthProviderConfig::AccountsRs(accounts_rs) => Box::new(AccountsRsProvider::new(
+                    accounts_rs.clone(),
+                    &config.token_service,
+                )),
+                AuthProviderConfig::Local => Box::new(LocalProvider::new(db_pool.clone())),
+                AuthProviderConfig::StaticTokens(static_tokens) => {
+                    Box::new(StaticTokensProvider::load(static_tokens)?)
+                }
+                AuthProviderConfig::Oidc(oidc) => Box::new(OidcProvider::load(oidc.clone())?),
+            };
+            info!("Authenticating users with {}", provider.name());
+            providers.push(provider);
+        }
+
+        Ok(Self {
+            providers: Arc::new(providers),
+            db_pool: db_pool.clone(),
+        })
+    }
+
+    /// Asks every provider in turn, for the token endpoint.
+    pub async fn authenticate(
+        &self,
+        credentials: &Credentials,
+    ) -> RegistryResult<Option<Identity>> {
+        self.authenticate_with(credentials, true).await
+    }
+
+    /// Asks the providers that don't call out to another service, for credentials sent with a
+    /// registry request instead of a token.
+    pub async fn authenticate_request(
+        &self,
+        credentials: &Credentials,
+    ) -> RegistryResult<Option<Identity>> {
+        self.authenticate_with(credentials, false).await
+    }
+
+    /// A provider that fails doesn't stop the others from accepting the credentials, its error is
+    /// only returned if none of them do. An identity whose username belongs to another provider
+    /// is rejected like invalid credentials.
+    async fn authenticate_with(
+        &self,
+        credentials: &Credentials,
+        include_remote: bool,
+    ) -> RegistryResult<Option<Identity>> {
+        let mut failure: Option<RegistryError> = None;
+
+        for provider in self.providers.iter() {
+            if provider.is_remote() && !include_remote {
+                continue;
+            }
+
+            let identity = match provider.authenticate(credentials).await {
+                Ok(Some(identity)) => {
+                    auth_identity_service::bind(&self.db_pool, provider.name(), &identity.username)
+                        .await
+                        .map(|is_bound| is_bound.then_some(identity))
+                }
+                result => result,
+            };
+
+            match identity {
+                Ok(Some(identity)) => {
+                    info!(
+                        "Authenticated {} ({}, groups {:?}) with {}",
+                        identity.username,
+                        identity.display_name,
+                        identity.groups,
+                        provider.name()
+                    );
+                    return Ok(Some(identity));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    error!("Auth provider {} failed, err: {err:?}", provider.name());
+                    failure = Some(err);
+                }
+            }
+        }
+
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+
+use crate::{
+    config::OidcConfig,
+    registry_error::{RegistryError, RegistryResult},
+    types::credentials::Credentials,
+};
+
+use super::{AuthProvider, Identity};
+
+/// ID tokens are signed by the OpenID Connect provider with one of its private keys, symmetric
+/// algorithms would let anyone holding the key set forge tokens.
+const ALLOWED_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+const DISPLAY_NAME_CLAIM: &str = "name";
+
+/// ID tokens of an OpenID Connect provider, verified against the keys of a JWKS file which is read
+/// once at startup. Clients send a token as a bearer token or as the password of HTTP Basic
+/// credentials, the username is ignored.
+pub struct OidcProvider {
+    keys: JwkSet,
+    config: OidcConfig,
+}
+
+impl OidcProvider {
+    pub fn load(config: OidcConfig) -> RegistryResult<Self> {
+        let contents = std::fs::read_to_string(&config.jwks_file)?;
+        let keys: JwkSet = serde_json::from_str(&contents)?;
+
+        info!(
+            "Loaded {} keys of {} from {}",
+            keys.keys.len(),
+            config.issuer,
+            config.jwks_file
+        );
+        Ok(Self { keys, config })
+    }
+
+    fn verify(&self, token: &str) -> RegistryResult<HashMap<String, Value>> {
+        let header = jsonwebtoken::decode_header(token)
+            .map_err(|err| RegistryError::InvalidToken(err.to_string()))?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(RegistryError::InvalidToken(format!(
+                "algorithm {:?} is not allowed",
+                header.alg
+            )));
+        }
+
+        // Tokens without a key id can only be verified when there is a single key.
+        let jwk = match &header.kid {
+            Some(kid) => self.keys.find(kid),
+            None if self.keys.keys.len() == 1 => self.keys.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| RegistryError::InvalidToken(format!("unknown key {:?}", header.kid)))?;
+        let key = DecodingKey::from_jwk(jwk)
+            .map_err(|err| RegistryError::InvalidToken(err.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
+        jsonwebtoken::decode::<HashMap<String, Value>>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|err| RegistryError::InvalidToken(err.to_string()))
+    }
+}
+
+#[rocket::async_trait]
+impl AuthProvider for OidcProvider {
+    fn name(&self) -> &'static str {
+        "oidc"
+    }
+
+    async fn authenticate(&self, credentials: &Credentials) -> RegistryResult<Option<Identity>> {
+        let token = match credentials {
+            Credentials::Bearer(token) => token,
+            Credentials::Basic { password, .. } => password,
+        };
+
+        // Passwords and tokens of other providers are no JWTs, they aren't worth a warning.
+        if jsonwebtoken::decode_header(token).is_err() {
+            return Ok(None);
+        }
+
+        let claims = match self.verify(token) {
+            Ok(claims) => claims,
+            Err(err) => {
+                warn!("Rejecting ID token, err: {err:?}");
+                return Ok(None);
+            }
+        };
+
+        let Some(username) = claims
+            .get(&self.config.username_claim)
+            .and_then(Value::as_str)
+            .filter(|username| !username.is_empty())
+        else {
+            warn!(
+                "ID token is missing the username claim {}",
+                self.config.username_claim
+            );
+            return Ok(None);
+        };
+
+        let groups = claims
+            .get(&self.config.groups_claim)
+            .and_then(Value::as_array)
+            .map(|groups| {
+                groups
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Some(Identity {
+            username: username.to_string(),
+            display_name: claims
+                .get(DISPLAY_NAME_CLAIM)
+                .and_then(Value::as_str)
+                .unwrap_or(username)
+                .to_string(),
+            groups,
+        }))
+    }
+}
@@ -0,0 +1,70 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::StaticTokensConfig, registry_error::RegistryResult, types::credentials::Credentials,
+};
+
+use super::{AuthProvider, Identity};
+
+/// An entry of the static tokens file. Only the SHA-256 hash of a token is stored, e.g. the output
+/// of `printf %s "$TOKEN" | sha256sum`.
+#[derive(Deserialize)]
+struct StaticToken {
+    token_sha256: String,
+    username: String,
+    display_name: Option<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+/// API tokens listed in a JSON file, which is read once at startup. Clients send a token as a
+/// bearer token or as the password of HTTP Basic credentials, the username is ignored.
+pub struct StaticTokensProvider {
+    tokens: Vec<StaticToken>,
+}
+
+impl StaticTokensProvider {
+    pub fn load(config: &StaticTokensConfig) -> RegistryResult<Self> {
+        let contents = std::fs::read_to_string(&config.file)?;
+        let mut tokens: Vec<StaticToken> = serde_json::from_str(&contents)?;
+        for token in tokens.iter_mut() {
+            token.token_sha256.make_ascii_lowercase();
+        }
+
+        info!("Loaded {} static tokens from {}", tokens.len(), config.file);
+        Ok(Self { tokens })
+    }
+}
+
+#[rocket::async_trait]
+impl AuthProvider for StaticTokensProvider {
+    fn name(&self) -> &'static str {
+        "static-tokens"
+    }
+
+    async fn authenticate(&self, credentials: &Credentials) -> RegistryResult<Option<Identity>> {
+        let token = match credentials {
+            Credentials::Bearer(token) => token,
+            Credentials::Basic { password, .. } => password,
+        };
+
+        let token_sha256 = format!("{:x}", Sha256::digest(token.as_bytes()));
+        let Some(token) = self
+            .tokens
+            .iter()
+            .find(|token| token.token_sha256 == token_sha256)
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(Identity {
+            username: token.username.clone(),
+            display_name: token
+                .display_name
+                .clone()
+                .unwrap_or_else(|| token.username.clone()),
+            groups: token.groups.clone(),
+        }))
+    }
+}
@@ -1,4 +1,8 @@
-use std::env::{self, VarError};
+use std::{
+    env::{self, VarError},
+    fmt::Display,
+    time::Duration,
+};
 
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum ConfigError {
@@ -8,19 +12,43 @@ pub enum ConfigError {
     VarEmpty(String),
     #[error("Invalid bool `{0}`")]
     InvalidBool(String),
+    #[error("Invalid number `{0}`")]
+    InvalidNumber(String),
+    #[error("Interval `{0}` must be longer than 0 seconds")]
+    ZeroInterval(String),
+    #[error("Unknown storage driver `{0}`")]
+    UnknownStorageDriver(String),
+    #[error("Unknown auth provider `{0}`")]
+    UnknownAuthProvider(String),
+    #[error("Invalid platform `{0}`, expected `os/architecture`")]
+    InvalidPlatform(String),
+    #[error("Invalid proxy namespace `{0}`")]
+    InvalidProxyNamespace(String),
 }
 
 pub type ConfigResult<T> = Result<T, ConfigError>;
 
+#[derive(Clone)]
 pub struct Config {
     pub database_url: String,
     pub log_db_statements: bool,
     pub storage_directory: String,
+    pub storage: StorageConfig,
     pub docker_socket_url: String,
     pub registry_url: String,
-    pub auth_service: String,
-    pub accounts_rs_auth_endpoint: String,
-    pub accounts_rs_me_endpoint: String,
+    pub auth_providers: Vec<AuthProviderConfig>,
+    /// The name of the service tokens are issued for, clients send it to the token endpoint.
+    pub token_service: String,
+    pub token_realm: String,
+    pub token_signing_key: String,
+    pub token_ttl: Duration,
+    pub upload_session_max_age: Duration,
+    pub upload_session_sweep_interval: Duration,
+    pub gc_grace_period: Duration,
+    /// The platform served to clients that pull a tagged index but don't accept indexes.
+    pub default_platform: PlatformConfig,
+    pub proxy: Option<ProxyConfig>,
+    pub notifications: NotificationConfig,
 }
 
 impl Config {
@@ -30,11 +58,227 @@ impl Config {
             database_url: load_env_str("DATABASE_URL")?,
             log_db_statements: load_env_bool("LOG_DB_STATEMENTS")?,
             storage_directory: load_env_str("STORAGE_DIRECTORY")?,
+            storage: StorageConfig::load()?,
             docker_socket_url: load_env_str("DOCKER_SOCKET_URL")?,
             registry_url: load_env_str("REGISTRY_URL")?,
-            auth_service: load_env_str("AUTH_SERVICE")?,
-            accounts_rs_auth_endpoint: load_env_str("ACCOUNTS_RS_AUTH_ENDPOINT")?,
-            accounts_rs_me_endpoint: load_env_str("ACCOUNTS_RS_ME_ENDPOINT")?,
+            auth_providers: AuthProviderConfig::load()?,
+            token_service: load_env_str("TOKEN_SERVICE")?,
+            token_realm: load_env_str("TOKEN_REALM")?,
+            token_signing_key: load_env_str("TOKEN_SIGNING_KEY")?,
+            token_ttl: load_env_seconds("TOKEN_TTL_SECONDS")?,
+            upload_session_max_age: load_env_seconds("UPLOAD_SESSION_MAX_AGE_SECONDS")?,
+            upload_session_sweep_interval: load_env_interval(
+                "UPLOAD_SESSION_SWEEP_INTERVAL_SECONDS",
+            )?,
+            gc_grace_period: load_env_seconds("GC_GRACE_PERIOD_SECONDS")?,
+            default_platform: PlatformConfig::load()?,
+            proxy: ProxyConfig::load()?,
+            notifications: NotificationConfig::load()?,
+        })
+    }
+}
+
+/// Where blobs and manifests are stored. Uploads in progress are always staged in
+/// `STORAGE_DIRECTORY`.
+#[derive(Clone)]
+pub enum StorageConfig {
+    /// Files below `STORAGE_DIRECTORY`.
+    Filesystem,
+    /// Objects in a bucket of an S3-compatible object store.
+    S3(S3Config),
+}
+
+#[derive(Clone)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl StorageConfig {
+    fn load() -> ConfigResult<Self> {
+        let driver = load_env_str("STORAGE_DRIVER")?;
+        match driver.as_str() {
+            "filesystem" => Ok(Self::Filesystem),
+            "s3" => Ok(Self::S3(S3Config {
+                endpoint: load_env_str("S3_ENDPOINT")?,
+                bucket: load_env_str("S3_BUCKET")?,
+                region: load_env_str("S3_REGION")?,
+                access_key_id: load_env_str("S3_ACCESS_KEY_ID")?,
+                secret_access_key: load_env_str("S3_SECRET_ACCESS_KEY")?,
+            })),
+            _ => Err(ConfigError::UnknownStorageDriver(driver)),
+        }
+    }
+}
+
+/// Where users are authenticated, clients either get a token with their credentials or send them
+/// with every request, see [`crate::auth_provider`]. Providers are tried in the configured order.
+#[derive(Clone)]
+pub enum AuthProviderConfig {
+    /// Users of an accounts-rs instance.
+    AccountsRs(AccountsRsConfig),
+    /// Users stored by the registry, managed with `container-registry-rs users`.
+    Local,
+    /// API tokens listed in a file, e.g. for CI pipelines.
+    StaticTokens(StaticTokensConfig),
+    /// ID tokens of an OpenID Connect provider, verified with the keys of a JWKS file.
+    Oidc(OidcConfig),
+}
+
+#[derive(Clone)]
+pub struct AccountsRsConfig {
+    pub auth_endpoint: String,
+    pub me_endpoint: String,
+}
+
+#[derive(Clone)]
+pub struct StaticTokensConfig {
+    pub file: String,
+}
+
+#[derive(Clone)]
+pub struct OidcConfig {
+    pub jwks_file: String,
+    pub issuer: String,
+    pub audience: String,
+    /// The claim holding the username, e.g. `preferred_username` or `email`.
+    pub username_claim: String,
+    /// The claim holding the list of groups, tokens without it have no groups.
+    pub groups_claim: String,
+}
+
+impl AuthProviderConfig {
+    fn load() -> ConfigResult<Vec<Self>> {
+        let providers = load_env_str("AUTH_PROVIDERS")?
+            .split(',')
+            .map(str::trim)
+            .filter(|provider| !provider.is_empty())
+            .map(Self::load_provider)
+            .collect::<ConfigResult<Vec<_>>>()?;
+
+        if providers.is_empty() {
+            return Err(ConfigError::VarEmpty("AUTH_PROVIDERS".to_string()));
+        }
+
+        Ok(providers)
+    }
+
+    fn load_provider(provider: &str) -> ConfigResult<Self> {
+        match provider {
+            "accounts-rs" => Ok(Self::AccountsRs(AccountsRsConfig {
+                auth_endpoint: load_env_str("ACCOUNTS_RS_AUTH_ENDPOINT")?,
+                me_endpoint: load_env_str("ACCOUNTS_RS_ME_ENDPOINT")?,
+            })),
+            "local" => Ok(Self::Local),
+            "static-tokens" => Ok(Self::StaticTokens(StaticTokensConfig {
+                file: load_env_str("STATIC_TOKENS_FILE")?,
+            })),
+            "oidc" => Ok(Self::Oidc(OidcConfig {
+                jwks_file: load_env_str("OIDC_JWKS_FILE")?,
+                issuer: load_env_str("OIDC_ISSUER")?,
+                audience: load_env_str("OIDC_AUDIENCE")?,
+                username_claim: load_env_str("OIDC_USERNAME_CLAIM")?,
+                groups_claim: load_env_str("OIDC_GROUPS_CLAIM")?,
+            })),
+            _ => Err(ConfigError::UnknownAuthProvider(provider.to_string())),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PlatformConfig {
+    pub os: String,
+    pub architecture: String,
+}
+
+impl PlatformConfig {
+    fn load() -> ConfigResult<Self> {
+        let platform = load_env_str("DEFAULT_PLATFORM")?;
+        match platform.split_once('/') {
+            Some((os, architecture))
+                if !os.is_empty() && !architecture.is_empty() && !architecture.contains('/') =>
+            {
+                Ok(Self {
+                    os: os.to_string(),
+                    architecture: architecture.to_string(),
+                })
+            }
+            _ => Err(ConfigError::InvalidPlatform(platform)),
+        }
+    }
+}
+
+impl Display for PlatformConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)
+    }
+}
+
+/// A namespace whose repositories mirror an upstream registry, e.g. `dockerhub/library/alpine`
+/// is pulled from `library/alpine` on the upstream when the namespace is `dockerhub`.
+#[derive(Clone)]
+pub struct ProxyConfig {
+    pub namespace: String,
+    pub upstream_url: String,
+    /// How long a cached tag is served before it is checked against the upstream again.
+    pub tag_ttl: Duration,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    fn load() -> ConfigResult<Option<Self>> {
+        if !load_env_bool("PROXY_ENABLED")? {
+            return Ok(None);
+        }
+
+        let namespace = load_env_str("PROXY_NAMESPACE")?;
+        if namespace.contains('/') {
+            return Err(ConfigError::InvalidProxyNamespace(namespace));
+        }
+
+        Ok(Some(Self {
+            namespace,
+            upstream_url: load_env_str("PROXY_UPSTREAM_URL")?
+                .trim_end_matches('/')
+                .to_string(),
+            tag_ttl: load_env_seconds("PROXY_TAG_TTL_SECONDS")?,
+            username: load_optional_env_str("PROXY_UPSTREAM_USERNAME")?,
+            password: load_optional_env_str("PROXY_UPSTREAM_PASSWORD")?,
+        }))
+    }
+}
+
+/// Where registry events are sent to, in addition to the endpoints configured per repository.
+#[derive(Clone)]
+pub struct NotificationConfig {
+    pub endpoints: Vec<String>,
+    /// Deliveries are given up after this many failed attempts.
+    pub max_attempts: u32,
+    /// The delay after the first failed attempt, which doubles with every further failure.
+    pub retry_interval: Duration,
+}
+
+impl NotificationConfig {
+    fn load() -> ConfigResult<Self> {
+        let endpoints = load_optional_env_str("NOTIFICATION_ENDPOINTS")?
+            .map(|endpoints| {
+                endpoints
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|endpoint| !endpoint.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            endpoints,
+            max_attempts: load_env_number("NOTIFICATION_MAX_ATTEMPTS")?,
+            retry_interval: load_env_interval("NOTIFICATION_RETRY_INTERVAL_SECONDS")?,
         })
     }
 }
@@ -50,6 +294,14 @@ fn load_env_str(key: &str) -> ConfigResult<String> {
     Ok(var)
 }
 
+fn load_optional_env_str(key: &str) -> ConfigResult<Option<String>> {
+    match env::var(key) {
+        Ok(var) if !var.is_empty() => Ok(Some(var)),
+        Ok(_) | Err(VarError::NotPresent) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
 fn load_env_bool(key: &str) -> ConfigResult<bool> {
     let var = load_env_str(key)?;
     match var.as_str() {
@@ -58,3 +310,22 @@ fn load_env_bool(key: &str) -> ConfigResult<bool> {
         _ => Err(ConfigError::InvalidBool(var)),
     }
 }
+
+fn load_env_number<T: std::str::FromStr>(key: &str) -> ConfigResult<T> {
+    let var = load_env_str(key)?;
+    var.parse().map_err(|_| ConfigError::InvalidNumber(var))
+}
+
+fn load_env_seconds(key: &str) -> ConfigResult<Duration> {
+    Ok(Duration::from_secs(load_env_number(key)?))
+}
+
+/// Like [`load_env_seconds`], for durations that something is repeated after, which can't be 0.
+fn load_env_interval(key: &str) -> ConfigResult<Duration> {
+    let interval = load_env_seconds(key)?;
+    if interval.is_zero() {
+        return Err(ConfigError::ZeroInterval(key.to_string()));
+    }
+
+    Ok(interval)
+}
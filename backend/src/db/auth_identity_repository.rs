@@ -0,0 +1,37 @@
+use sqlx::Transaction;
+
+use crate::{models::auth_identity::AuthIdentity, registry_error::RegistryResult};
+
+use super::DB;
+
+/// Binds the username to the provider unless it is bound already, returns the binding that
+/// exists afterwards.
+pub async fn insert_or_find(
+    transaction: &mut Transaction<'_, DB>,
+    username: &str,
+    provider: &str,
+) -> RegistryResult<AuthIdentity> {
+    sqlx::query!(
+        r#"
+INSERT INTO auth_identity(username, provider)
+VALUES                   ($1,       $2)
+ON CONFLICT (username) DO NOTHING
+        "#,
+        username,
+        provider
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(sqlx::query_as!(
+        AuthIdentity,
+        r#"
+SELECT username, provider, created_at
+FROM auth_identity
+WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use sqlx::Transaction;
 use uuid::Uuid;
 
@@ -9,16 +11,20 @@ pub async fn insert(
     transaction: &mut Transaction<'_, DB>,
     repository: &str,
     digest: &str,
+    size: i64,
+    media_type: Option<&str>,
 ) -> RegistryResult<Blob> {
     Ok(sqlx::query_as!(
         Blob,
         r#"
-INSERT INTO blob(repository, digest)
-VALUES          ($1,         $2)
-RETURNING id, repository, digest, created_at
+INSERT INTO blob(repository, digest, size, media_type)
+VALUES          ($1,         $2,     $3,   $4)
+RETURNING id, repository, digest, size, media_type, created_at
     "#,
         repository,
         digest,
+        size,
+        media_type,
     )
     .fetch_one(&mut **transaction)
     .await?)
@@ -32,7 +38,7 @@ pub async fn find_by_repository_and_id(
     Ok(sqlx::query_as!(
         Blob,
         r#"
-SELECT id, repository, digest, created_at
+SELECT id, repository, digest, size, media_type, created_at
 FROM blob
 WHERE id = $1 AND repository = $2
     "#,
@@ -51,7 +57,7 @@ pub async fn find_by_repository_and_digest(
     Ok(sqlx::query_as!(
         Blob,
         r#"
-SELECT id, repository, digest, created_at
+SELECT id, repository, digest, size, media_type, created_at
 FROM blob
 WHERE digest = $1 AND repository = $2
     "#,
@@ -62,6 +68,62 @@ WHERE digest = $1 AND repository = $2
     .await?)
 }
 
+/// Remembers the media type a manifest declared for the blob, so that it can be served with it.
+pub async fn set_media_type(
+    transaction: &mut Transaction<'_, DB>,
+    id: Uuid,
+    media_type: &str,
+) -> RegistryResult<()> {
+    sqlx::query!(
+        r#"
+UPDATE blob
+SET media_type = $1
+WHERE id = $2
+        "#,
+        media_type,
+        id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the digests of the blobs that were stored before their size was recorded.
+pub async fn find_digests_without_size(
+    transaction: &mut Transaction<'_, DB>,
+) -> RegistryResult<Vec<String>> {
+    Ok(sqlx::query_scalar!(
+        r#"
+SELECT DISTINCT digest
+FROM blob
+WHERE size IS NULL
+    "#
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+pub async fn set_size_by_digest(
+    transaction: &mut Transaction<'_, DB>,
+    digest: &str,
+    size: i64,
+) -> RegistryResult<()> {
+    sqlx::query!(
+        r#"
+UPDATE blob
+SET size = $1
+WHERE digest = $2 AND size IS NULL
+        "#,
+        size,
+        digest
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn delete_blob(transaction: &mut Transaction<'_, DB>, id: Uuid) -> RegistryResult<()> {
     sqlx::query_as!(
         Blob,
@@ -85,7 +147,7 @@ pub async fn find_blobs_by_digest(
     Ok(sqlx::query_as!(
         Blob,
         r#"
-SELECT id, repository, digest, created_at
+SELECT id, repository, digest, size, media_type, created_at
 FROM blob
 WHERE digest = $1
     "#,
@@ -94,3 +156,39 @@ WHERE digest = $1
     .fetch_all(&mut **transaction)
     .await?)
 }
+
+/// Deletes blobs older than `grace_period` that no manifest refers to, either as its config or
+/// as one of its layers.
+pub async fn delete_unreferenced(
+    transaction: &mut Transaction<'_, DB>,
+    grace_period: Duration,
+) -> RegistryResult<Vec<Blob>> {
+    Ok(sqlx::query_as!(
+        Blob,
+        r#"
+DELETE
+FROM blob b
+WHERE b.created_at < now() - make_interval(secs => $1)
+    AND NOT EXISTS (SELECT 1 FROM manifest_layer ml WHERE ml.blob_id = b.id)
+    AND NOT EXISTS (SELECT 1 FROM manifest m WHERE m.blob_id = b.id)
+RETURNING id, repository, digest, size, media_type, created_at
+        "#,
+        grace_period.as_secs_f64()
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+pub async fn exists_by_digest(
+    transaction: &mut Transaction<'_, DB>,
+    digest: &str,
+) -> RegistryResult<bool> {
+    Ok(sqlx::query_scalar!(
+        r#"
+SELECT EXISTS(SELECT 1 FROM blob WHERE digest = $1) AS "exists!"
+    "#,
+        digest
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
@@ -0,0 +1,44 @@
+use sqlx::Transaction;
+
+use crate::{models::local_user::LocalUser, registry_error::RegistryResult};
+
+use super::DB;
+
+/// Adds the user, or replaces the password of a user that already exists.
+pub async fn upsert(
+    transaction: &mut Transaction<'_, DB>,
+    username: &str,
+    password_hash: &str,
+) -> RegistryResult<LocalUser> {
+    Ok(sqlx::query_as!(
+        LocalUser,
+        r#"
+INSERT INTO local_user(username, password_hash)
+VALUES                ($1,       $2)
+ON CONFLICT (username) DO UPDATE
+SET password_hash = $2, updated_at = now()
+RETURNING id, username, password_hash, created_at, updated_at
+        "#,
+        username,
+        password_hash
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
+
+pub async fn find_by_username(
+    transaction: &mut Transaction<'_, DB>,
+    username: &str,
+) -> RegistryResult<Option<LocalUser>> {
+    Ok(sqlx::query_as!(
+        LocalUser,
+        r#"
+SELECT id, username, password_hash, created_at, updated_at
+FROM local_user
+WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_optional(&mut **transaction)
+    .await?)
+}
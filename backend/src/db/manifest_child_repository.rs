@@ -0,0 +1,88 @@
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{models::manifest_child::ManifestChild, registry_error::RegistryResult};
+
+use super::DB;
+
+pub async fn upsert(
+    transaction: &mut Transaction<'_, DB>,
+    index_id: Uuid,
+    child_id: Uuid,
+    media_type: &str,
+    size: i64,
+    platform: Option<serde_json::Value>,
+) -> RegistryResult<ManifestChild> {
+    Ok(sqlx::query_as!(
+        ManifestChild,
+        r#"
+INSERT INTO manifest_child(index_id, child_id, media_type, size, platform)
+VALUES                    ($1,       $2,       $3,         $4,   $5)
+ON CONFLICT (index_id, child_id) DO UPDATE
+SET media_type = $3, size = $4, platform = $5
+RETURNING index_id, child_id, media_type, size, platform, created_at
+        "#,
+        index_id,
+        child_id,
+        media_type,
+        size,
+        platform
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
+
+pub async fn find_all_by_child(
+    transaction: &mut Transaction<'_, DB>,
+    child_id: Uuid,
+) -> RegistryResult<Vec<ManifestChild>> {
+    Ok(sqlx::query_as!(
+        ManifestChild,
+        r#"
+SELECT index_id, child_id, media_type, size, platform, created_at
+FROM manifest_child
+WHERE child_id = $1
+        "#,
+        child_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+pub async fn delete_all_for_index(
+    transaction: &mut Transaction<'_, DB>,
+    index_id: Uuid,
+) -> RegistryResult<()> {
+    sqlx::query_as!(
+        ManifestChild,
+        r#"
+DELETE
+FROM manifest_child
+WHERE index_id = $1
+        "#,
+        index_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_all_for_child(
+    transaction: &mut Transaction<'_, DB>,
+    child_id: Uuid,
+) -> RegistryResult<()> {
+    sqlx::query_as!(
+        ManifestChild,
+        r#"
+DELETE
+FROM manifest_child
+WHERE child_id = $1
+        "#,
+        child_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
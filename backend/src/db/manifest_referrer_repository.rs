@@ -0,0 +1,78 @@
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{
+    models::manifest_referrer::{ManifestReferrer, Referrer},
+    registry_error::RegistryResult,
+};
+
+use super::DB;
+
+pub async fn upsert(
+    transaction: &mut Transaction<'_, DB>,
+    manifest_id: Uuid,
+    subject_digest: &str,
+    media_type: &str,
+    artifact_type: Option<&str>,
+    size: i64,
+    annotations: Option<serde_json::Value>,
+) -> RegistryResult<ManifestReferrer> {
+    Ok(sqlx::query_as!(
+        ManifestReferrer,
+        r#"
+INSERT INTO manifest_referrer(manifest_id, subject_digest, media_type, artifact_type, size, annotations)
+VALUES                       ($1,          $2,             $3,         $4,            $5,   $6)
+ON CONFLICT (manifest_id) DO UPDATE
+SET subject_digest = $2, media_type = $3, artifact_type = $4, size = $5, annotations = $6
+RETURNING manifest_id, subject_digest, media_type, artifact_type, size, annotations, created_at
+        "#,
+        manifest_id,
+        subject_digest,
+        media_type,
+        artifact_type,
+        size,
+        annotations
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
+
+pub async fn find_all_by_repository_and_subject(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    subject_digest: &str,
+) -> RegistryResult<Vec<Referrer>> {
+    Ok(sqlx::query_as!(
+        Referrer,
+        r#"
+SELECT DISTINCT ON (m.digest) m.digest, r.media_type, r.artifact_type, r.size, r.annotations
+FROM manifest_referrer r
+JOIN manifest m ON m.id = r.manifest_id
+WHERE m.repository = $1 AND r.subject_digest = $2
+ORDER BY m.digest ASC
+        "#,
+        repository,
+        subject_digest
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+pub async fn delete_for_manifest(
+    transaction: &mut Transaction<'_, DB>,
+    manifest_id: Uuid,
+) -> RegistryResult<()> {
+    sqlx::query_as!(
+        ManifestReferrer,
+        r#"
+DELETE
+FROM manifest_referrer
+WHERE manifest_id = $1
+        "#,
+        manifest_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
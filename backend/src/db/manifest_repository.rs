@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use sqlx::Transaction;
 use uuid::Uuid;
 
@@ -8,8 +10,7 @@ use super::DB;
 pub async fn insert(
     transaction: &mut Transaction<'_, DB>,
     repository: &str,
-    blob_id: Uuid,
-    tag: Option<&str>,
+    blob_id: Option<Uuid>,
     digest: &str,
     content_type_top: &str,
     content_type_sub: &str,
@@ -17,12 +18,11 @@ pub async fn insert(
     Ok(sqlx::query_as!(
         Manifest,
         r#"
-INSERT INTO manifest(repository, tag, blob_id, digest, content_type_top, content_type_sub)
-VALUES              ($1,         $2,  $3,      $4,     $5,               $6)
-RETURNING id, repository, tag, blob_id, digest, content_type_top, content_type_sub, created_at
+INSERT INTO manifest(repository, blob_id, digest, content_type_top, content_type_sub)
+VALUES              ($1,         $2,      $3,     $4,               $5)
+RETURNING id, repository, blob_id, digest, content_type_top, content_type_sub, created_at
         "#,
         repository,
-        tag,
         blob_id,
         digest,
         content_type_top,
@@ -35,14 +35,15 @@ RETURNING id, repository, tag, blob_id, digest, content_type_top, content_type_s
 pub async fn find_by_repository_and_tag(
     transaction: &mut Transaction<'_, DB>,
     repository: &str,
-    tag: Option<&str>,
+    tag: &str,
 ) -> RegistryResult<Option<Manifest>> {
     Ok(sqlx::query_as!(
         Manifest,
         r#"
-SELECT id, repository, tag, blob_id, digest, content_type_top, content_type_sub, created_at
-FROM manifest
-WHERE repository = $1 AND tag = $2
+SELECT m.id, m.repository, m.blob_id, m.digest, m.content_type_top, m.content_type_sub, m.created_at
+FROM manifest m
+JOIN tag t ON t.manifest_id = m.id
+WHERE t.repository = $1 AND t.name = $2
     "#,
         repository,
         tag
@@ -51,7 +52,7 @@ WHERE repository = $1 AND tag = $2
     .await?)
 }
 
-pub async fn find_first_by_repository_and_digest(
+pub async fn find_by_repository_and_digest(
     transaction: &mut Transaction<'_, DB>,
     repository: &str,
     digest: &str,
@@ -59,9 +60,9 @@ pub async fn find_first_by_repository_and_digest(
     Ok(sqlx::query_as!(
         Manifest,
         r#"
-SELECT m.id, m.repository, m.tag, m.blob_id, m.digest, m.content_type_top, m.content_type_sub, m.created_at
-FROM manifest m
-WHERE m.repository = $1 AND m.digest = $2
+SELECT id, repository, blob_id, digest, content_type_top, content_type_sub, created_at
+FROM manifest
+WHERE repository = $1 AND digest = $2
         "#,
         repository,
         digest
@@ -70,123 +71,75 @@ WHERE m.repository = $1 AND m.digest = $2
     .await?)
 }
 
-pub async fn find_by_repository_and_digest(
-    transaction: &mut Transaction<'_, DB>,
-    repository: &str,
-    digest: &str,
-) -> RegistryResult<Vec<Manifest>> {
-    Ok(sqlx::query_as!(
-        Manifest,
-        r#"
-SELECT m.id, m.repository, m.tag, m.blob_id, m.digest, m.content_type_top, m.content_type_sub, m.created_at
-FROM manifest m
-WHERE m.repository = $1 AND m.digest = $2
-        "#,
-        repository,
-        digest
-    )
-    .fetch_all(&mut **transaction)
-    .await?)
-}
-
-pub async fn find_all_by_repository(
+pub async fn find_all_by_blob_id_and_repository(
     transaction: &mut Transaction<'_, DB>,
     repository: &str,
+    blob_id: Uuid,
 ) -> RegistryResult<Vec<Manifest>> {
     Ok(sqlx::query_as!(
         Manifest,
         r#"
-SELECT id, repository, tag, blob_id, digest, content_type_top, content_type_sub, created_at
+SELECT id, repository, blob_id, digest, content_type_top, content_type_sub, created_at
 FROM manifest
-WHERE repository = $1
-ORDER BY tag ASC
+WHERE blob_id = $1 AND repository = $2
         "#,
+        blob_id,
         repository
     )
     .fetch_all(&mut **transaction)
     .await?)
 }
 
-pub async fn find_all_by_repository_max(
-    transaction: &mut Transaction<'_, DB>,
-    repository: &str,
-    n: i64,
-) -> RegistryResult<Vec<Manifest>> {
-    Ok(sqlx::query_as!(
-        Manifest,
-        r#"
-SELECT id, repository, tag, blob_id, digest, content_type_top, content_type_sub, created_at
-FROM manifest
-WHERE repository = $1
-ORDER BY tag ASC
-LIMIT $2
-        "#,
-        repository,
-        n
-    )
-    .fetch_all(&mut **transaction)
-    .await?)
-}
-
-pub async fn find_all_by_repository_last(
-    transaction: &mut Transaction<'_, DB>,
-    repository: &str,
-    last: &str,
-) -> RegistryResult<Vec<Manifest>> {
-    Ok(sqlx::query_as!(
-        Manifest,
-        r#"
-SELECT id, repository, tag, blob_id, digest, content_type_top, content_type_sub, created_at
-FROM manifest
-WHERE repository = $1 AND tag > $2
-ORDER BY tag ASC
-        "#,
-        repository,
-        last,
-    )
-    .fetch_all(&mut **transaction)
-    .await?)
-}
-
-pub async fn find_all_by_repository_last_max(
+/// Finds manifests older than `grace_period` that can't be reached from any tag, either directly,
+/// as a manifest listed by a reachable index or as a referrer of a reachable manifest.
+pub async fn find_all_unreachable(
     transaction: &mut Transaction<'_, DB>,
-    repository: &str,
-    last: &str,
-    n: i64,
+    grace_period: Duration,
 ) -> RegistryResult<Vec<Manifest>> {
     Ok(sqlx::query_as!(
         Manifest,
         r#"
-SELECT id, repository, tag, blob_id, digest, content_type_top, content_type_sub, created_at
-FROM manifest
-WHERE repository = $1 AND tag > $2
-ORDER BY tag ASC
-LIMIT $3
-        "#,
-        repository,
-        last,
-        n
+WITH RECURSIVE edge(parent_id, child_id) AS (
+    SELECT index_id, child_id
+    FROM manifest_child
+    UNION ALL
+    SELECT subject.id, mr.manifest_id
+    FROM manifest_referrer mr
+    JOIN manifest referrer ON referrer.id = mr.manifest_id
+    JOIN manifest subject
+        ON subject.repository = referrer.repository AND subject.digest = mr.subject_digest
+),
+reachable(id) AS (
+    SELECT manifest_id
+    FROM tag
+    UNION
+    SELECT e.child_id
+    FROM edge e
+    JOIN reachable r ON e.parent_id = r.id
+)
+SELECT m.id, m.repository, m.blob_id, m.digest, m.content_type_top, m.content_type_sub, m.created_at
+FROM manifest m
+WHERE m.created_at < now() - make_interval(secs => $1)
+    AND m.id NOT IN (SELECT id FROM reachable)
+    "#,
+        grace_period.as_secs_f64()
     )
     .fetch_all(&mut **transaction)
     .await?)
 }
 
-pub async fn find_all_by_blob_id_and_repository(
+/// Manifest files are shared by every repository that holds a manifest with the same digest.
+pub async fn exists_by_digest(
     transaction: &mut Transaction<'_, DB>,
-    repository: &str,
-    blob_id: Uuid,
-) -> RegistryResult<Vec<Manifest>> {
-    Ok(sqlx::query_as!(
-        Manifest,
+    digest: &str,
+) -> RegistryResult<bool> {
+    Ok(sqlx::query_scalar!(
         r#"
-SELECT id, repository, tag, blob_id, digest, content_type_top, content_type_sub, created_at
-FROM manifest
-WHERE blob_id = $1 AND repository = $2
-        "#,
-        blob_id,
-        repository
+SELECT EXISTS(SELECT 1 FROM manifest WHERE digest = $1) AS "exists!"
+    "#,
+        digest
     )
-    .fetch_all(&mut **transaction)
+    .fetch_one(&mut **transaction)
     .await?)
 }
 
@@ -208,24 +161,3 @@ WHERE id = $1
 
     Ok(())
 }
-
-pub async fn delete_tag(
-    transaction: &mut Transaction<'_, DB>,
-    name: &str,
-    tag: &str,
-) -> RegistryResult<()> {
-    sqlx::query_as!(
-        Manifest,
-        r#"
-UPDATE manifest
-SET tag = NULL
-WHERE repository = $1 AND tag = $2
-        "#,
-        name,
-        tag
-    )
-    .execute(&mut **transaction)
-    .await?;
-
-    Ok(())
-}
@@ -2,11 +2,18 @@ use sqlx::{Pool, Postgres, Transaction};
 
 use crate::registry_error::{RegistryError, RegistryResult};
 
+pub mod auth_identity_repository;
 pub mod blob_repository;
+pub mod local_user_repository;
+pub mod manifest_child_repository;
 pub mod manifest_layer_repository;
+pub mod manifest_referrer_repository;
 pub mod manifest_repository;
+pub mod notification_endpoint_repository;
+pub mod notification_repository;
 pub mod owner_repository;
 pub mod repository_repository;
+pub mod tag_repository;
 pub mod upload_session_repository;
 
 pub type DB = Postgres;
@@ -20,3 +27,16 @@ pub async fn new_transaction(db_pool: &Pool<DB>) -> RegistryResult<Transaction<'
         }
     }
 }
+
+/// Serializes changes to the stored file of a digest between uploads and the garbage collector.
+/// The lock is held until the transaction ends.
+pub async fn lock_digest(
+    transaction: &mut Transaction<'_, DB>,
+    digest: &str,
+) -> RegistryResult<()> {
+    sqlx::query!("SELECT pg_advisory_xact_lock(hashtext($1))", digest)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{models::notification_endpoint::NotificationEndpoint, registry_error::RegistryResult};
+
+use super::DB;
+
+/// Adds the endpoint to the repository, adding an endpoint that already exists returns it.
+pub async fn upsert(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    url: &str,
+) -> RegistryResult<NotificationEndpoint> {
+    Ok(sqlx::query_as!(
+        NotificationEndpoint,
+        r#"
+INSERT INTO notification_endpoint(repository, url)
+VALUES                           ($1,         $2)
+ON CONFLICT (repository, url) DO UPDATE
+SET url = $2
+RETURNING id, repository, url, created_at
+        "#,
+        repository,
+        url
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
+
+pub async fn find_all_by_repository(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+) -> RegistryResult<Vec<NotificationEndpoint>> {
+    Ok(sqlx::query_as!(
+        NotificationEndpoint,
+        r#"
+SELECT id, repository, url, created_at
+FROM notification_endpoint
+WHERE repository = $1
+ORDER BY created_at ASC
+        "#,
+        repository
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+/// Returns whether an endpoint was deleted.
+pub async fn delete_by_repository_and_id(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    id: Uuid,
+) -> RegistryResult<bool> {
+    let result = sqlx::query!(
+        r#"
+DELETE
+FROM notification_endpoint
+WHERE repository = $1 AND id = $2
+        "#,
+        repository,
+        id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
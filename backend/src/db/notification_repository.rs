@@ -0,0 +1,93 @@
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{models::notification::Notification, registry_error::RegistryResult};
+
+use super::DB;
+
+pub async fn insert(
+    transaction: &mut Transaction<'_, DB>,
+    endpoint: &str,
+    payload: &serde_json::Value,
+) -> RegistryResult<Notification> {
+    Ok(sqlx::query_as!(
+        Notification,
+        r#"
+INSERT INTO notification(endpoint, payload)
+VALUES                  ($1,       $2)
+RETURNING id, endpoint, payload, attempts, next_attempt_at, last_error, created_at
+        "#,
+        endpoint,
+        payload
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
+
+/// Takes up to `limit` notifications that are due, oldest first, and postpones them by
+/// `lease_seconds` so that no other delivery picks them up in the meantime. Notifications whose
+/// delivery is interrupted are retried once the lease runs out.
+pub async fn claim_due(
+    transaction: &mut Transaction<'_, DB>,
+    limit: i64,
+    lease_seconds: f64,
+) -> RegistryResult<Vec<Notification>> {
+    Ok(sqlx::query_as!(
+        Notification,
+        r#"
+UPDATE notification
+SET next_attempt_at = now() + make_interval(secs => $2)
+WHERE id IN (
+    SELECT id
+    FROM notification
+    WHERE next_attempt_at <= now()
+    ORDER BY created_at ASC
+    LIMIT $1
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING id, endpoint, payload, attempts, next_attempt_at, last_error, created_at
+        "#,
+        limit,
+        lease_seconds
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+/// Records a failed delivery attempt, the notification is retried after `delay_seconds`.
+pub async fn set_failed_attempt(
+    transaction: &mut Transaction<'_, DB>,
+    id: Uuid,
+    delay_seconds: f64,
+    error: &str,
+) -> RegistryResult<()> {
+    sqlx::query!(
+        r#"
+UPDATE notification
+SET attempts = attempts + 1, next_attempt_at = now() + make_interval(secs => $2), last_error = $3
+WHERE id = $1
+        "#,
+        id,
+        delay_seconds,
+        error
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete(transaction: &mut Transaction<'_, DB>, id: Uuid) -> RegistryResult<()> {
+    sqlx::query!(
+        r#"
+DELETE
+FROM notification
+WHERE id = $1
+        "#,
+        id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
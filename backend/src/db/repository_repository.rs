@@ -18,7 +18,7 @@ pub async fn insert(
         r#"
 INSERT INTO repository(owner, namespace_name)
 VALUES                ($1,    $2)
-RETURNING id, owner, namespace_name, created_at
+RETURNING id, owner, namespace_name, is_public, created_at
         "#,
         owner,
         namespace
@@ -34,7 +34,7 @@ pub async fn find_by_name(
     Ok(sqlx::query_as!(
         Repository,
         r#"
-SELECT id, owner, namespace_name, created_at
+SELECT id, owner, namespace_name, is_public, created_at
 FROM repository
 WHERE namespace_name = $1
         "#,
@@ -44,11 +44,28 @@ WHERE namespace_name = $1
     .await?)
 }
 
+pub async fn find_optional_by_name(
+    transaction: &mut Transaction<'_, DB>,
+    namespace: &str,
+) -> RegistryResult<Option<Repository>> {
+    Ok(sqlx::query_as!(
+        Repository,
+        r#"
+SELECT id, owner, namespace_name, is_public, created_at
+FROM repository
+WHERE namespace_name = $1
+        "#,
+        namespace
+    )
+    .fetch_optional(&mut **transaction)
+    .await?)
+}
+
 pub async fn get_all(transaction: &mut Transaction<'_, DB>) -> RegistryResult<Vec<Repository>> {
     Ok(sqlx::query_as!(
         Repository,
         r#"
-SELECT id, owner, namespace_name, created_at
+SELECT id, owner, namespace_name, is_public, created_at
 FROM repository
         "#
     )
@@ -56,17 +73,74 @@ FROM repository
     .await?)
 }
 
-pub async fn find_all_with_owners(
+/// Finds the public repositories and the ones owned by `username`.
+pub async fn find_all_visible_with_owners(
     transaction: &mut Transaction<'_, DB>,
+    username: Option<&str>,
 ) -> RegistryResult<Vec<ViewableRepository>> {
     Ok(sqlx::query_as!(
         ViewableRepository,
         r#"
-SELECT r.namespace_name, r.created_at, o.username
+SELECT r.namespace_name, r.is_public, r.created_at, o.username
 FROM repository r
 JOIN owner o ON o.id = r.owner
-        "#
+WHERE r.is_public OR o.username = $1
+        "#,
+        username
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+/// Lists the names of the public repositories and the ones owned by `username` or proxied in
+/// `proxy_namespace` in lexical order, starting after `last` and limited to `limit` names.
+/// Anonymous users only see the public ones.
+pub async fn find_names_page(
+    transaction: &mut Transaction<'_, DB>,
+    username: Option<&str>,
+    proxy_namespace: Option<&str>,
+    last: Option<&str>,
+    limit: Option<i64>,
+) -> RegistryResult<Vec<String>> {
+    Ok(sqlx::query_scalar!(
+        r#"
+SELECT r.namespace_name
+FROM repository r
+JOIN owner o ON o.id = r.owner
+WHERE (
+    r.is_public
+    OR o.username = $1
+    OR ($1::TEXT IS NOT NULL AND starts_with(r.namespace_name, $2 || '/'))
+)
+  AND ($3::TEXT IS NULL OR r.namespace_name COLLATE "C" > $3 COLLATE "C")
+ORDER BY r.namespace_name COLLATE "C" ASC
+LIMIT $4
+        "#,
+        username,
+        proxy_namespace,
+        last,
+        limit
     )
     .fetch_all(&mut **transaction)
     .await?)
 }
+
+pub async fn update_is_public(
+    transaction: &mut Transaction<'_, DB>,
+    namespace: &str,
+    is_public: bool,
+) -> RegistryResult<()> {
+    sqlx::query!(
+        r#"
+UPDATE repository
+SET is_public = $2
+WHERE namespace_name = $1
+        "#,
+        namespace,
+        is_public
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{models::tag::Tag, registry_error::RegistryResult};
+
+use super::DB;
+
+/// Points the tag at the manifest, creating the tag if it doesn't exist yet.
+pub async fn upsert(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    name: &str,
+    manifest_id: Uuid,
+) -> RegistryResult<Tag> {
+    Ok(sqlx::query_as!(
+        Tag,
+        r#"
+INSERT INTO tag(repository, name, manifest_id)
+VALUES         ($1,         $2,   $3)
+ON CONFLICT (repository, name) DO UPDATE
+SET manifest_id = $3, updated_at = now()
+RETURNING id, repository, name, manifest_id, created_at, updated_at
+        "#,
+        repository,
+        name,
+        manifest_id
+    )
+    .fetch_one(&mut **transaction)
+    .await?)
+}
+
+pub async fn find_by_repository_and_name(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    name: &str,
+) -> RegistryResult<Option<Tag>> {
+    Ok(sqlx::query_as!(
+        Tag,
+        r#"
+SELECT id, repository, name, manifest_id, created_at, updated_at
+FROM tag
+WHERE repository = $1 AND name = $2
+        "#,
+        repository,
+        name
+    )
+    .fetch_optional(&mut **transaction)
+    .await?)
+}
+
+pub async fn find_all_by_repository(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+) -> RegistryResult<Vec<Tag>> {
+    Ok(sqlx::query_as!(
+        Tag,
+        r#"
+SELECT id, repository, name, manifest_id, created_at, updated_at
+FROM tag
+WHERE repository = $1
+ORDER BY name ASC
+        "#,
+        repository
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+/// Lists tag names in lexical order, starting after `last` and limited to `limit` names.
+pub async fn find_names_page(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    last: Option<&str>,
+    limit: Option<i64>,
+) -> RegistryResult<Vec<String>> {
+    Ok(sqlx::query_scalar!(
+        r#"
+SELECT name
+FROM tag
+WHERE repository = $1 AND ($2::TEXT IS NULL OR name > $2)
+ORDER BY name ASC
+LIMIT $3
+        "#,
+        repository,
+        last,
+        limit
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+/// Returns whether a tag was deleted.
+pub async fn delete_by_repository_and_name(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    name: &str,
+) -> RegistryResult<bool> {
+    let result = sqlx::query!(
+        r#"
+DELETE
+FROM tag
+WHERE repository = $1 AND name = $2
+        "#,
+        repository,
+        name
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_all_for_manifest(
+    transaction: &mut Transaction<'_, DB>,
+    manifest_id: Uuid,
+) -> RegistryResult<()> {
+    sqlx::query!(
+        r#"
+DELETE
+FROM tag
+WHERE manifest_id = $1
+        "#,
+        manifest_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
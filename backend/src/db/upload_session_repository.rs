@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use sqlx::Transaction;
 use uuid::Uuid;
 
@@ -8,7 +10,7 @@ use super::DB;
 pub async fn insert(
     transaction: &mut Transaction<'_, DB>,
     prev_session: Option<Uuid>,
-    starting_byte_index: i32,
+    starting_byte_index: i64,
     repository: &str,
 ) -> RegistryResult<UploadSession> {
     Ok(sqlx::query_as!(
@@ -47,6 +49,45 @@ WHERE id = $2
     Ok(())
 }
 
+/// Saves the SHA-256 state of the upload up to the last complete block before the session's
+/// starting byte.
+pub async fn set_sha256_state(
+    transaction: &mut Transaction<'_, DB>,
+    id: Uuid,
+    state: &[u8],
+) -> RegistryResult<()> {
+    sqlx::query!(
+        r#"
+UPDATE upload_session
+SET sha256_state = $1
+WHERE id = $2
+        "#,
+        state,
+        id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn find_sha256_state(
+    transaction: &mut Transaction<'_, DB>,
+    id: Uuid,
+) -> RegistryResult<Option<Vec<u8>>> {
+    Ok(sqlx::query_scalar!(
+        r#"
+SELECT sha256_state
+FROM upload_session
+WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?
+    .flatten())
+}
+
 pub async fn set_finished(
     transaction: &mut Transaction<'_, DB>,
     session_id: Uuid,
@@ -105,3 +146,52 @@ WHERE previous_session = $1 AND repository = $2
     .fetch_optional(&mut **transaction)
     .await?)
 }
+
+/// Deletes every session in the chain that `session_id` is part of and returns their ids.
+pub async fn delete_chain(
+    transaction: &mut Transaction<'_, DB>,
+    repository: &str,
+    session_id: Uuid,
+) -> RegistryResult<Vec<Uuid>> {
+    Ok(sqlx::query_scalar!(
+        r#"
+WITH RECURSIVE chain(id, previous_session) AS (
+    SELECT id, previous_session
+    FROM upload_session
+    WHERE id = $1 AND repository = $2
+    UNION
+    SELECT s.id, s.previous_session
+    FROM upload_session s
+    JOIN chain c ON s.id = c.previous_session OR s.previous_session = c.id
+)
+DELETE
+FROM upload_session
+WHERE id IN (SELECT id FROM chain)
+RETURNING id
+    "#,
+        session_id,
+        repository
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
+
+/// Finds the latest session of every unfinished upload that has not received data for `max_age`.
+pub async fn find_all_abandoned(
+    transaction: &mut Transaction<'_, DB>,
+    max_age: Duration,
+) -> RegistryResult<Vec<UploadSession>> {
+    Ok(sqlx::query_as!(
+        UploadSession,
+        r#"
+SELECT s.id, s.previous_session, s.starting_byte_index, s.digest, s.repository, s.created_at, s.is_finished
+FROM upload_session s
+WHERE NOT s.is_finished
+    AND s.created_at < now() - make_interval(secs => $1)
+    AND NOT EXISTS (SELECT 1 FROM upload_session n WHERE n.previous_session = s.id)
+    "#,
+        max_age.as_secs_f64()
+    )
+    .fetch_all(&mut **transaction)
+    .await?)
+}
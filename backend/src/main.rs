@@ -2,26 +2,45 @@
 
 use std::str::FromStr;
 
-use api::container_spec::AuthFailure;
+use api::container_spec::{
+    errors::{OCIError, OCIErrorResponse},
+    name_rewrite::RepositoryNameRewrite,
+    AuthFailure,
+};
+use auth_provider::AuthProviders;
 use config::Config;
-use rocket::{fs::FileServer, Request};
+use db::DB;
+use notification_dispatcher::NotificationDispatcher;
+use rocket::{fs::FileServer, http::Status, Request};
 use rocket_dyn_templates::Template;
+use services::{
+    garbage_collection_service::{self, GarbageCollectionOptions},
+    local_user_service,
+};
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
-    ConnectOptions,
+    ConnectOptions, Pool,
 };
+use storage::Storage;
+use upload_session_sweeper::UploadSessionSweeper;
+use upstream_registry::Proxy;
 
 #[macro_use]
 extern crate rocket;
 
 pub mod api;
+pub mod auth_provider;
 pub mod config;
 pub mod db;
 pub mod debug_headers;
 pub mod models;
+pub mod notification_dispatcher;
 pub mod registry_error;
 pub mod services;
+pub mod storage;
 pub mod types;
+pub mod upload_session_sweeper;
+pub mod upstream_registry;
 
 #[launch]
 async fn rocket() -> _ {
@@ -46,6 +65,28 @@ async fn rocket() -> _ {
         .await
         .expect("Failed to run migrations");
 
+    let storage = Storage::new(&config);
+    let proxy = Proxy::new(&config);
+
+    services::migrate_manifest_files_service::migrate_legacy_manifest_files(&config, &storage)
+        .await
+        .expect("Failed to migrate manifest files");
+    services::migrate_blob_files_service::migrate_blob_files(&db_pool, &config, &storage)
+        .await
+        .expect("Failed to migrate blob files");
+
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.first().is_some_and(|command| command == "gc") {
+        run_garbage_collection(&db_pool, &config, &storage, &args[1..]).await;
+    }
+    if args.first().is_some_and(|command| command == "users") {
+        run_user_management(&db_pool, &args[1..]).await;
+    }
+
+    // Commands don't authenticate anyone, providers that can't be set up only stop the server.
+    let auth_providers =
+        AuthProviders::new(&config, &db_pool).expect("Failed to set up auth providers");
+
     // TODO: avoid hardcoded URL
     // let docker = docker_api::Docker::new(config.docker_socket_url.clone())
     //    .expect("Failed to connect to docker");
@@ -55,24 +96,34 @@ async fn rocket() -> _ {
             "/",
             routes![
                 api::container_spec::blobs::read_blob::get_blob,
+                api::container_spec::blobs::read_blob::head_blob,
                 api::container_spec::get_spec_compliance,
+                api::container_spec::catalog::get_catalog,
                 api::container_spec::blobs::create_session::post_create_session,
                 api::container_spec::blobs::finalize_blob_upload::put_upload_blob,
                 api::container_spec::blobs::upload_blob_section::patch_upload_blob,
                 api::container_spec::blobs::upload_blob_monolithic::post_monolithic_upload,
                 api::container_spec::blobs::read_session::get_upload_session,
                 api::container_spec::blobs::delete_blob::delete_blob,
+                api::container_spec::blobs::delete_session::delete_upload_session,
                 api::container_spec::manifests::delete_manifest,
                 api::container_spec::manifests::put_manifest,
                 api::container_spec::manifests::get_manifest,
+                api::container_spec::manifests::head_manifest,
                 api::container_spec::tags::get_tags,
+                api::container_spec::referrers::get_referrers,
+                api::container_spec::token::get_token,
             ],
         )
         .mount(
             "/api",
             routes![
                 api::frontend::repositories::get_all_repositories,
-                api::frontend::repositories::get_repository
+                api::frontend::repositories::get_repository,
+                api::frontend::repositories::put_visibility,
+                api::frontend::webhooks::get_webhooks,
+                api::frontend::webhooks::post_webhook,
+                api::frontend::webhooks::delete_webhook,
             ],
         )
         // TODO: Auth
@@ -86,12 +137,113 @@ async fn rocket() -> _ {
         // )
         .mount("/public", FileServer::from("static/public"))
         .register("/", catchers![unauthorized_catcher])
+        .register("/v2", catchers![container_spec_catcher])
         .manage(db_pool)
         .manage(config)
+        .manage(storage)
+        .manage(proxy)
+        .manage(auth_providers)
         // .manage(docker)
+        .attach(RepositoryNameRewrite)
+        .attach(UploadSessionSweeper)
+        .attach(NotificationDispatcher)
         .attach(Template::fairing())
 }
 
+/// Runs the garbage collector instead of the server: `container-registry-rs gc [--dry-run]
+/// [--delete-untagged]`.
+async fn run_garbage_collection(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    storage: &Storage,
+    args: &[String],
+) -> ! {
+    let options = GarbageCollectionOptions {
+        dry_run: args.iter().any(|arg| arg == "--dry-run"),
+        delete_untagged: args.iter().any(|arg| arg == "--delete-untagged"),
+    };
+    let dry_run = options.dry_run;
+
+    let report = match garbage_collection_service::collect_garbage(
+        db_pool, config, storage, options,
+    )
+    .await
+    {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Garbage collection failed, err: {err:?}");
+            std::process::exit(1);
+        }
+    };
+
+    let action = if dry_run { "Would delete" } else { "Deleted" };
+    for manifest in report.manifests.iter() {
+        println!(
+            "{action} manifest {}@{}",
+            manifest.repository, manifest.digest
+        );
+    }
+    for blob in report.blobs.iter() {
+        println!("{action} blob {}@{}", blob.repository, blob.digest);
+    }
+    for digest in report.manifest_files.iter() {
+        println!("{action} manifest file {digest}");
+    }
+    for digest in report.blob_files.iter() {
+        println!("{action} blob file {digest}");
+    }
+    println!(
+        "{action} {} manifests, {} blobs, {} manifest files and {} blob files",
+        report.manifests.len(),
+        report.blobs.len(),
+        report.manifest_files.len(),
+        report.blob_files.len()
+    );
+
+    std::process::exit(0);
+}
+
+/// Manages the users of the local auth provider instead of running the server:
+/// `container-registry-rs users add <username>` reads the password from stdin,
+/// `container-registry-rs users import <htpasswd file>` imports the users of an htpasswd file.
+async fn run_user_management(db_pool: &Pool<DB>, args: &[String]) -> ! {
+    let result = match (args.first().map(String::as_str), args.get(1)) {
+        (Some("add"), Some(username)) => {
+            let mut password = String::new();
+            if let Err(err) = std::io::stdin().read_line(&mut password) {
+                eprintln!("Failed to read password, err: {err:?}");
+                std::process::exit(1);
+            }
+            let password = password.trim_end_matches(['\r', '\n']);
+
+            local_user_service::add_user(db_pool, username, password)
+                .await
+                .map(|_| println!("Saved user {username}"))
+        }
+        (Some("import"), Some(path)) => {
+            local_user_service::import_htpasswd(db_pool, std::path::Path::new(path))
+                .await
+                .map(|usernames| {
+                    for username in usernames.iter() {
+                        println!("Imported user {username}");
+                    }
+                    println!("Imported {} users", usernames.len());
+                })
+        }
+        _ => {
+            eprintln!("Usage: users add <username> | users import <htpasswd file>");
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("User management failed, err: {err:?}");
+        std::process::exit(1);
+    }
+
+    std::process::exit(0);
+}
+
 #[catch(401)]
 fn unauthorized_catcher(req: &Request) -> AuthFailure {
     let auth_failure_response: &AuthFailure = req.local_cache(|| {
@@ -101,3 +253,17 @@ fn unauthorized_catcher(req: &Request) -> AuthFailure {
 
     auth_failure_response.clone()
 }
+
+/// Requests rejected before reaching a handler (e.g. by a failing request guard) should still
+/// receive an error body that registry clients understand.
+#[catch(default)]
+fn container_spec_catcher(status: Status, req: &Request) -> Result<OCIErrorResponse, AuthFailure> {
+    let code = match status.code {
+        401 | 403 => return Err(unauthorized_catcher(req)),
+        404 | 405 => OCIError::Unsupported,
+        413 => OCIError::SizeInvalid,
+        _ => OCIError::Unknown,
+    };
+
+    Ok(OCIErrorResponse::with_status(code, status))
+}
@@ -0,0 +1,8 @@
+use sqlx::types::chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct AuthIdentity {
+    pub username: String,
+    pub provider: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -6,5 +6,7 @@ pub struct Blob {
     pub id: Uuid,
     pub repository: String,
     pub digest: String,
+    pub size: Option<i64>,
+    pub media_type: Option<String>,
     pub created_at: DateTime<Utc>,
 }
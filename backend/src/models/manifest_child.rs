@@ -0,0 +1,13 @@
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Link from an image index to one of the manifests it lists.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ManifestChild {
+    pub index_id: Uuid,
+    pub child_id: Uuid,
+    pub media_type: String,
+    pub size: i64,
+    pub platform: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
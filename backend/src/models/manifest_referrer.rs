@@ -0,0 +1,23 @@
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ManifestReferrer {
+    pub manifest_id: Uuid,
+    pub subject_digest: String,
+    pub media_type: String,
+    pub artifact_type: Option<String>,
+    pub size: i64,
+    pub annotations: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A manifest referring to a subject, together with the digest of the manifest itself.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Referrer {
+    pub digest: String,
+    pub media_type: String,
+    pub artifact_type: Option<String>,
+    pub size: i64,
+    pub annotations: Option<serde_json::Value>,
+}
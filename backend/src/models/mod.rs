@@ -1,6 +1,13 @@
+pub mod auth_identity;
 pub mod blob;
+pub mod local_user;
 pub mod manifest;
+pub mod manifest_child;
 pub mod manifest_layer;
+pub mod manifest_referrer;
+pub mod notification;
+pub mod notification_endpoint;
 pub mod owner;
 pub mod repository;
+pub mod tag;
 pub mod upload_session;
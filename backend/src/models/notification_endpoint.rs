@@ -0,0 +1,10 @@
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct NotificationEndpoint {
+    pub id: Uuid,
+    pub repository: String,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -6,12 +6,14 @@ pub struct Repository {
     pub id: Uuid,
     pub owner: Uuid,
     pub namespace_name: String,
+    pub is_public: bool,
     pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct ViewableRepository {
     pub namespace_name: String,
+    pub is_public: bool,
     pub created_at: DateTime<Utc>,
     pub username: String,
 }
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    tokio::{self, time::MissedTickBehavior},
+    Orbit, Rocket,
+};
+use sqlx::Pool;
+
+use crate::{config::Config, db::DB, services::notification_service};
+
+/// How often the queue is checked for notifications that are due.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delivers queued registry events to their endpoints. The queue lives in the database, so events
+/// that could not be delivered before a restart are delivered afterwards.
+pub struct NotificationDispatcher;
+
+#[rocket::async_trait]
+impl Fairing for NotificationDispatcher {
+    fn info(&self) -> Info {
+        Info {
+            name: "Notification dispatcher",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(db_pool), Some(config)) = (rocket.state::<Pool<DB>>(), rocket.state::<Config>())
+        else {
+            error!("Notification dispatcher is missing the database pool or config!");
+            return;
+        };
+
+        let db_pool = db_pool.clone();
+        let config = config.clone();
+        let client = match notification_service::new_client() {
+            Ok(client) => client,
+            Err(err) => {
+                error!("Failed to build the notification client, err: {err:?}");
+                return;
+            }
+        };
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+
+                match notification_service::deliver_due_notifications(&db_pool, &config, &client)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(delivered) => info!("Delivered {delivered} notifications"),
+                    Err(err) => error!("Failed to deliver notifications, err: {err:?}"),
+                }
+            }
+        });
+    }
+}
@@ -4,11 +4,9 @@ use std::io;
 pub enum RegistryError {
     #[error("Sqlx error")]
     SqlxError(#[from] sqlx::Error),
-    #[error("Rocket error")]
-    RocketError(#[from] rocket::Error),
     #[error("IO Error")]
     IOError(#[from] io::Error),
-    #[error("Session with was not found")]
+    #[error("Upload session was not found")]
     SessionNotFound,
     #[error("Invalid content length")]
     InvalidContentLength,
@@ -20,9 +18,9 @@ pub enum RegistryError {
     UnsupportedManifestType,
     #[error("Invalid digest")]
     InvalidDigest,
-    #[error("Invalid manifest schema")]
+    #[error("Invalid manifest schema: {0}")]
     InvalidManifestSchema(String),
-    #[error("Serde json error")]
+    #[error("Serde json error: {0}")]
     SerdeJsonError(#[from] serde_json::Error),
     #[error("Invalid content range")]
     InvalidContentRange,
@@ -40,10 +38,50 @@ pub enum RegistryError {
     ManifestNotFound,
     #[error("Manifest file not found")]
     ManifestFileNotFound,
+    #[error("Manifest file does not match its digest `{0}`")]
+    ManifestFileCorrupted(String),
+    #[error("Manifest of type `{0}` is not accepted by the client")]
+    ManifestNotAcceptable(String),
     #[error("Manifest still references blob")]
     BlobManifestStillExists,
+    #[error("Manifest is still referenced by an index")]
+    ManifestStillReferenced,
+    #[error("Manifest references unknown manifests or blobs {}", .0.join(", "))]
+    ManifestBlobUnknown(Vec<String>),
+    #[error("Size of `{0}` does not match the size declared in the manifest")]
+    ManifestBlobSizeMismatch(String),
     #[error("Failed to delete tag")]
     FailedToDeleteTag,
+    #[error("Blob exceeds the upload size limit")]
+    BlobTooLarge,
+    #[error("Storage error: {0}")]
+    StorageError(String),
+    #[error("Upstream registry error: {0}")]
+    UpstreamError(String),
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+    #[error("Accounts service error: {0}")]
+    AccountsServiceError(String),
+    #[error("Repository not found")]
+    RepositoryNotFound,
+    #[error("Only the owner of the repository may do this")]
+    NotRepositoryOwner,
+    #[error("Requested access `{0}` is denied")]
+    AccessDenied(String),
+    #[error("Invalid username `{0}`")]
+    InvalidUsername(String),
+    #[error("Invalid htpasswd entry on line {0}")]
+    InvalidHtpasswdEntry(usize),
+    #[error("Password hash error: {0}")]
+    PasswordHashError(String),
+    #[error("Invalid notification endpoint `{0}`")]
+    InvalidNotificationEndpoint(String),
+    #[error("Invalid repository name `{0}`")]
+    InvalidName(String),
+    #[error("Invalid tag `{0}`")]
+    InvalidTag(String),
+    #[error("The requested range can't be satisfied")]
+    RangeNotSatisfiable,
 }
 
 pub type RegistryResult<T> = Result<T, RegistryError>;
@@ -0,0 +1,25 @@
+use sqlx::Pool;
+
+use crate::{
+    db::{self, auth_identity_repository, DB},
+    registry_error::RegistryResult,
+};
+
+/// Returns whether `username` belongs to `provider`. A username that no provider has
+/// authenticated yet is bound to this one, every other provider is rejected for it from then on.
+pub async fn bind(db_pool: &Pool<DB>, provider: &str, username: &str) -> RegistryResult<bool> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+    let identity =
+        auth_identity_repository::insert_or_find(&mut transaction, username, provider).await?;
+    transaction.commit().await?;
+
+    if identity.provider != provider {
+        warn!(
+            "Rejecting {username} from {provider}, the username belongs to {}",
+            identity.provider
+        );
+        return Ok(false);
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,147 @@
+use sqlx::{Pool, Transaction};
+
+use crate::{
+    db::{self, owner_repository, repository_repository, DB},
+    registry_error::{RegistryError, RegistryResult},
+    types::resource_scope::{
+        ResourceScope, ALL_ACTIONS, CATALOG_RESOURCE_NAME, DELETE_ACTION, PULL_ACTION, PUSH_ACTION,
+        REGISTRY_RESOURCE_TYPE, REPOSITORY_RESOURCE_TYPE,
+    },
+    upstream_registry::Proxy,
+};
+
+/// The actions `username` may perform on the repository `namespace`, anonymous users have no
+/// username:
+/// - the owner of a repository may pull, push and delete
+/// - public repositories can be pulled by everyone, including anonymous users
+/// - repositories that don't exist yet can be created by any user, whoever pushes first owns them
+/// - proxied repositories can be pulled by every user but are only ever written by the proxy
+pub async fn permitted_actions(
+    db_pool: &Pool<DB>,
+    proxy: &Proxy,
+    username: Option<&str>,
+    namespace: &str,
+) -> RegistryResult<Vec<&'static str>> {
+    if proxy.upstream_for(namespace).is_some() {
+        return Ok(match username {
+            Some(_) => vec![PULL_ACTION],
+            None => vec![],
+        });
+    }
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    let Some(repository) =
+        repository_repository::find_optional_by_name(&mut transaction, namespace).await?
+    else {
+        return Ok(match username {
+            Some(_) => vec![PULL_ACTION, PUSH_ACTION, DELETE_ACTION],
+            None => vec![],
+        });
+    };
+    let owner = owner_repository::find_by_id(&mut transaction, repository.owner).await?;
+
+    transaction.commit().await?;
+
+    if username == Some(owner.username.as_str()) {
+        Ok(vec![PULL_ACTION, PUSH_ACTION, DELETE_ACTION])
+    } else if repository.is_public {
+        Ok(vec![PULL_ACTION])
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// The part of a requested token scope that `username` is permitted, or `None` if nothing of it
+/// is:
+/// - on a repository, the requested actions that [`permitted_actions`] allows, `*` requests all
+///   of them
+/// - the catalog only lists the repositories the caller may see, anyone can get it
+/// - other resources are never granted
+pub async fn permitted_scope(
+    db_pool: &Pool<DB>,
+    proxy: &Proxy,
+    username: Option<&str>,
+    requested: &ResourceScope,
+) -> RegistryResult<Option<ResourceScope>> {
+    let permitted = match requested.resource_type.as_str() {
+        REPOSITORY_RESOURCE_TYPE => {
+            permitted_actions(db_pool, proxy, username, &requested.name).await?
+        }
+        REGISTRY_RESOURCE_TYPE if requested.name == CATALOG_RESOURCE_NAME => vec![ALL_ACTIONS],
+        _ => vec![],
+    };
+
+    let actions = permitted
+        .into_iter()
+        .filter(|permitted| {
+            requested
+                .actions
+                .iter()
+                .any(|action| action == permitted || action == ALL_ACTIONS)
+        })
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    if actions.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(ResourceScope {
+        actions,
+        ..requested.clone()
+    }))
+}
+
+/// Checks that `username` may perform every action of a repository `scope`, other resources are
+/// not tied to a repository and only require a token that grants them.
+pub async fn authorize(
+    db_pool: &Pool<DB>,
+    proxy: &Proxy,
+    username: Option<&str>,
+    scope: &ResourceScope,
+) -> RegistryResult<()> {
+    if scope.resource_type != REPOSITORY_RESOURCE_TYPE {
+        return Ok(());
+    }
+
+    let permitted = permitted_actions(db_pool, proxy, username, &scope.name).await?;
+    let denied = scope.actions.iter().any(|action| {
+        action == ALL_ACTIONS || !permitted.iter().any(|permitted| permitted == action)
+    });
+
+    if denied {
+        warn!(
+            "{} is not allowed to access {scope}",
+            username.unwrap_or("Anonymous user")
+        );
+        return Err(RegistryError::AccessDenied(scope.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Settings of a repository, such as its webhooks and visibility, can only be managed by its
+/// owner.
+pub async fn verify_owner(
+    transaction: &mut Transaction<'_, DB>,
+    username: &str,
+    repository: &str,
+) -> RegistryResult<()> {
+    let Some(repository) =
+        repository_repository::find_optional_by_name(transaction, repository).await?
+    else {
+        return Err(RegistryError::RepositoryNotFound);
+    };
+
+    let owner = owner_repository::find_by_id(transaction, repository.owner).await?;
+    if owner.username != username {
+        warn!(
+            "{username} is not the owner of {}",
+            repository.namespace_name
+        );
+        return Err(RegistryError::NotRepositoryOwner);
+    }
+
+    Ok(())
+}
@@ -1,25 +1,23 @@
-use std::fs;
-
 use sqlx::Pool;
 
 use crate::{
-    config::Config,
     db::{self, blob_repository, manifest_repository, DB},
     registry_error::{RegistryError, RegistryResult},
+    storage::{blob_path, Storage},
+    types::digest::Digest,
 };
 
-use super::upload_blob_service::get_blob_file_path;
-
 pub async fn delete_blob(
     db_pool: &Pool<DB>,
-    config: &Config,
+    storage: &Storage,
     name: &str,
-    digest: &str,
+    digest: &Digest,
 ) -> RegistryResult<()> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
     let Some(blob) =
-        blob_repository::find_by_repository_and_digest(&mut transaction, name, digest).await?
+        blob_repository::find_by_repository_and_digest(&mut transaction, name, &digest.to_string())
+            .await?
     else {
         return Err(RegistryError::BlobNotFound);
     };
@@ -34,12 +32,13 @@ pub async fn delete_blob(
 
     blob_repository::delete_blob(&mut transaction, blob.id).await?;
 
+    db::lock_digest(&mut transaction, &digest.to_string()).await?;
     let remaining_references =
-        blob_repository::find_blobs_by_digest(&mut transaction, digest).await?;
+        blob_repository::find_blobs_by_digest(&mut transaction, &digest.to_string()).await?;
 
     if remaining_references.is_empty() {
         info!("Last reference to blob with digest {digest} remove, deleting file");
-        delete_blob_file(config, digest)?;
+        delete_blob_file(storage, digest).await?;
     }
 
     transaction.commit().await?;
@@ -47,20 +46,15 @@ pub async fn delete_blob(
     Ok(())
 }
 
-fn delete_blob_file(config: &Config, digest: &str) -> RegistryResult<()> {
-    let Some(digest) = digest.strip_prefix("sha256:") else {
-        error!("Digest did not start with `sha256:`? {digest}");
-        return Err(RegistryError::InvalidDigest);
-    };
-
-    let file_path = get_blob_file_path(config, digest);
-    if !file_path.exists() {
-        error!("blob file with digest {digest} did not exist at path {file_path:?}");
+async fn delete_blob_file(storage: &Storage, digest: &Digest) -> RegistryResult<()> {
+    let path = blob_path(digest);
+    if storage.stat(&path).await?.is_none() {
+        error!("blob file with digest {digest} did not exist at path {path}");
         return Err(RegistryError::BlobFileNotFound);
     }
 
-    fs::remove_file(file_path.as_path()).map_err(|err| {
-        error!("Failed to remove blob file at path {file_path:?} due to err: {err:?}");
+    storage.delete(&path).await.map_err(|err| {
+        error!("Failed to remove blob file at path {path} due to err: {err:?}");
         err
     })?;
 
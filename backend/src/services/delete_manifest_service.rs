@@ -1,65 +1,109 @@
-use std::fs;
-
 use sqlx::Pool;
-use uuid::Uuid;
 
 use crate::{
-    config::Config,
-    db::{self, manifest_layer_repository, manifest_repository, DB},
+    db::{
+        self, manifest_child_repository, manifest_layer_repository, manifest_referrer_repository,
+        manifest_repository, tag_repository, DB,
+    },
     registry_error::{RegistryError, RegistryResult},
+    storage::{manifest_path, Storage},
+    types::digest::Digest,
 };
 
-use super::upload_manifest_service::get_manifest_file_path;
-
 pub async fn delete_tag(db_pool: &Pool<DB>, name: &str, tag: &str) -> RegistryResult<()> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    if let Err(err) = manifest_repository::delete_tag(&mut transaction, name, tag).await {
-        warn!("Failed to set tag to null in {name} / {tag} due to err: {err:?}");
-        return Err(RegistryError::FailedToDeleteTag);
+    let deleted =
+        match tag_repository::delete_by_repository_and_name(&mut transaction, name, tag).await {
+            Ok(deleted) => deleted,
+            Err(err) => {
+                warn!("Failed to delete tag {name} / {tag} due to err: {err:?}");
+                return Err(RegistryError::FailedToDeleteTag);
+            }
+        };
+
+    if !deleted {
+        warn!("Tag not found in {name} / {tag}");
+        return Err(RegistryError::ManifestNotFound);
     }
 
+    transaction.commit().await?;
+
     Ok(())
 }
 
 pub async fn delete_manifest(
     db_pool: &Pool<DB>,
-    config: &Config,
+    storage: &Storage,
     name: &str,
-    digest: &str,
+    digest: &Digest,
 ) -> RegistryResult<()> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    let manifests =
-        manifest_repository::find_by_repository_and_digest(&mut transaction, name, digest).await?;
-
-    if manifests.is_empty() {
+    let Some(manifest) = manifest_repository::find_by_repository_and_digest(
+        &mut transaction,
+        name,
+        &digest.to_string(),
+    )
+    .await?
+    else {
         warn!("Manifest not found in {name} / {digest}");
         return Err(RegistryError::ManifestNotFound);
     };
 
-    for manifest in manifests.into_iter() {
-        manifest_layer_repository::delete_all_for_manifest(&mut transaction, manifest.id).await?;
+    let indexes =
+        manifest_child_repository::find_all_by_child(&mut transaction, manifest.id).await?;
+    if !indexes.is_empty() {
+        error!("There are still indexes that refer to this manifest that must be deleted first");
+        return Err(RegistryError::ManifestStillReferenced);
+    }
 
-        manifest_repository::delete_manifest(&mut transaction, manifest.id).await?;
+    tag_repository::delete_all_for_manifest(&mut transaction, manifest.id).await?;
+    manifest_child_repository::delete_all_for_index(&mut transaction, manifest.id).await?;
+    manifest_layer_repository::delete_all_for_manifest(&mut transaction, manifest.id).await?;
+    manifest_referrer_repository::delete_for_manifest(&mut transaction, manifest.id).await?;
 
-        delete_manifest_file(config, manifest.id)?;
-    }
+    manifest_repository::delete_manifest(&mut transaction, manifest.id).await?;
 
     transaction.commit().await?;
 
+    // The manifest is deleted once its rows are, a file left behind by a failure from here on is
+    // reclaimed by the garbage collector.
+    if let Err(err) = delete_unused_manifest_file(db_pool, storage, digest).await {
+        error!("Failed to delete the file of manifest {digest}, err: {err:?}");
+    }
+
     Ok(())
 }
 
-fn delete_manifest_file(config: &Config, manifest_id: Uuid) -> RegistryResult<()> {
-    let file_path = get_manifest_file_path(config, manifest_id);
+async fn delete_unused_manifest_file(
+    db_pool: &Pool<DB>,
+    storage: &Storage,
+    digest: &Digest,
+) -> RegistryResult<()> {
+    let mut transaction = db::new_transaction(db_pool).await?;
 
-    if !file_path.exists() {
-        error!("Manifest file for manifest {manifest_id} does not exist at path {file_path:?}!");
-        return Err(RegistryError::ManifestFileNotFound);
+    db::lock_digest(&mut transaction, &digest.to_string()).await?;
+    if manifest_repository::exists_by_digest(&mut transaction, &digest.to_string()).await? {
+        info!("Manifest {digest} is still used by another repository, keeping its file");
+    } else {
+        delete_manifest_file(storage, digest).await?;
     }
 
-    fs::remove_file(file_path)?;
+    transaction.commit().await?;
 
     Ok(())
 }
+
+/// A missing file is treated as already deleted, so a manifest whose file was lost can still be
+/// deleted.
+async fn delete_manifest_file(storage: &Storage, digest: &Digest) -> RegistryResult<()> {
+    let path = manifest_path(digest);
+
+    if storage.stat(&path).await?.is_none() {
+        warn!("Manifest file for manifest {digest} does not exist at path {path}");
+        return Ok(());
+    }
+
+    storage.delete(&path).await
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+use sqlx::Pool;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    db::{self, upload_session_repository, DB},
+    registry_error::RegistryResult,
+    types::session_id::SessionId,
+};
+
+use super::{
+    get_upload_session_service::retrieve_last_upload_session,
+    upload_blob_service::get_blob_upload_path,
+};
+
+/// Cancels an unfinished upload, removing every session of the upload and the uploaded data.
+pub async fn cancel_upload(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    namespace: &str,
+    session_id: SessionId,
+) -> RegistryResult<()> {
+    let latest = retrieve_last_upload_session(db_pool, namespace, session_id).await?;
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+    let session_ids =
+        upload_session_repository::delete_chain(&mut transaction, namespace, latest.id).await?;
+    transaction.commit().await?;
+
+    info!(
+        "Cancelled upload {} in {namespace} ({} sessions)",
+        latest.id,
+        session_ids.len()
+    );
+
+    delete_staging_files(config, &session_ids)
+}
+
+/// Removes uploads that have not received any data for longer than the configured maximum age.
+/// Returns the number of uploads that were expired.
+pub async fn expire_upload_sessions(db_pool: &Pool<DB>, config: &Config) -> RegistryResult<usize> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    let abandoned = upload_session_repository::find_all_abandoned(
+        &mut transaction,
+        config.upload_session_max_age,
+    )
+    .await?;
+
+    let mut session_ids = vec![];
+    for session in abandoned.iter() {
+        info!(
+            "Expiring upload session {} in {}, last updated at {}",
+            session.id, session.repository, session.created_at
+        );
+        session_ids.extend(
+            upload_session_repository::delete_chain(
+                &mut transaction,
+                &session.repository,
+                session.id,
+            )
+            .await?,
+        );
+    }
+
+    transaction.commit().await?;
+
+    delete_staging_files(config, &session_ids)?;
+
+    Ok(abandoned.len())
+}
+
+fn delete_staging_files(config: &Config, session_ids: &[Uuid]) -> RegistryResult<()> {
+    for session_id in session_ids.iter() {
+        let staging_path = get_blob_upload_path(config, *session_id)?;
+        if staging_path.exists() {
+            info!("Removing upload data at {staging_path:?}");
+            fs::remove_file(staging_path)?;
+        }
+    }
+
+    Ok(())
+}
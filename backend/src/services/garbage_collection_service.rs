@@ -0,0 +1,511 @@
+use std::time::SystemTime;
+
+use sqlx::{Pool, Transaction};
+
+use crate::{
+    config::Config,
+    db::{
+        self, blob_repository, manifest_child_repository, manifest_layer_repository,
+        manifest_referrer_repository, manifest_repository, DB,
+    },
+    models::{blob::Blob, manifest::Manifest},
+    registry_error::RegistryResult,
+    storage::{Storage, StorageMetadata, BLOBS_PREFIX, MANIFESTS_PREFIX},
+    types::digest::Digest,
+};
+
+pub struct GarbageCollectionOptions {
+    /// Only report what would be deleted.
+    pub dry_run: bool,
+    /// Also delete manifests that can't be reached from a tag.
+    pub delete_untagged: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct GarbageCollectionReport {
+    pub manifests: Vec<Manifest>,
+    pub blobs: Vec<Blob>,
+    pub manifest_files: Vec<Digest>,
+    pub blob_files: Vec<Digest>,
+}
+
+#[derive(Clone, Copy)]
+enum StoredContent {
+    Manifest,
+    Blob,
+}
+
+/// Deletes manifests and blobs that are no longer referenced, followed by the files that no
+/// longer belong to any manifest or blob.
+///
+/// Anything younger than the configured grace period is kept, as clients upload blobs before
+/// the manifest that refers to them. Files are only removed while holding the lock of their
+/// digest, which uploads take before storing a file, so content that is pushed again whilst the
+/// collector runs is never lost.
+pub async fn collect_garbage(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    storage: &Storage,
+    options: GarbageCollectionOptions,
+) -> RegistryResult<GarbageCollectionReport> {
+    let mut report = GarbageCollectionReport::default();
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    if options.delete_untagged {
+        report.manifests = delete_unreachable_manifests(&mut transaction, config).await?;
+    }
+
+    report.blobs =
+        blob_repository::delete_unreferenced(&mut transaction, config.gc_grace_period).await?;
+
+    if options.dry_run {
+        // The deletions above are visible to this transaction only, so it tells which files
+        // would be orphaned without changing anything.
+        report.manifest_files =
+            find_orphaned_files(&mut transaction, config, storage, StoredContent::Manifest).await?;
+        report.blob_files =
+            find_orphaned_files(&mut transaction, config, storage, StoredContent::Blob).await?;
+
+        transaction.rollback().await?;
+
+        return Ok(report);
+    }
+
+    transaction.commit().await?;
+
+    report.manifest_files =
+        delete_orphaned_files(db_pool, config, storage, StoredContent::Manifest).await?;
+    report.blob_files =
+        delete_orphaned_files(db_pool, config, storage, StoredContent::Blob).await?;
+
+    Ok(report)
+}
+
+async fn delete_unreachable_manifests(
+    transaction: &mut Transaction<'_, DB>,
+    config: &Config,
+) -> RegistryResult<Vec<Manifest>> {
+    let manifests =
+        manifest_repository::find_all_unreachable(transaction, config.gc_grace_period).await?;
+
+    for manifest in manifests.iter() {
+        info!(
+            "Deleting unreachable manifest {} in {}",
+            manifest.digest, manifest.repository
+        );
+        manifest_child_repository::delete_all_for_index(transaction, manifest.id).await?;
+        manifest_child_repository::delete_all_for_child(transaction, manifest.id).await?;
+        manifest_layer_repository::delete_all_for_manifest(transaction, manifest.id).await?;
+        manifest_referrer_repository::delete_for_manifest(transaction, manifest.id).await?;
+        manifest_repository::delete_manifest(transaction, manifest.id).await?;
+    }
+
+    Ok(manifests)
+}
+
+/// Returns the digests of the stored files that no longer belong to any row, without removing
+/// them.
+async fn find_orphaned_files(
+    transaction: &mut Transaction<'_, DB>,
+    config: &Config,
+    storage: &Storage,
+    content: StoredContent,
+) -> RegistryResult<Vec<Digest>> {
+    let mut orphaned = vec![];
+
+    for (digest, _, metadata) in stored_files(storage, content).await? {
+        if is_past_grace_period(&metadata, config)
+            && !is_stored_in_db(transaction, content, &digest).await?
+        {
+            orphaned.push(digest);
+        }
+    }
+
+    Ok(orphaned)
+}
+
+/// Removes the stored files that no longer belong to any row and returns their digests. Every
+/// file is checked and removed in a transaction of its own, so the lock of its digest is only
+/// held briefly.
+async fn delete_orphaned_files(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    storage: &Storage,
+    content: StoredContent,
+) -> RegistryResult<Vec<Digest>> {
+    let mut orphaned = vec![];
+
+    for (digest, path, metadata) in stored_files(storage, content).await? {
+        if !is_past_grace_period(&metadata, config) {
+            continue;
+        }
+
+        let mut transaction = db::new_transaction(db_pool).await?;
+        db::lock_digest(&mut transaction, &digest.to_string()).await?;
+
+        if !is_stored_in_db(&mut transaction, content, &digest).await? {
+            info!("Removing orphaned file {path}");
+            storage.delete(&path).await?;
+            orphaned.push(digest);
+        }
+
+        transaction.commit().await?;
+    }
+
+    Ok(orphaned)
+}
+
+async fn is_stored_in_db(
+    transaction: &mut Transaction<'_, DB>,
+    content: StoredContent,
+    digest: &Digest,
+) -> RegistryResult<bool> {
+    match content {
+        StoredContent::Manifest => {
+            manifest_repository::exists_by_digest(transaction, &digest.to_string()).await
+        }
+        StoredContent::Blob => {
+            blob_repository::exists_by_digest(transaction, &digest.to_string()).await
+        }
+    }
+}
+
+/// Lists the files below the content-addressed prefix of `content`. Anything that isn't named
+/// after a digest, such as a manifest that is still being written, is skipped.
+async fn stored_files(
+    storage: &Storage,
+    content: StoredContent,
+) -> RegistryResult<Vec<(Digest, String, StorageMetadata)>> {
+    let prefix = match content {
+        StoredContent::Manifest => MANIFESTS_PREFIX,
+        StoredContent::Blob => BLOBS_PREFIX,
+    };
+
+    let mut files = vec![];
+    for (path, metadata) in storage.list(prefix).await? {
+        let Some((algorithm, hex)) = path
+            .strip_prefix(prefix)
+            .and_then(|name| name.strip_prefix('/'))
+            .and_then(|name| name.split_once('/'))
+        else {
+            continue;
+        };
+
+        let Ok(digest) = Digest::parse(&format!("{algorithm}:{hex}")) else {
+            continue;
+        };
+
+        if path == digest.storage_path(prefix) {
+            files.push((digest, path, metadata));
+        }
+    }
+
+    Ok(files)
+}
+
+fn is_past_grace_period(metadata: &StorageMetadata, config: &Config) -> bool {
+    let age = SystemTime::now()
+        .duration_since(metadata.modified)
+        .unwrap_or_default();
+
+    age > config.gc_grace_period
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use rocket::http::ContentType;
+    use sqlx::postgres::PgPoolOptions;
+    use uuid::Uuid;
+
+    use crate::{
+        config::StorageConfig,
+        services::{upload_blob_service, upload_manifest_service},
+        storage::blob_path,
+        types::{digest::DigestAlgorithm, reference::Reference},
+    };
+
+    use super::*;
+
+    const IMAGE_MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+    const IMAGE_INDEX_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+    const CONFIG_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+    const LAYER_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+    struct Image {
+        digest: Digest,
+        size: usize,
+        blobs: Vec<Digest>,
+    }
+
+    struct TestRegistry {
+        db_pool: Pool<DB>,
+        config: Config,
+        storage: Storage,
+        namespace: String,
+    }
+
+    impl TestRegistry {
+        async fn push_blob(&self, content: &[u8]) -> Digest {
+            let digest = DigestAlgorithm::Sha256.digest(content);
+            upload_blob_service::import_blob(
+                &self.db_pool,
+                &self.config,
+                &self.storage,
+                "gc-test",
+                &self.namespace,
+                &digest,
+                Box::pin(std::io::Cursor::new(content.to_vec())),
+            )
+            .await
+            .unwrap();
+
+            digest
+        }
+
+        /// Pushes an image with a config and a layer blob of unique content.
+        async fn push_image(&self, subject: Option<&Image>) -> Image {
+            let config = format!(r#"{{"id":"{}"}}"#, Uuid::new_v4());
+            let layer = Uuid::new_v4().to_string();
+            let config_digest = self.push_blob(config.as_bytes()).await;
+            let layer_digest = self.push_blob(layer.as_bytes()).await;
+
+            let subject = subject
+                .map(|subject| {
+                    format!(
+                        r#","subject":{{"mediaType":"{IMAGE_MANIFEST_TYPE}","digest":"{}","size":{}}}"#,
+                        subject.digest, subject.size
+                    )
+                })
+                .unwrap_or_default();
+            let manifest = format!(
+                r#"{{"schemaVersion":2,"mediaType":"{IMAGE_MANIFEST_TYPE}","config":{{"mediaType":"{CONFIG_TYPE}","digest":"{config_digest}","size":{}}},"layers":[{{"mediaType":"{LAYER_TYPE}","digest":"{layer_digest}","size":{}}}]{subject}}}"#,
+                config.len(),
+                layer.len()
+            );
+
+            let digest = self
+                .push_manifest(IMAGE_MANIFEST_TYPE, None, manifest.as_bytes())
+                .await;
+            Image {
+                digest,
+                size: manifest.len(),
+                blobs: vec![config_digest, layer_digest],
+            }
+        }
+
+        async fn push_manifest(&self, media_type: &str, tag: Option<&str>, data: &[u8]) -> Digest {
+            let digest = DigestAlgorithm::Sha256.digest(data);
+            let reference = match tag {
+                Some(tag) => Reference::Tag(tag.to_string()),
+                None => Reference::Digest(digest.clone()),
+            };
+            let (top, sub) = media_type.split_once('/').unwrap();
+
+            upload_manifest_service::upload_manifest(
+                &self.db_pool,
+                &self.storage,
+                &self.namespace,
+                &reference,
+                &ContentType::new(top.to_string(), sub.to_string()),
+                data.to_vec(),
+            )
+            .await
+            .unwrap();
+
+            digest
+        }
+
+        /// Moves the rows of the test repository and the stored files past the grace period.
+        async fn age_content(&self) {
+            let created_at = "now() - interval '2 hours'";
+            for table in ["blob", "manifest"] {
+                sqlx::query(&format!(
+                    "UPDATE {table} SET created_at = {created_at} WHERE repository = $1"
+                ))
+                .bind(&self.namespace)
+                .execute(&self.db_pool)
+                .await
+                .unwrap();
+            }
+
+            age_files(
+                Path::new(&self.config.storage_directory),
+                SystemTime::now() - Duration::from_secs(2 * 60 * 60),
+            );
+        }
+
+        async fn blob_exists(&self, digest: &Digest) -> bool {
+            let mut transaction = db::new_transaction(&self.db_pool).await.unwrap();
+            blob_repository::find_by_repository_and_digest(
+                &mut transaction,
+                &self.namespace,
+                &digest.to_string(),
+            )
+            .await
+            .unwrap()
+            .is_some()
+        }
+
+        async fn manifest_exists(&self, digest: &Digest) -> bool {
+            let mut transaction = db::new_transaction(&self.db_pool).await.unwrap();
+            manifest_repository::find_by_repository_and_digest(
+                &mut transaction,
+                &self.namespace,
+                &digest.to_string(),
+            )
+            .await
+            .unwrap()
+            .is_some()
+        }
+
+        async fn file_exists(&self, path: &str) -> bool {
+            self.storage.stat(path).await.unwrap().is_some()
+        }
+    }
+
+    fn age_files(path: &Path, modified: SystemTime) {
+        for entry in std::fs::read_dir(path).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                age_files(&path, modified);
+            } else {
+                std::fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+            }
+        }
+    }
+
+    fn sorted<T: ToString>(items: impl IntoIterator<Item = T>) -> Vec<String> {
+        let mut items = items
+            .into_iter()
+            .map(|item| item.to_string())
+            .collect::<Vec<_>>();
+        items.sort();
+        items
+    }
+
+    /// Collects garbage in the database of `DATABASE_URL`, which also removes unreferenced
+    /// content of other repositories there. Run with
+    /// `cargo test -- --ignored collects_unreferenced_content`.
+    #[rocket::async_test]
+    #[ignore]
+    async fn collects_unreferenced_content() {
+        let directory = std::env::temp_dir().join(format!("gc-test-{}", Uuid::new_v4()));
+        let mut config = Config::new().unwrap();
+        config.storage = StorageConfig::Filesystem;
+        config.storage_directory = directory.to_string_lossy().to_string();
+        config.gc_grace_period = Duration::from_secs(60 * 60);
+
+        let registry = TestRegistry {
+            db_pool: PgPoolOptions::new()
+                .connect(&config.database_url)
+                .await
+                .unwrap(),
+            storage: Storage::new(&config),
+            namespace: format!("gc-test/app-{}", Uuid::new_v4().simple()),
+            config,
+        };
+
+        // A tagged index of two images, one of them with a referrer.
+        let amd64 = registry.push_image(None).await;
+        let arm64 = registry.push_image(None).await;
+        let referrer = registry.push_image(Some(&amd64)).await;
+        let index = format!(
+            r#"{{"schemaVersion":2,"mediaType":"{IMAGE_INDEX_TYPE}","manifests":[{{"mediaType":"{IMAGE_MANIFEST_TYPE}","digest":"{}","size":{}}},{{"mediaType":"{IMAGE_MANIFEST_TYPE}","digest":"{}","size":{}}}]}}"#,
+            amd64.digest, amd64.size, arm64.digest, arm64.size
+        );
+        let index = registry
+            .push_manifest(IMAGE_INDEX_TYPE, Some("latest"), index.as_bytes())
+            .await;
+
+        let untagged = registry.push_image(None).await;
+        let unreferenced_blob = registry.push_blob(Uuid::new_v4().as_bytes()).await;
+        let orphaned_file = DigestAlgorithm::Sha256.digest(Uuid::new_v4().as_bytes());
+        registry
+            .storage
+            .put(
+                &blob_path(&orphaned_file),
+                Uuid::new_v4().as_bytes().to_vec(),
+            )
+            .await
+            .unwrap();
+
+        registry.age_content().await;
+
+        // A blob that is being pushed isn't referenced by a manifest yet.
+        let pushed_blob = registry.push_blob(Uuid::new_v4().as_bytes()).await;
+
+        let expected_manifests = sorted([&untagged.digest]);
+        let expected_blobs = sorted(untagged.blobs.iter().chain([&unreferenced_blob]));
+        let expected_blob_files = sorted(expected_blobs.iter().chain([&orphaned_file.to_string()]));
+
+        for dry_run in [true, false] {
+            let report = collect_garbage(
+                &registry.db_pool,
+                &registry.config,
+                &registry.storage,
+                GarbageCollectionOptions {
+                    dry_run,
+                    delete_untagged: true,
+                },
+            )
+            .await
+            .unwrap();
+
+            let in_repository = |repository: &str| repository == registry.namespace;
+            assert_eq!(
+                sorted(
+                    report
+                        .manifests
+                        .iter()
+                        .filter(|manifest| in_repository(&manifest.repository))
+                        .map(|manifest| &manifest.digest)
+                ),
+                expected_manifests
+            );
+            assert_eq!(
+                sorted(
+                    report
+                        .blobs
+                        .iter()
+                        .filter(|blob| in_repository(&blob.repository))
+                        .map(|blob| &blob.digest)
+                ),
+                expected_blobs
+            );
+            assert_eq!(sorted(&report.manifest_files), expected_manifests);
+            assert_eq!(sorted(&report.blob_files), expected_blob_files);
+
+            // A dry run leaves everything in place.
+            assert_eq!(registry.manifest_exists(&untagged.digest).await, dry_run);
+            assert_eq!(registry.blob_exists(&unreferenced_blob).await, dry_run);
+            assert_eq!(
+                registry.file_exists(&blob_path(&orphaned_file)).await,
+                dry_run
+            );
+        }
+
+        assert!(registry.manifest_exists(&index).await);
+        for image in [&amd64, &arm64, &referrer] {
+            assert!(registry.manifest_exists(&image.digest).await);
+            for blob in image.blobs.iter() {
+                assert!(registry.blob_exists(blob).await);
+                assert!(registry.file_exists(&blob_path(blob)).await);
+            }
+        }
+        assert!(registry.blob_exists(&pushed_blob).await);
+        assert!(registry.file_exists(&blob_path(&pushed_blob)).await);
+
+        std::fs::remove_dir_all(directory).ok();
+    }
+}
@@ -6,10 +6,15 @@ use crate::{
     registry_error::RegistryResult,
 };
 
-pub async fn get_all_repositories(db_pool: &Pool<DB>) -> RegistryResult<Vec<ViewableRepository>> {
+/// Lists the repositories `username` can see: the public ones and the ones it owns.
+pub async fn get_all_repositories(
+    db_pool: &Pool<DB>,
+    username: Option<&str>,
+) -> RegistryResult<Vec<ViewableRepository>> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    let repositories = repository_repository::find_all_with_owners(&mut transaction).await?;
+    let repositories =
+        repository_repository::find_all_visible_with_owners(&mut transaction, username).await?;
 
     transaction.commit().await?;
 
@@ -1,25 +1,27 @@
-use std::path::Path;
-
-use rocket::fs::NamedFile;
 use sqlx::Pool;
 
 use crate::{
-    config::Config,
     db::{self, blob_repository, DB},
     models::blob::Blob,
-    registry_error::RegistryResult,
+    registry_error::{RegistryError, RegistryResult},
+    storage::{blob_path, Storage, StorageMetadata, StorageReader},
+    types::digest::Digest,
 };
 
 pub async fn find_blob_by_digest(
     db_pool: &Pool<DB>,
-    config: &Config,
+    storage: &Storage,
     namespace: &str,
-    digest: &str,
-) -> RegistryResult<Option<(Blob, NamedFile)>> {
+    digest: &Digest,
+) -> RegistryResult<Option<(Blob, StorageMetadata)>> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    let blob =
-        blob_repository::find_by_repository_and_digest(&mut transaction, namespace, digest).await?;
+    let blob = blob_repository::find_by_repository_and_digest(
+        &mut transaction,
+        namespace,
+        &digest.to_string(),
+    )
+    .await?;
 
     transaction.commit().await?;
 
@@ -29,25 +31,24 @@ pub async fn find_blob_by_digest(
         return Ok(None);
     };
 
-    let file = blob_file(config, blob.digest.clone()).await?;
-
-    Ok(Some((blob, file)))
-}
-
-async fn blob_file(config: &Config, digest: String) -> RegistryResult<NamedFile> {
-    let digest = if let Some(d) = digest.strip_prefix("sha256:") {
-        d.to_string()
-    } else {
-        digest
+    let path = blob_path(digest);
+    info!("Looking for blob at path {path}");
+    let Some(metadata) = storage.stat(&path).await? else {
+        error!("Blob {digest} exists but its file is missing at path {path}");
+        return Err(RegistryError::BlobFileNotFound);
     };
 
-    let file_path = format!(
-        "{}/blobs/sha256/{}.tar.gz",
-        config.storage_directory, digest,
-    );
-    info!("Looking for blob at path {file_path}");
-    let blob_path = Path::new(file_path.as_str());
-    let file = NamedFile::open(blob_path).await?;
+    Ok(Some((blob, metadata)))
+}
 
-    Ok(file)
+/// Streams the content of a blob, starting at byte `offset`.
+pub async fn read_blob(
+    storage: &Storage,
+    digest: &Digest,
+    offset: u64,
+) -> RegistryResult<StorageReader> {
+    storage
+        .get(&blob_path(digest), offset)
+        .await?
+        .ok_or(RegistryError::BlobFileNotFound)
 }
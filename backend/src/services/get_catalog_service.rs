@@ -0,0 +1,48 @@
+use sqlx::Pool;
+
+use crate::{
+    db::{self, repository_repository, DB},
+    registry_error::RegistryResult,
+    upstream_registry::Proxy,
+};
+
+pub struct CatalogPage {
+    pub repositories: Vec<String>,
+    /// Whether there are more repositories after the last one of this page.
+    pub has_more: bool,
+}
+
+/// Lists the repositories `username` may pull, which are the public ones, the ones it owns and
+/// the proxied ones, see [`super::authorization_service::permitted_actions`].
+pub async fn get_catalog(
+    db_pool: &Pool<DB>,
+    proxy: &Proxy,
+    username: Option<&str>,
+    n: Option<usize>,
+    last: Option<&str>,
+) -> RegistryResult<CatalogPage> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    // Fetch one extra name to find out whether there is a next page.
+    let limit = n.map(|n| n as i64 + 1);
+    let mut repositories = repository_repository::find_names_page(
+        &mut transaction,
+        username,
+        proxy.namespace(),
+        last,
+        limit,
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    let has_more = matches!(n, Some(n) if repositories.len() > n);
+    if let Some(n) = n {
+        repositories.truncate(n);
+    }
+
+    Ok(CatalogPage {
+        repositories,
+        has_more,
+    })
+}
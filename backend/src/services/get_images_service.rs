@@ -5,8 +5,8 @@ use sqlx::{
 };
 
 use crate::{
-    db::{manifest_repository, new_transaction, repository_repository, DB},
-    models::{manifest::Manifest, repository::Repository},
+    db::{new_transaction, repository_repository, tag_repository, DB},
+    models::{repository::Repository, tag},
     registry_error::RegistryResult,
 };
 
@@ -22,11 +22,11 @@ pub struct Tag {
     pub created_at: DateTime<Utc>,
 }
 
-impl From<Manifest> for Tag {
-    fn from(value: Manifest) -> Self {
+impl From<tag::Tag> for Tag {
+    fn from(value: tag::Tag) -> Self {
         Self {
-            reference: value.tag.unwrap_or(value.digest),
-            created_at: value.created_at,
+            reference: value.name,
+            created_at: value.updated_at,
         }
     }
 }
@@ -62,15 +62,11 @@ async fn map_image(
     transaction: &mut Transaction<'_, Postgres>,
     repository: Repository,
 ) -> RegistryResult<Image> {
-    let manifests =
-        manifest_repository::find_all_by_repository(transaction, &repository.namespace_name)
-            .await?;
+    let tags =
+        tag_repository::find_all_by_repository(transaction, &repository.namespace_name).await?;
 
     Ok(Image {
         name: repository.namespace_name,
-        tags: manifests
-            .into_iter()
-            .map(|manifest| manifest.into())
-            .collect(),
+        tags: tags.into_iter().map(|tag| tag.into()).collect(),
     })
 }
@@ -1,46 +1,48 @@
-use std::path::Path;
-
-use rocket::fs::NamedFile;
-use sqlx::Pool;
-use uuid::Uuid;
+use sqlx::{Pool, Transaction};
 
 use crate::{
-    config::Config,
+    config::PlatformConfig,
     db::{self, blob_repository, manifest_repository, DB},
     models::{blob::Blob, manifest::Manifest},
-    registry_error::RegistryResult,
+    registry_error::{RegistryError, RegistryResult},
+    storage::{manifest_path, Storage},
+    types::{
+        accepted_media_types::AcceptedMediaTypes, digest::Digest, manifest::FatManifest,
+        reference::Reference,
+    },
 };
 
 pub struct ManifestInfo {
     pub manifest: Manifest,
-    pub blob: Blob,
-    pub named_file: NamedFile,
+    pub blob: Option<Blob>,
+    pub data: Vec<u8>,
 }
 
 pub async fn find_manifest(
     db_pool: &Pool<DB>,
     namespace: &str,
-    reference: &str,
-    config: &Config,
+    reference: &Reference,
+    accepted: &AcceptedMediaTypes,
+    default_platform: &PlatformConfig,
+    storage: &Storage,
 ) -> RegistryResult<Option<ManifestInfo>> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    let manifest = if reference.starts_with("sha256:") {
-        info!("Identified as a digest {reference}, retrieving manifest from that");
-        manifest_repository::find_first_by_repository_and_digest(
-            &mut transaction,
-            namespace,
-            reference,
-        )
-        .await?
-    } else {
-        info!("Assumed to be tag {reference}, retrieving manifest from that");
-        manifest_repository::find_by_repository_and_tag(
-            &mut transaction,
-            namespace,
-            Some(reference),
-        )
-        .await?
+    let manifest = match reference {
+        Reference::Digest(digest) => {
+            info!("Identified as a digest {digest}, retrieving manifest from that");
+            manifest_repository::find_by_repository_and_digest(
+                &mut transaction,
+                namespace,
+                &digest.to_string(),
+            )
+            .await?
+        }
+        Reference::Tag(tag) => {
+            info!("Identified as a tag {tag}, retrieving manifest from that");
+            manifest_repository::find_by_repository_and_tag(&mut transaction, namespace, tag)
+                .await?
+        }
     };
 
     let manifest = if let Some(m) = manifest {
@@ -49,37 +51,112 @@ pub async fn find_manifest(
         return Ok(None);
     };
 
-    let blob =
-        blob_repository::find_by_repository_and_id(&mut transaction, namespace, manifest.blob_id)
-            .await?;
+    let manifest = negotiate_manifest(
+        &mut transaction,
+        storage,
+        namespace,
+        reference,
+        accepted,
+        default_platform,
+        manifest,
+    )
+    .await?;
 
-    let blob = if let Some(b) = blob {
-        b
-    } else {
-        error!("Manifest blob not found! Blob ID {}", manifest.blob_id);
-        return Ok(None);
+    // Image indexes don't have a config blob.
+    let blob = match manifest.blob_id {
+        Some(blob_id) => {
+            let blob =
+                blob_repository::find_by_repository_and_id(&mut transaction, namespace, blob_id)
+                    .await?;
+
+            if blob.is_none() {
+                error!("Manifest blob not found! Blob ID {blob_id}");
+                return Ok(None);
+            }
+
+            blob
+        }
+        None => None,
     };
 
     transaction.commit().await?;
 
-    let file = manifest_file(config, manifest.id).await?;
+    let data = read_manifest_file(storage, &Digest::parse(&manifest.digest)?).await?;
 
     Ok(Some(ManifestInfo {
         manifest,
         blob,
-        named_file: file,
+        data,
     }))
 }
 
-async fn manifest_file(config: &Config, manifest_id: Uuid) -> RegistryResult<NamedFile> {
-    let file_path = format!(
-        "{}/manifests/{}.json",
-        config.storage_directory,
-        manifest_id.to_string()
+/// Picks the manifest to serve based on the media types accepted by the client. A tagged index is
+/// resolved to its manifest for the default platform when the client only accepts single
+/// manifests, manifests requested by digest are never substituted as the client can't verify
+/// them against the digest it asked for.
+async fn negotiate_manifest(
+    transaction: &mut Transaction<'_, DB>,
+    storage: &Storage,
+    namespace: &str,
+    reference: &Reference,
+    accepted: &AcceptedMediaTypes,
+    default_platform: &PlatformConfig,
+    manifest: Manifest,
+) -> RegistryResult<Manifest> {
+    let media_type = format!(
+        "{}/{}",
+        manifest.content_type_top, manifest.content_type_sub
     );
-    info!("Looking for manifest at path {file_path}");
-    let manifest_path = Path::new(file_path.as_str());
-    let file = NamedFile::open(manifest_path).await?;
+    if accepted.accepts(&media_type) {
+        return Ok(manifest);
+    }
+
+    if matches!(reference, Reference::Digest(_)) || !FatManifest::is_index_type(&media_type) {
+        warn!(
+            "Client does not accept manifest {} of type {media_type}",
+            manifest.digest
+        );
+        return Err(RegistryError::ManifestNotAcceptable(media_type));
+    }
+
+    let data = read_manifest_file(storage, &Digest::parse(&manifest.digest)?).await?;
+    let index: FatManifest = serde_json::from_slice(&data)?;
+    let Some(descriptor) = index.find_platform_manifest(
+        &default_platform.os,
+        &default_platform.architecture,
+        |media_type| accepted.accepts(media_type),
+    ) else {
+        warn!(
+            "Index {} has no accepted manifest for {default_platform}",
+            manifest.digest
+        );
+        return Err(RegistryError::ManifestNotAcceptable(media_type));
+    };
+
+    info!(
+        "Resolved index {} to manifest {} for {default_platform}",
+        manifest.digest, descriptor.digest
+    );
+    manifest_repository::find_by_repository_and_digest(transaction, namespace, &descriptor.digest)
+        .await?
+        .ok_or(RegistryError::ManifestNotFound)
+}
+
+/// Reads the stored manifest and verifies that it still matches its digest before it is served.
+async fn read_manifest_file(storage: &Storage, digest: &Digest) -> RegistryResult<Vec<u8>> {
+    let path = manifest_path(digest);
+    info!("Looking for manifest at path {path}");
+
+    let Some(data) = storage.read(&path).await? else {
+        error!("Manifest file for manifest {digest} does not exist at path {path}!");
+        return Err(RegistryError::ManifestFileNotFound);
+    };
+
+    let file_digest = digest.algorithm().digest(&data);
+    if file_digest != *digest {
+        error!("Manifest file {path} has digest {file_digest}, expected {digest}");
+        return Err(RegistryError::ManifestFileCorrupted(digest.to_string()));
+    }
 
-    Ok(file)
+    Ok(data)
 }
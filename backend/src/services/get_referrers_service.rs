@@ -0,0 +1,33 @@
+use sqlx::Pool;
+
+use crate::{
+    db::{self, manifest_referrer_repository, DB},
+    models::manifest_referrer::Referrer,
+    registry_error::RegistryResult,
+    types::digest::Digest,
+};
+
+pub async fn get_referrers(
+    db_pool: &Pool<DB>,
+    repository: &str,
+    subject_digest: &Digest,
+    artifact_type: Option<&str>,
+) -> RegistryResult<Vec<Referrer>> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    let referrers = manifest_referrer_repository::find_all_by_repository_and_subject(
+        &mut transaction,
+        repository,
+        &subject_digest.to_string(),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    let referrers = referrers
+        .into_iter()
+        .filter(|r| artifact_type.is_none() || r.artifact_type.as_deref() == artifact_type)
+        .collect();
+
+    Ok(referrers)
+}
@@ -1,32 +1,56 @@
 use sqlx::Pool;
 
 use crate::{
-    db::{self, manifest_repository, owner_repository, repository_repository, DB},
-    models::manifest::Manifest,
-    registry_error::RegistryResult,
+    db::{self, owner_repository, repository_repository, tag_repository, DB},
+    models::tag::Tag,
+    registry_error::{RegistryError, RegistryResult},
+    services::authorization_service,
+    types::{
+        resource_scope::{ResourceScope, PULL_ACTION},
+        visibility::Visibility,
+    },
+    upstream_registry::Proxy,
 };
 
 pub struct RepositoryInfo {
     pub name: String,
     pub owner_username: String,
-    pub tags: Vec<Manifest>,
+    pub visibility: Visibility,
+    pub tags: Vec<Tag>,
 }
 
-pub async fn get_repository(db_pool: &Pool<DB>, name: &str) -> RegistryResult<RepositoryInfo> {
+/// Finds a repository that `username` may pull from, other repositories are reported as not
+/// found so that private repositories aren't revealed.
+pub async fn get_repository(
+    db_pool: &Pool<DB>,
+    proxy: &Proxy,
+    username: Option<&str>,
+    name: &str,
+) -> RegistryResult<RepositoryInfo> {
+    let scope = ResourceScope::repository(name, &[PULL_ACTION]);
+    match authorization_service::authorize(db_pool, proxy, username, &scope).await {
+        Err(RegistryError::AccessDenied(_)) => return Err(RegistryError::RepositoryNotFound),
+        result => result?,
+    }
+
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    let repository = repository_repository::find_by_name(&mut transaction, name).await?;
+    let Some(repository) =
+        repository_repository::find_optional_by_name(&mut transaction, name).await?
+    else {
+        return Err(RegistryError::RepositoryNotFound);
+    };
     let owner = owner_repository::find_by_id(&mut transaction, repository.owner).await?;
 
-    let tags =
-        manifest_repository::find_all_by_repository(&mut transaction, &repository.namespace_name)
-            .await?;
+    let tags = tag_repository::find_all_by_repository(&mut transaction, &repository.namespace_name)
+        .await?;
 
     transaction.commit().await?;
 
     Ok(RepositoryInfo {
         name: repository.namespace_name,
         owner_username: owner.username,
+        visibility: repository.is_public.into(),
         tags,
     })
 }
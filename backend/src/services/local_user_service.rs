@@ -0,0 +1,210 @@
+use std::{path::Path, sync::OnceLock};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, SaltString},
+    Argon2, PasswordHasher, PasswordVerifier,
+};
+use rocket::tokio;
+use sqlx::Pool;
+use uuid::Uuid;
+
+use crate::{
+    db::{self, local_user_repository, DB},
+    registry_error::{RegistryError, RegistryResult},
+};
+
+const ARGON2_HASH_PREFIX: &str = "$argon2";
+/// htpasswd only creates bcrypt hashes with one of these prefixes.
+const BCRYPT_HASH_PREFIXES: [&str; 3] = ["$2y$", "$2b$", "$2a$"];
+
+/// Unknown users are checked against this hash, so that they take as long to reject as a wrong
+/// password and don't reveal which users exist.
+static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+
+/// Adds a user with the password, or replaces the password of an existing user.
+pub async fn add_user(db_pool: &Pool<DB>, username: &str, password: &str) -> RegistryResult<()> {
+    if username.is_empty() || username.contains(':') {
+        return Err(RegistryError::InvalidUsername(username.to_string()));
+    }
+
+    let password_hash = hash_password(password.to_string()).await?;
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+    local_user_repository::upsert(&mut transaction, username, &password_hash).await?;
+    transaction.commit().await?;
+
+    info!("Saved local user {username}");
+    Ok(())
+}
+
+/// Imports the users of an htpasswd file, replacing the passwords of users that already exist.
+/// Only bcrypt (`htpasswd -B`) and argon2 hashes are imported, bcrypt hashes are replaced with
+/// argon2 ones when the user first logs in. Returns the names of the imported users.
+pub async fn import_htpasswd(db_pool: &Pool<DB>, path: &Path) -> RegistryResult<Vec<String>> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    let entries = parse_htpasswd(&contents)?;
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    let mut imported = vec![];
+    for (username, password_hash) in entries {
+        local_user_repository::upsert(&mut transaction, username, password_hash).await?;
+        imported.push(username.to_string());
+    }
+
+    transaction.commit().await?;
+
+    Ok(imported)
+}
+
+/// Returns the username and password hash of every entry that can be imported, fails on the
+/// first malformed line.
+fn parse_htpasswd(contents: &str) -> RegistryResult<Vec<(&str, &str)>> {
+    let mut entries = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((username, password_hash)) = line.split_once(':') else {
+            return Err(RegistryError::InvalidHtpasswdEntry(index + 1));
+        };
+        if username.is_empty() {
+            return Err(RegistryError::InvalidHtpasswdEntry(index + 1));
+        }
+
+        if !is_supported_hash(password_hash) {
+            warn!("Skipping {username}, only bcrypt and argon2 hashes can be imported");
+            continue;
+        }
+
+        entries.push((username, password_hash));
+    }
+
+    Ok(entries)
+}
+
+/// Checks the password of a local user. Returns `false` for unknown users.
+pub async fn verify_password(
+    db_pool: &Pool<DB>,
+    username: &str,
+    password: &str,
+) -> RegistryResult<bool> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+    let user = local_user_repository::find_by_username(&mut transaction, username).await?;
+    transaction.commit().await?;
+
+    let is_known = user.is_some();
+    let password_hash = user.map(|user| user.password_hash);
+    let is_bcrypt = password_hash
+        .as_ref()
+        .is_some_and(|hash| !hash.starts_with(ARGON2_HASH_PREFIX));
+    let candidate = password.to_string();
+    let verified = tokio::task::spawn_blocking(move || {
+        let password_hash = password_hash
+            .as_deref()
+            .unwrap_or_else(|| dummy_password_hash());
+        if is_bcrypt {
+            bcrypt::verify(&candidate, password_hash).unwrap_or(false)
+        } else {
+            PasswordHash::new(password_hash)
+                .map(|hash| {
+                    Argon2::default()
+                        .verify_password(candidate.as_bytes(), &hash)
+                        .is_ok()
+                })
+                .unwrap_or(false)
+        }
+    })
+    .await
+    .map_err(|err| RegistryError::PasswordHashError(err.to_string()))?;
+
+    if !is_known {
+        warn!("Unknown local user {username}");
+        return Ok(false);
+    }
+
+    if verified && is_bcrypt {
+        info!("Replacing the imported bcrypt hash of {username}");
+        add_user(db_pool, username, password).await?;
+    }
+
+    Ok(verified)
+}
+
+async fn hash_password(password: String) -> RegistryResult<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| RegistryError::PasswordHashError(err.to_string()))
+    })
+    .await
+    .map_err(|err| RegistryError::PasswordHashError(err.to_string()))?
+}
+
+/// Hashes a random password on first use, which no candidate can match.
+fn dummy_password_hash() -> &'static str {
+    DUMMY_PASSWORD_HASH.get_or_init(|| {
+        let password = Uuid::new_v4().to_string();
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .unwrap_or_default()
+    })
+}
+
+fn is_supported_hash(password_hash: &str) -> bool {
+    password_hash.starts_with(ARGON2_HASH_PREFIX)
+        || BCRYPT_HASH_PREFIXES
+            .iter()
+            .any(|prefix| password_hash.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BCRYPT_HASH: &str = "$2y$05$3ZRHXmvRv3dhSLf7NIpyLOwNhbPIRhvEfSUp8G4JNrkqV3QGawNpW";
+    const ARGON2_HASH: &str =
+        "$argon2id$v=19$m=19456,t=2,p=1$c29tZXNhbHQ$RdescudvJCsgt3ub+b+dWRWJTmaaJObG";
+
+    #[test]
+    fn parses_htpasswd_entries() {
+        let contents = format!(
+            "# registry users\n\nalice:{BCRYPT_HASH}\n  bob:{ARGON2_HASH}  \r\ncarol:{{SHA}}W6ph5Mm5Pz8GgiULbPgzG37mj9g=\ndave:$apr1$salt$hash\n"
+        );
+
+        let entries = parse_htpasswd(&contents).unwrap();
+        assert_eq!(entries, vec![("alice", BCRYPT_HASH), ("bob", ARGON2_HASH)]);
+    }
+
+    #[test]
+    fn rejects_malformed_htpasswd_lines() {
+        for (contents, line) in [
+            (format!("alice:{BCRYPT_HASH}\nbob\n"), 2),
+            (format!("\n:{BCRYPT_HASH}\n"), 2),
+        ] {
+            assert!(
+                matches!(
+                    parse_htpasswd(&contents),
+                    Err(RegistryError::InvalidHtpasswdEntry(invalid)) if invalid == line
+                ),
+                "{contents}"
+            );
+        }
+    }
+
+    #[test]
+    fn dummy_password_hash_matches_no_password() {
+        let hash = PasswordHash::new(dummy_password_hash()).unwrap();
+        for candidate in ["", "password", dummy_password_hash()] {
+            assert!(Argon2::default()
+                .verify_password(candidate.as_bytes(), &hash)
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{fs, path::Path};
+
+use sqlx::Pool;
+
+use crate::{
+    config::Config,
+    db::{blob_repository, DB},
+    registry_error::RegistryResult,
+    storage::{blob_path, Storage, BLOBS_PREFIX},
+    types::digest::Digest,
+};
+
+/// Renames blob files stored by older versions as `blobs/<alg>/<hex>.tar.gz` to `blobs/<alg>/<hex>`
+/// and records the size of blobs uploaded before sizes were stored. Older versions only stored
+/// blobs on the local filesystem, so only files below `STORAGE_DIRECTORY` are renamed.
+pub async fn migrate_blob_files(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    storage: &Storage,
+) -> RegistryResult<()> {
+    rename_legacy_blob_files(config)?;
+
+    let mut transaction = db_pool.begin().await?;
+    let digests = blob_repository::find_digests_without_size(&mut transaction).await?;
+    for digest in &digests {
+        let Ok(parsed) = Digest::parse(digest) else {
+            warn!("Skipping size backfill of blob with invalid digest {digest}");
+            continue;
+        };
+
+        match storage.stat(&blob_path(&parsed)).await? {
+            Some(metadata) => {
+                blob_repository::set_size_by_digest(&mut transaction, digest, metadata.size as i64)
+                    .await?;
+            }
+            None => warn!("Can't read size of blob {digest}, its file is missing"),
+        }
+    }
+    transaction.commit().await?;
+
+    if !digests.is_empty() {
+        info!("Recorded the size of {} blobs", digests.len());
+    }
+
+    Ok(())
+}
+
+fn rename_legacy_blob_files(config: &Config) -> RegistryResult<()> {
+    let blob_dir = Path::new(&config.storage_directory).join(BLOBS_PREFIX);
+    if !blob_dir.exists() {
+        return Ok(());
+    }
+
+    let mut migrated = 0;
+    for algorithm_dir in fs::read_dir(blob_dir)? {
+        let algorithm_dir = algorithm_dir?.path();
+        if !algorithm_dir.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(algorithm_dir)? {
+            let path = entry?.path();
+            let Some(hex) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".tar.gz"))
+            else {
+                continue;
+            };
+
+            let target = path.with_file_name(hex);
+            if target.exists() {
+                fs::remove_file(&path)?;
+            } else {
+                fs::rename(&path, &target)?;
+            }
+            migrated += 1;
+        }
+    }
+
+    if migrated > 0 {
+        info!("Removed the .tar.gz extension from {migrated} blob files");
+    }
+
+    Ok(())
+}
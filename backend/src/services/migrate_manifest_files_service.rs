@@ -0,0 +1,55 @@
+use std::{fs, path::Path};
+
+use uuid::Uuid;
+
+use crate::{
+    config::Config, registry_error::RegistryResult, storage::Storage,
+    types::digest::DigestAlgorithm,
+};
+
+use super::upload_manifest_service::save_file;
+
+/// Moves manifests stored by older versions as `manifests/<uuid>.json` into the content-addressed
+/// layout. Each file is keyed by the digest of its actual contents, so files that went stale when
+/// a tag was overwritten no longer shadow the manifest the database points to.
+pub async fn migrate_legacy_manifest_files(
+    config: &Config,
+    storage: &Storage,
+) -> RegistryResult<()> {
+    let legacy_dir = Path::new(&config.storage_directory).join("manifests");
+    if !legacy_dir.exists() {
+        return Ok(());
+    }
+
+    let mut migrated = 0;
+    for entry in fs::read_dir(legacy_dir)? {
+        let path = entry?.path();
+        if !is_legacy_manifest_file(&path) {
+            continue;
+        }
+
+        let data = fs::read(&path)?;
+        let digest = DigestAlgorithm::Sha256.digest(&data);
+
+        info!("Migrating manifest file {path:?} to {digest}");
+        save_file(storage, &digest, data).await?;
+        fs::remove_file(&path)?;
+
+        migrated += 1;
+    }
+
+    if migrated > 0 {
+        info!("Migrated {migrated} manifest files to content-addressed storage");
+    }
+
+    Ok(())
+}
+
+fn is_legacy_manifest_file(path: &Path) -> bool {
+    path.is_file()
+        && path.extension().is_some_and(|ext| ext == "json")
+        && path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| Uuid::parse_str(stem).is_ok())
+}
@@ -1,11 +1,24 @@
+pub mod auth_identity_service;
+pub mod authorization_service;
 pub mod delete_blob_service;
 pub mod delete_manifest_service;
+pub mod delete_upload_session_service;
+pub mod garbage_collection_service;
 pub mod get_all_repositories_service;
 pub mod get_blob_service;
+pub mod get_catalog_service;
 pub mod get_images_service;
 pub mod get_manifest_service;
+pub mod get_referrers_service;
 pub mod get_repository_service;
 pub mod get_tags_service;
 pub mod get_upload_session_service;
+pub mod local_user_service;
+pub mod migrate_blob_files_service;
+pub mod migrate_manifest_files_service;
+pub mod notification_service;
+pub mod proxy_service;
+pub mod token_service;
+pub mod update_repository_service;
 pub mod upload_blob_service;
 pub mod upload_manifest_service;
@@ -0,0 +1,406 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use reqwest::{header, redirect, Client, Url};
+use rocket::tokio::net::lookup_host;
+use sqlx::{types::chrono::Utc, Pool};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    db::{self, notification_endpoint_repository, notification_repository, DB},
+    models::{notification::Notification, notification_endpoint::NotificationEndpoint},
+    registry_error::{RegistryError, RegistryResult},
+    services::authorization_service::verify_owner,
+    types::notification_event::{
+        Envelope, Event, EventAction, EventActor, EventRequest, EventSource, EventTarget,
+        EVENTS_MEDIA_TYPE,
+    },
+};
+
+/// How many notifications are taken from the queue at once.
+const DELIVERY_BATCH_SIZE: i64 = 50;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Retry delays stop doubling after this many failed attempts.
+const MAX_BACKOFF_EXPONENT: u32 = 10;
+
+/// Queues an event for every endpoint of the deployment and of the repository. Events are
+/// delivered by the notification dispatcher, failing to queue them is logged but never fails the
+/// request that caused them.
+pub async fn notify(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    action: EventAction,
+    target: EventTarget,
+    request: &EventRequest,
+    actor: Option<&str>,
+) {
+    let repository = target.repository.clone();
+    let event = Event {
+        id: Uuid::new_v4(),
+        timestamp: Utc::now(),
+        action,
+        target,
+        request: request.clone(),
+        actor: EventActor {
+            name: actor.map(str::to_string),
+        },
+        source: EventSource {
+            addr: config.registry_url.clone(),
+        },
+    };
+
+    if let Err(err) = queue_event(db_pool, config, &repository, event).await {
+        error!("Failed to queue {action:?} event for {repository}, err: {err:?}");
+    }
+}
+
+async fn queue_event(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    repository: &str,
+    event: Event,
+) -> RegistryResult<()> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    let mut endpoints = config.notifications.endpoints.clone();
+    endpoints.extend(
+        notification_endpoint_repository::find_all_by_repository(&mut transaction, repository)
+            .await?
+            .into_iter()
+            .map(|endpoint| endpoint.url),
+    );
+
+    if !endpoints.is_empty() {
+        let payload = serde_json::to_value(Envelope {
+            events: vec![event],
+        })?;
+        for endpoint in endpoints.iter() {
+            notification_repository::insert(&mut transaction, endpoint, &payload).await?;
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Delivers the notifications that are due, returns how many were delivered. Failed deliveries
+/// are retried with an exponential backoff until the configured number of attempts is reached.
+pub async fn deliver_due_notifications(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    client: &Client,
+) -> RegistryResult<usize> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+    // The lease outlasts the deliveries of the whole batch.
+    let lease = DELIVERY_TIMEOUT * (DELIVERY_BATCH_SIZE as u32 + 1);
+    let notifications = notification_repository::claim_due(
+        &mut transaction,
+        DELIVERY_BATCH_SIZE,
+        lease.as_secs_f64(),
+    )
+    .await?;
+    transaction.commit().await?;
+
+    let mut delivered = 0;
+    for notification in notifications {
+        let result = deliver(config, client, &notification).await;
+
+        let mut transaction = db::new_transaction(db_pool).await?;
+        match result {
+            Ok(()) => {
+                notification_repository::delete(&mut transaction, notification.id).await?;
+                delivered += 1;
+            }
+            Err(err) if notification.attempts + 1 >= config.notifications.max_attempts as i32 => {
+                error!(
+                    "Giving up on notification {} to {} after {} attempts, err: {err}",
+                    notification.id,
+                    notification.endpoint,
+                    notification.attempts + 1
+                );
+                notification_repository::delete(&mut transaction, notification.id).await?;
+            }
+            Err(err) => {
+                let delay = config.notifications.retry_interval
+                    * 2u32.pow((notification.attempts as u32).min(MAX_BACKOFF_EXPONENT));
+                warn!(
+                    "Failed to deliver notification {} to {}, retrying in {delay:?}, err: {err}",
+                    notification.id, notification.endpoint
+                );
+                notification_repository::set_failed_attempt(
+                    &mut transaction,
+                    notification.id,
+                    delay.as_secs_f64(),
+                    &err,
+                )
+                .await?;
+            }
+        }
+        transaction.commit().await?;
+    }
+
+    Ok(delivered)
+}
+
+/// Builds the client that delivers notifications. Redirects aren't followed, an endpoint could
+/// redirect to any address.
+pub fn new_client() -> reqwest::Result<Client> {
+    new_client_builder().build()
+}
+
+fn new_client_builder() -> reqwest::ClientBuilder {
+    Client::builder().redirect(redirect::Policy::none())
+}
+
+/// The endpoints of the deployment are configured by its operator and may be internal services,
+/// the endpoints that repository owners add may only be public addresses. Their host is resolved
+/// before every delivery and the request is sent to the checked addresses, so a DNS record that
+/// changes after the check can't point the request elsewhere.
+async fn deliver(
+    config: &Config,
+    client: &Client,
+    notification: &Notification,
+) -> Result<(), String> {
+    let pinned_client;
+    let client = if config
+        .notifications
+        .endpoints
+        .contains(&notification.endpoint)
+    {
+        client
+    } else {
+        let url = Url::parse(&notification.endpoint).map_err(|err| err.to_string())?;
+        let addresses = resolve_public_addresses(&url)
+            .await
+            .map_err(|err| err.to_string())?;
+        pinned_client = new_client_builder()
+            .resolve_to_addrs(url.host_str().unwrap_or_default(), &addresses)
+            .build()
+            .map_err(|err| err.to_string())?;
+        &pinned_client
+    };
+
+    let response = client
+        .post(&notification.endpoint)
+        .header(header::CONTENT_TYPE, EVENTS_MEDIA_TYPE)
+        .json(&notification.payload)
+        .timeout(DELIVERY_TIMEOUT)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("endpoint returned {status}"));
+    }
+
+    Ok(())
+}
+
+pub async fn get_endpoints(
+    db_pool: &Pool<DB>,
+    username: &str,
+    repository: &str,
+) -> RegistryResult<Vec<NotificationEndpoint>> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    verify_owner(&mut transaction, username, repository).await?;
+    let endpoints =
+        notification_endpoint_repository::find_all_by_repository(&mut transaction, repository)
+            .await?;
+
+    transaction.commit().await?;
+
+    Ok(endpoints)
+}
+
+pub async fn add_endpoint(
+    db_pool: &Pool<DB>,
+    username: &str,
+    repository: &str,
+    url: &str,
+) -> RegistryResult<NotificationEndpoint> {
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {
+            resolve_public_addresses(&parsed).await?;
+        }
+        _ => return Err(RegistryError::InvalidNotificationEndpoint(url.to_string())),
+    }
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    verify_owner(&mut transaction, username, repository).await?;
+    let endpoint =
+        notification_endpoint_repository::upsert(&mut transaction, repository, url).await?;
+
+    transaction.commit().await?;
+
+    info!("Added notification endpoint {url} to {repository}");
+    Ok(endpoint)
+}
+
+/// Returns whether the endpoint existed.
+pub async fn delete_endpoint(
+    db_pool: &Pool<DB>,
+    username: &str,
+    repository: &str,
+    id: Uuid,
+) -> RegistryResult<bool> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    verify_owner(&mut transaction, username, repository).await?;
+    let deleted = notification_endpoint_repository::delete_by_repository_and_id(
+        &mut transaction,
+        repository,
+        id,
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(deleted)
+}
+
+/// Resolves the host of an endpoint, fails unless every address it resolves to is public.
+async fn resolve_public_addresses(url: &Url) -> RegistryResult<Vec<SocketAddr>> {
+    let invalid = || RegistryError::InvalidNotificationEndpoint(url.to_string());
+
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err(invalid());
+    };
+    // IPv6 addresses are enclosed in brackets in URLs.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addresses = match lookup_host((host, port)).await {
+        Ok(addresses) => addresses.collect::<Vec<_>>(),
+        Err(err) => {
+            warn!("Failed to resolve notification endpoint {url}, err: {err:?}");
+            return Err(invalid());
+        }
+    };
+
+    if addresses.is_empty() || !addresses.iter().all(|address| is_public(address.ip())) {
+        warn!("Notification endpoint {url} resolves to non-public addresses {addresses:?}");
+        return Err(invalid());
+    }
+
+    Ok(addresses)
+}
+
+/// Whether the address is globally reachable, rather than loopback, private, link-local, shared,
+/// multicast or reserved for documentation or other special purposes.
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_v4(address),
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(address),
+        },
+    }
+}
+
+fn is_public_v4(address: Ipv4Addr) -> bool {
+    let [first, second, third, _] = address.octets();
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_documentation()
+        || address.is_multicast()
+        // "This network" 0.0.0.0/8
+        || first == 0
+        // Shared address space 100.64.0.0/10
+        || (first == 100 && (second & 0b1100_0000) == 64)
+        // IETF protocol assignments 192.0.0.0/24
+        || (first == 192 && second == 0 && third == 0)
+        // Benchmarking 198.18.0.0/15
+        || (first == 198 && (second & 0b1111_1110) == 18)
+        // Reserved 240.0.0.0/4
+        || first >= 240)
+}
+
+fn is_public_v6(address: Ipv6Addr) -> bool {
+    let segments = address.segments();
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_multicast()
+        // Unique local fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // Link-local fe80::/10 and the deprecated site-local fec0::/10
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        // IPv4-translated 64:ff9b::/96 and 64:ff9b:1::/48 can reach private IPv4 addresses
+        || (segments[0] == 0x64 && segments[1] == 0xff9b)
+        // Documentation 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_public_str(address: &str) -> bool {
+        is_public(address.parse().unwrap())
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        for address in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "100.128.0.1",
+            "172.32.0.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(is_public_str(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn rejects_internal_addresses() {
+        for address in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "192.0.0.1",
+            "198.18.0.1",
+            "224.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public_str(address), "{address}");
+        }
+    }
+
+    #[rocket::async_test]
+    async fn rejects_endpoints_that_resolve_to_internal_addresses() {
+        for url in [
+            "http://localhost:8080/events",
+            "http://127.0.0.1/events",
+            "http://[::1]:8080/events",
+            "http://169.254.169.254/latest/meta-data",
+        ] {
+            let result = resolve_public_addresses(&Url::parse(url).unwrap()).await;
+            assert!(
+                matches!(result, Err(RegistryError::InvalidNotificationEndpoint(_))),
+                "{url}"
+            );
+        }
+    }
+}
@@ -0,0 +1,563 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use rocket::{
+    futures::{future::BoxFuture, FutureExt},
+    http::ContentType,
+    tokio::{
+        self,
+        io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf},
+    },
+};
+use sqlx::{types::chrono::Utc, Pool};
+
+use crate::{
+    config::Config,
+    db::{self, blob_repository, manifest_repository, tag_repository, DB},
+    models::blob::Blob,
+    registry_error::{RegistryError, RegistryResult},
+    services::{get_blob_service, upload_blob_service, upload_manifest_service},
+    storage::{Storage, StorageReader},
+    types::{digest::Digest, manifest::ParsedManifest, reference::Reference},
+    upstream_registry::{Proxy, UpstreamRegistry},
+};
+
+/// Upstreams such as Docker Hub send blobs without a more specific type.
+const DEFAULT_BLOB_MEDIA_TYPE: &str = "application/octet-stream";
+
+/// How much of a blob that is streamed from the upstream is buffered for a slow client.
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A blob whose content is streamed from the upstream whilst it is being cached.
+pub struct UpstreamBlobStream {
+    pub blob: Blob,
+    pub reader: StorageReader,
+    pub size: u64,
+}
+
+#[derive(PartialEq)]
+enum CacheState {
+    Fresh,
+    Stale,
+    Missing,
+}
+
+/// Pulls the manifest from the upstream if the repository is proxied and the manifest is not
+/// cached yet, or if the cached tag is older than the tag TTL. A stale tag keeps being served
+/// while the upstream can't be reached.
+pub async fn cache_manifest(
+    db_pool: &Pool<DB>,
+    storage: &Storage,
+    proxy: &Proxy,
+    namespace: &str,
+    reference: &Reference,
+) -> RegistryResult<()> {
+    let Some((upstream, upstream_name)) = proxy.upstream_for(namespace) else {
+        return Ok(());
+    };
+
+    let state = cache_state(db_pool, upstream, namespace, reference).await?;
+    if state == CacheState::Fresh {
+        return Ok(());
+    }
+
+    info!("Pulling manifest {namespace}:{reference} from upstream {upstream_name}");
+    match store_manifest(
+        db_pool,
+        storage,
+        upstream,
+        namespace,
+        upstream_name,
+        reference,
+    )
+    .await
+    {
+        Ok(()) => Ok(()),
+        Err(err) if state == CacheState::Stale => {
+            warn!("Failed to revalidate {namespace}:{reference}, serving the cached manifest, err: {err:?}");
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Pulls the blob from the upstream if the repository is proxied and the blob's content is not
+/// cached yet.
+///
+/// With `stream`, the content is returned as it arrives whilst it is cached in the background,
+/// which carries on if the client goes away. Otherwise, or if the upstream does not tell the
+/// blob's size, this only returns once the whole blob has been cached.
+pub async fn cache_blob(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    storage: &Storage,
+    proxy: &Proxy,
+    namespace: &str,
+    digest: &Digest,
+    stream: bool,
+) -> RegistryResult<Option<UpstreamBlobStream>> {
+    let Some((upstream, upstream_name)) = proxy.upstream_for(namespace) else {
+        return Ok(None);
+    };
+
+    match get_blob_service::find_blob_by_digest(db_pool, storage, namespace, digest).await {
+        Ok(Some(_)) => return Ok(None),
+        Ok(None) | Err(RegistryError::BlobFileNotFound) => {}
+        Err(err) => return Err(err),
+    }
+
+    info!("Pulling blob {digest} for {namespace} from upstream {upstream_name}");
+    let Some((reader, size)) = upstream.fetch_blob(upstream_name, digest).await? else {
+        return Ok(None);
+    };
+
+    let size = match size {
+        Some(size) if stream => size,
+        _ => {
+            upload_blob_service::import_blob(
+                db_pool,
+                config,
+                storage,
+                upstream.namespace(),
+                namespace,
+                digest,
+                reader,
+            )
+            .await?;
+            return Ok(None);
+        }
+    };
+
+    let digest_string = digest.to_string();
+    upload_blob_service::declare_blobs(
+        db_pool,
+        upstream.namespace(),
+        namespace,
+        &[(digest_string.as_str(), size as i64, DEFAULT_BLOB_MEDIA_TYPE)],
+    )
+    .await?;
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+    let blob =
+        blob_repository::find_by_repository_and_digest(&mut transaction, namespace, &digest_string)
+            .await?
+            .ok_or(RegistryError::InvalidState)?;
+    transaction.commit().await?;
+
+    let (client, client_reader) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+    let reader = TeeReader {
+        inner: reader,
+        copy: Some(client),
+        pending: vec![],
+    };
+
+    let (db_pool, config, storage) = (db_pool.clone(), config.clone(), storage.clone());
+    let (owner, namespace, digest) = (
+        upstream.namespace().to_string(),
+        namespace.to_string(),
+        digest.clone(),
+    );
+    tokio::spawn(async move {
+        if let Err(err) = upload_blob_service::import_blob(
+            &db_pool,
+            &config,
+            &storage,
+            &owner,
+            &namespace,
+            &digest,
+            Box::pin(reader),
+        )
+        .await
+        {
+            error!("Failed to cache blob {digest} for {namespace}, err: {err:?}");
+        }
+    });
+
+    Ok(Some(UpstreamBlobStream {
+        blob,
+        reader: Box::pin(client_reader),
+        size,
+    }))
+}
+
+/// Finds the blob and its size for a HEAD request. The blob of a proxied repository whose
+/// content is not cached yet is only looked up on the upstream and declared, its content is
+/// pulled once a client downloads it.
+pub async fn stat_blob(
+    db_pool: &Pool<DB>,
+    storage: &Storage,
+    proxy: &Proxy,
+    namespace: &str,
+    digest: &Digest,
+) -> RegistryResult<Option<(Blob, u64)>> {
+    let cached = get_blob_service::find_blob_by_digest(db_pool, storage, namespace, digest).await;
+    let Some((upstream, upstream_name)) = proxy.upstream_for(namespace) else {
+        return cached.map(|blob| blob.map(|(blob, metadata)| (blob, metadata.size)));
+    };
+
+    match cached {
+        Ok(Some((blob, metadata))) => return Ok(Some((blob, metadata.size))),
+        Ok(None) | Err(RegistryError::BlobFileNotFound) => {}
+        Err(err) => return Err(err),
+    }
+
+    info!("Looking up blob {digest} for {namespace} on upstream {upstream_name}");
+    let Some(upstream_blob) = upstream.stat_blob(upstream_name, digest).await? else {
+        return Ok(None);
+    };
+
+    let digest = digest.to_string();
+    upload_blob_service::declare_blobs(
+        db_pool,
+        upstream.namespace(),
+        namespace,
+        &[(
+            digest.as_str(),
+            upstream_blob.size as i64,
+            upstream_blob
+                .media_type
+                .as_deref()
+                .unwrap_or(DEFAULT_BLOB_MEDIA_TYPE),
+        )],
+    )
+    .await?;
+
+    let mut transaction = db::new_transaction(db_pool).await?;
+    let blob = blob_repository::find_by_repository_and_digest(&mut transaction, namespace, &digest)
+        .await?;
+    transaction.commit().await?;
+
+    Ok(blob.map(|blob| (blob, upstream_blob.size)))
+}
+
+async fn cache_state(
+    db_pool: &Pool<DB>,
+    upstream: &UpstreamRegistry,
+    namespace: &str,
+    reference: &Reference,
+) -> RegistryResult<CacheState> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    let state = match reference {
+        // Content addressed manifests never change.
+        Reference::Digest(digest) => {
+            match manifest_repository::find_by_repository_and_digest(
+                &mut transaction,
+                namespace,
+                &digest.to_string(),
+            )
+            .await?
+            {
+                Some(_) => CacheState::Fresh,
+                None => CacheState::Missing,
+            }
+        }
+        Reference::Tag(tag) => {
+            match tag_repository::find_by_repository_and_name(&mut transaction, namespace, tag)
+                .await?
+            {
+                Some(tag)
+                    if (Utc::now() - tag.updated_at).to_std().unwrap_or_default()
+                        < upstream.tag_ttl() =>
+                {
+                    CacheState::Fresh
+                }
+                Some(_) => CacheState::Stale,
+                None => CacheState::Missing,
+            }
+        }
+    };
+
+    transaction.commit().await?;
+
+    Ok(state)
+}
+
+/// Stores the upstream manifest through a regular manifest upload. The manifests listed by an
+/// index are stored first, the blobs of an image are only declared and pulled when requested.
+fn store_manifest<'a>(
+    db_pool: &'a Pool<DB>,
+    storage: &'a Storage,
+    upstream: &'a UpstreamRegistry,
+    namespace: &'a str,
+    upstream_name: &'a str,
+    reference: &'a Reference,
+) -> BoxFuture<'a, RegistryResult<()>> {
+    async move {
+        let Some(manifest) = upstream
+            .fetch_manifest(upstream_name, &reference.to_string())
+            .await?
+        else {
+            return Ok(());
+        };
+
+        let Some(content_type) = ContentType::parse_flexible(&manifest.media_type) else {
+            warn!(
+                "Upstream manifest {upstream_name}:{reference} has invalid media type {}",
+                manifest.media_type
+            );
+            return Err(RegistryError::UnsupportedManifestType);
+        };
+
+        match ParsedManifest::parse(&content_type, &manifest.data)? {
+            ParsedManifest::Image(image_manifest) => {
+                let descriptors = std::iter::once(&image_manifest.config)
+                    .map(|config| {
+                        (
+                            config.digest.as_str(),
+                            config.size,
+                            config.media_type.as_str(),
+                        )
+                    })
+                    .chain(image_manifest.layers.iter().map(|layer| {
+                        (layer.digest.as_str(), layer.size, layer.media_type.as_str())
+                    }))
+                    .collect::<Vec<_>>();
+
+                upload_blob_service::declare_blobs(
+                    db_pool,
+                    upstream.namespace(),
+                    namespace,
+                    &descriptors,
+                )
+                .await?;
+            }
+            ParsedManifest::Index(index) => {
+                for descriptor in index.manifests.iter() {
+                    let child = Reference::Digest(Digest::parse(&descriptor.digest)?);
+                    if cache_state(db_pool, upstream, namespace, &child).await?
+                        == CacheState::Missing
+                    {
+                        store_manifest(
+                            db_pool,
+                            storage,
+                            upstream,
+                            namespace,
+                            upstream_name,
+                            &child,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        upload_manifest_service::upload_manifest(
+            db_pool,
+            storage,
+            namespace,
+            reference,
+            &content_type,
+            manifest.data,
+        )
+        .await?;
+
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Reader that passes everything read from `inner` on to `copy`, until `copy` is closed. What
+/// was read is passed on before reading more, so a slow reader of the copy slows reading down.
+struct TeeReader {
+    inner: StorageReader,
+    copy: Option<DuplexStream>,
+    pending: Vec<u8>,
+}
+
+impl AsyncRead for TeeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        while !this.pending.is_empty() {
+            let Some(copy) = this.copy.as_mut() else {
+                this.pending.clear();
+                break;
+            };
+
+            match ready!(Pin::new(copy).poll_write(cx, &this.pending)) {
+                Ok(written) => {
+                    this.pending.drain(..written);
+                }
+                Err(_) => {
+                    info!("The client stopped reading, caching the rest of the blob");
+                    this.copy = None;
+                }
+            }
+        }
+
+        let start = buf.filled().len();
+        ready!(this.inner.as_mut().poll_read(cx, buf))?;
+
+        let read = &buf.filled()[start..];
+        if read.is_empty() {
+            // Closes the copy, its reader has everything.
+            this.copy = None;
+        } else if this.copy.is_some() {
+            this.pending.extend_from_slice(read);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rocket::tokio::io::AsyncReadExt;
+    use sqlx::postgres::PgPoolOptions;
+    use uuid::Uuid;
+
+    use crate::{
+        config::StorageConfig,
+        storage::StorageMetadata,
+        upstream_registry::tests::{proxy_config, StandInUpstream, IMAGE_MANIFEST_TYPE},
+    };
+
+    use super::*;
+
+    async fn wait_until_cached(
+        db_pool: &Pool<DB>,
+        storage: &Storage,
+        namespace: &str,
+        digest: &Digest,
+    ) -> (Blob, StorageMetadata) {
+        for _ in 0..50 {
+            if let Ok(Some(cached)) =
+                get_blob_service::find_blob_by_digest(db_pool, storage, namespace, digest).await
+            {
+                return cached;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        panic!("Blob {digest} was not cached");
+    }
+
+    /// Pulls an image from a stand-in upstream into the database of `DATABASE_URL`, run with
+    /// `cargo test -- --ignored caches_an_image_from_the_upstream`.
+    #[rocket::async_test]
+    #[ignore]
+    async fn caches_an_image_from_the_upstream() {
+        let mut stand_in = StandInUpstream::default();
+        let config_digest = stand_in.add_blob(b"{}");
+        let layer_digest = stand_in.add_blob(b"layer content");
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"mediaType":"{IMAGE_MANIFEST_TYPE}","config":{{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"{config_digest}","size":2}},"layers":[{{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","digest":"{layer_digest}","size":13}}]}}"#
+        );
+        stand_in.add_manifest("latest", IMAGE_MANIFEST_TYPE, manifest.as_bytes());
+        let large_content = vec![7; 4 * STREAM_BUFFER_SIZE];
+        let large_digest = stand_in.add_blob(&large_content);
+        let requests = stand_in.requests.clone();
+        let upstream_url = stand_in.serve().await;
+
+        let directory = std::env::temp_dir().join(format!("proxy-test-{}", Uuid::new_v4()));
+        let mut config = Config::new().unwrap();
+        config.storage = StorageConfig::Filesystem;
+        config.storage_directory = directory.to_string_lossy().to_string();
+        config.proxy = Some(proxy_config("stand-in", upstream_url));
+
+        let db_pool = PgPoolOptions::new()
+            .connect(&config.database_url)
+            .await
+            .unwrap();
+        let storage = Storage::new(&config);
+        let proxy = Proxy::new(&config);
+        let namespace = format!("stand-in/app-{}", Uuid::new_v4().simple());
+
+        let tag = Reference::Tag("latest".to_string());
+        cache_manifest(&db_pool, &storage, &proxy, &namespace, &tag)
+            .await
+            .unwrap();
+        let (upstream, _) = proxy.upstream_for(&namespace).unwrap();
+        assert!(
+            cache_state(&db_pool, upstream, &namespace, &tag)
+                .await
+                .unwrap()
+                == CacheState::Fresh
+        );
+
+        // A HEAD request only looks the layer up, its content is pulled by a GET request.
+        let (blob, size) = stat_blob(&db_pool, &storage, &proxy, &namespace, &layer_digest)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(blob.digest, layer_digest.to_string());
+        assert_eq!(size, 13);
+        assert!(!requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|request| request.starts_with("GET") && request.contains("/blobs/")));
+
+        cache_blob(
+            &db_pool,
+            &config,
+            &storage,
+            &proxy,
+            &namespace,
+            &layer_digest,
+            false,
+        )
+        .await
+        .unwrap();
+        let (_, metadata) =
+            get_blob_service::find_blob_by_digest(&db_pool, &storage, &namespace, &layer_digest)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(metadata.size, 13);
+
+        // The content of a GET request for the whole blob is streamed whilst it's cached.
+        let upstream_blob = cache_blob(
+            &db_pool,
+            &config,
+            &storage,
+            &proxy,
+            &namespace,
+            &config_digest,
+            true,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(upstream_blob.blob.digest, config_digest.to_string());
+        assert_eq!(upstream_blob.size, 2);
+        let mut data = vec![];
+        let mut reader = upstream_blob.reader;
+        reader.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"{}");
+
+        let (_, metadata) = wait_until_cached(&db_pool, &storage, &namespace, &config_digest).await;
+        assert_eq!(metadata.size, 2);
+
+        // Caching carries on when the client stops reading part way through a blob.
+        let upstream_blob = cache_blob(
+            &db_pool,
+            &config,
+            &storage,
+            &proxy,
+            &namespace,
+            &large_digest,
+            true,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let mut reader = upstream_blob.reader;
+        reader.read_exact(&mut [0; 10]).await.unwrap();
+        drop(reader);
+        let (_, metadata) = wait_until_cached(&db_pool, &storage, &namespace, &large_digest).await;
+        assert_eq!(metadata.size, large_content.len() as u64);
+
+        std::fs::remove_dir_all(directory).ok();
+    }
+}
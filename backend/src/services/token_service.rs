@@ -0,0 +1,174 @@
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    registry_error::{RegistryError, RegistryResult},
+    types::resource_scope::ResourceScope,
+};
+
+const TOKEN_ISSUER: &str = "container-registry-rs";
+const TOKEN_ALGORITHM: Algorithm = Algorithm::HS256;
+
+/// The claims of the tokens issued by the registry, following the Docker registry token
+/// specification. Anonymous tokens have an empty subject.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: i64,
+    pub nbf: i64,
+    pub iat: i64,
+    pub jti: String,
+    pub access: Vec<ResourceScope>,
+}
+
+impl Claims {
+    pub fn grants(&self, required: &ResourceScope) -> bool {
+        self.access.iter().any(|granted| granted.covers(required))
+    }
+}
+
+pub struct IssuedToken {
+    pub token: String,
+    pub expires_in: u64,
+    pub issued_at: DateTime<Utc>,
+}
+
+/// Signs a token for `username` that grants `access` until the configured TTL runs out.
+pub fn issue_token(
+    config: &Config,
+    username: Option<&str>,
+    access: Vec<ResourceScope>,
+) -> RegistryResult<IssuedToken> {
+    let issued_at = Utc::now();
+    let claims = Claims {
+        iss: TOKEN_ISSUER.to_string(),
+        sub: username.unwrap_or_default().to_string(),
+        aud: config.token_service.clone(),
+        exp: issued_at.timestamp() + config.token_ttl.as_secs() as i64,
+        nbf: issued_at.timestamp(),
+        iat: issued_at.timestamp(),
+        jti: Uuid::new_v4().to_string(),
+        access,
+    };
+
+    let token = encode_claims(&claims, &config.token_signing_key)?;
+
+    Ok(IssuedToken {
+        token,
+        expires_in: config.token_ttl.as_secs(),
+        issued_at,
+    })
+}
+
+/// Checks the signature, issuer, audience and lifetime of a token issued by [`issue_token`].
+pub fn verify_token(config: &Config, token: &str) -> RegistryResult<Claims> {
+    decode_claims(token, &config.token_service, &config.token_signing_key)
+}
+
+fn encode_claims(claims: &Claims, signing_key: &str) -> RegistryResult<String> {
+    jsonwebtoken::encode(
+        &Header::new(TOKEN_ALGORITHM),
+        claims,
+        &EncodingKey::from_secret(signing_key.as_bytes()),
+    )
+    .map_err(|err| RegistryError::InvalidToken(err.to_string()))
+}
+
+fn decode_claims(token: &str, service: &str, signing_key: &str) -> RegistryResult<Claims> {
+    let mut validation = Validation::new(TOKEN_ALGORITHM);
+    validation.set_issuer(&[TOKEN_ISSUER]);
+    validation.set_audience(&[service]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud"]);
+    validation.validate_nbf = true;
+
+    jsonwebtoken::decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(signing_key.as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .map_err(|err| RegistryError::InvalidToken(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: &str = "registry.example.com";
+    const SIGNING_KEY: &str = "test-signing-key";
+
+    fn claims(expires_in: i64) -> Claims {
+        let now = Utc::now().timestamp();
+        Claims {
+            iss: TOKEN_ISSUER.to_string(),
+            sub: "alice".to_string(),
+            aud: SERVICE.to_string(),
+            exp: now + expires_in,
+            nbf: now,
+            iat: now,
+            jti: Uuid::new_v4().to_string(),
+            access: vec![ResourceScope::repository("alice/app", &["pull"])],
+        }
+    }
+
+    #[test]
+    fn verifies_an_issued_token() {
+        let token = encode_claims(&claims(300), SIGNING_KEY).unwrap();
+
+        let verified = decode_claims(&token, SERVICE, SIGNING_KEY).unwrap();
+        assert_eq!(verified.sub, "alice");
+        assert!(verified.grants(&ResourceScope::repository("alice/app", &["pull"])));
+        assert!(!verified.grants(&ResourceScope::repository("alice/app", &["push"])));
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        // Beyond the default leeway of 60 seconds.
+        let token = encode_claims(&claims(-120), SIGNING_KEY).unwrap();
+
+        assert!(decode_claims(&token, SERVICE, SIGNING_KEY).is_err());
+    }
+
+    #[test]
+    fn rejects_a_token_for_another_service() {
+        let token = encode_claims(&claims(300), SIGNING_KEY).unwrap();
+
+        assert!(decode_claims(&token, "other.example.com", SIGNING_KEY).is_err());
+    }
+
+    #[test]
+    fn rejects_a_token_with_another_key() {
+        let token = encode_claims(&claims(300), "other-signing-key").unwrap();
+
+        assert!(decode_claims(&token, SERVICE, SIGNING_KEY).is_err());
+    }
+
+    #[test]
+    fn rejects_a_token_with_another_algorithm() {
+        for algorithm in [Algorithm::HS384, Algorithm::HS512] {
+            let token = jsonwebtoken::encode(
+                &Header::new(algorithm),
+                &claims(300),
+                &EncodingKey::from_secret(SIGNING_KEY.as_bytes()),
+            )
+            .unwrap();
+
+            assert!(decode_claims(&token, SERVICE, SIGNING_KEY).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_an_unsigned_token() {
+        let token = encode_claims(&claims(300), SIGNING_KEY).unwrap();
+        let (unsigned, _) = token.rsplit_once('.').unwrap();
+        let header = "eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0";
+        let (_, payload) = unsigned.split_once('.').unwrap();
+
+        assert!(decode_claims(&format!("{header}.{payload}."), SERVICE, SIGNING_KEY).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use sqlx::Pool;
+
+use crate::{
+    db::{self, repository_repository, DB},
+    registry_error::RegistryResult,
+    services::authorization_service,
+    types::visibility::Visibility,
+};
+
+pub async fn set_visibility(
+    db_pool: &Pool<DB>,
+    username: &str,
+    repository: &str,
+    visibility: Visibility,
+) -> RegistryResult<()> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+
+    authorization_service::verify_owner(&mut transaction, username, repository).await?;
+    repository_repository::update_is_public(&mut transaction, repository, visibility.is_public())
+        .await?;
+
+    transaction.commit().await?;
+
+    info!("Set visibility of {repository} to {visibility:?}");
+    Ok(())
+}
@@ -1,9 +1,18 @@
 use std::{
-    fs::{self, File},
-    io::{Read, Write},
+    fs, io,
     path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
 };
 
+use rocket::{
+    data::DataStream,
+    tokio::{
+        self,
+        fs::{File, OpenOptions},
+        io::{AsyncSeekExt, AsyncWrite, AsyncWriteExt, SeekFrom},
+    },
+};
 use sqlx::{Pool, Transaction};
 use uuid::Uuid;
 
@@ -13,9 +22,13 @@ use crate::{
         self, blob_repository, owner_repository, repository_repository, upload_session_repository,
         DB,
     },
-    models::{repository::Repository, upload_session::UploadSession},
+    models::{blob::Blob, repository::Repository, upload_session::UploadSession},
     registry_error::{RegistryError, RegistryResult},
-    types::session_id::SessionId,
+    storage::{blob_path, Storage, StorageReader},
+    types::{
+        digest::{Digest, DigestAlgorithm, DigestHasher, ResumableSha256, SHA256_BLOCK_SIZE},
+        session_id::SessionId,
+    },
 };
 
 const PG_UNIQUE_CONSTRAINT_ERROR_CODE: &str = "23505";
@@ -25,6 +38,81 @@ pub async fn create_session(
     username: &str,
     namespace: &str,
 ) -> RegistryResult<SessionId> {
+    let (mut transaction, repository) =
+        find_or_create_repository(db_pool, username, namespace).await?;
+
+    let session =
+        upload_session_repository::insert(&mut transaction, None, 0, &repository.namespace_name)
+            .await?;
+
+    transaction.commit().await?;
+
+    Ok(session.id.into())
+}
+
+/// Links a blob that has already been uploaded to the `from` repository into `namespace`.
+/// Returns `None` if the blob can't be mounted, in which case the client has to upload it.
+pub async fn mount_blob(
+    db_pool: &Pool<DB>,
+    storage: &Storage,
+    username: &str,
+    namespace: &str,
+    from: &str,
+    digest: &Digest,
+) -> RegistryResult<Option<Blob>> {
+    let mut transaction = db::new_transaction(db_pool).await?;
+    let source =
+        blob_repository::find_by_repository_and_digest(&mut transaction, from, &digest.to_string())
+            .await?;
+    transaction.commit().await?;
+
+    let Some(source) = source else {
+        info!("Blob {digest} does not exist in {from}, it can't be mounted");
+        return Ok(None);
+    };
+
+    let (mut transaction, repository) =
+        find_or_create_repository(db_pool, username, namespace).await?;
+
+    db::lock_digest(&mut transaction, &digest.to_string()).await?;
+
+    let Some(metadata) = storage.stat(&blob_path(digest)).await? else {
+        error!("Blob {digest} exists in {from} but its file is missing");
+        return Ok(None);
+    };
+
+    let existing = blob_repository::find_by_repository_and_digest(
+        &mut transaction,
+        &repository.namespace_name,
+        &digest.to_string(),
+    )
+    .await?;
+
+    let blob = match existing {
+        Some(blob) => blob,
+        None => {
+            info!("Mounting blob {digest} from {from} into {namespace}");
+            blob_repository::insert(
+                &mut transaction,
+                &repository.namespace_name,
+                &digest.to_string(),
+                source.size.unwrap_or(metadata.size as i64),
+                source.media_type.as_deref(),
+            )
+            .await?
+        }
+    };
+
+    transaction.commit().await?;
+
+    Ok(Some(blob))
+}
+
+async fn find_or_create_repository<'a>(
+    db_pool: &'a Pool<DB>,
+    username: &str,
+    namespace: &str,
+) -> RegistryResult<(Transaction<'a, DB>, Repository)> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
     let owner =
@@ -41,18 +129,12 @@ pub async fn create_session(
                 // Reset the transaction as the old one is cancelled.
                 transaction.rollback().await?;
                 transaction = db::new_transaction(db_pool).await?;
-                get_repository_if_exists(err, &mut transaction, &namespace).await?
+                get_repository_if_exists(err, &mut transaction, namespace).await?
             }
             Err(e) => return Err(e),
         };
 
-    let session =
-        upload_session_repository::insert(&mut transaction, None, 0, &repository.namespace_name)
-            .await?;
-
-    transaction.commit().await?;
-
-    Ok(session.id.into())
+    Ok((transaction, repository))
 }
 
 async fn get_repository_if_exists(
@@ -64,12 +146,12 @@ async fn get_repository_if_exists(
         if let Some(code) = db_err.code() {
             if code.to_string().as_str() == PG_UNIQUE_CONSTRAINT_ERROR_CODE {
                 // The repository already exists, let's get it!
-                return Ok(repository_repository::find_by_name(transaction, namespace).await?);
+                return repository_repository::find_by_name(transaction, namespace).await;
             }
         }
     }
 
-    return Err(RegistryError::SqlxError(err));
+    Err(RegistryError::SqlxError(err))
 }
 
 pub async fn upload_blob(
@@ -77,8 +159,9 @@ pub async fn upload_blob(
     namespace: &str,
     session_id: SessionId,
     config: &Config,
-    blob: Vec<u8>,
+    blob: DataStream<'_>,
     expected_start_byte: Option<usize>,
+    expected_length: Option<usize>,
 ) -> RegistryResult<UploadSession> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
@@ -106,18 +189,56 @@ pub async fn upload_blob(
         }
     }
 
-    let digest = sha256::digest(blob.as_slice());
+    let staging_path = get_blob_upload_path(config, session.id)?;
+    let starting_byte_index = session.starting_byte_index as u64;
+    let mut file = open_staging_file(&staging_path, starting_byte_index).await?;
 
-    upload_session_repository::set_digest(&mut transaction, session.id, &digest).await?;
+    // Chunks extend the running hash of the default algorithm, it is only used if the client
+    // finalizes the upload with the same algorithm.
+    let hasher = resume_sha256(
+        &mut transaction,
+        session.id,
+        starting_byte_index,
+        &staging_path,
+    )
+    .await?;
+    let mut writer = HashingWriter::new(&mut file, hasher);
+    let written = blob.stream_to(&mut writer).await?;
+    writer.flush().await?;
+    let hasher = writer.into_hasher();
+
+    if !written.complete {
+        warn!("Blob upload exceeded the blob size limit, discarding chunk");
+        file.set_len(starting_byte_index).await?;
+        return Err(RegistryError::BlobTooLarge);
+    }
+
+    if let Some(length) = expected_length {
+        if length as u64 != written.written {
+            warn!(
+                "Got invalid content_length value ({length}) when blob length was ({})",
+                written.written
+            );
+            file.set_len(starting_byte_index).await?;
+            return Err(RegistryError::InvalidContentLength);
+        }
+    }
+
+    // The digest of the upload so far marks the session as uploaded to.
+    upload_session_repository::set_digest(
+        &mut transaction,
+        session.id,
+        &hasher.clone().finalize().to_string(),
+    )
+    .await?;
 
     let Some(next_starting_byte_index) = session
         .starting_byte_index
-        .checked_add_unsigned(blob.len() as u32)
+        .checked_add_unsigned(written.written)
     else {
         error!(
             "Failed adding together starting byte index {} with blob length {}",
-            session.starting_byte_index,
-            blob.len()
+            session.starting_byte_index, written.written
         );
         return Err(RegistryError::InvalidState);
     };
@@ -129,160 +250,323 @@ pub async fn upload_blob(
         &session.repository,
     )
     .await?;
-
-    save_file(config, &digest, blob)?;
+    upload_session_repository::set_sha256_state(
+        &mut transaction,
+        new_session.id,
+        &hasher.saved_state(),
+    )
+    .await?;
+    let new_staging_path = get_blob_upload_path(config, new_session.id)?;
 
     transaction.commit().await?;
 
+    // The staging file always follows the latest session of the upload, it is only moved once
+    // that session has been committed.
+    fs::rename(staging_path, new_staging_path)?;
+
     Ok(new_session)
 }
 
 pub async fn finish_blob_upload(
     db_pool: &Pool<DB>,
     config: &Config,
+    storage: &Storage,
     namespace: &str,
     session_id: SessionId,
-    digest: &str,
+    received_digest: &Digest,
 ) -> RegistryResult<Uuid> {
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    let mut digests = vec![];
-
-    let (previous_id, first_digest) =
-        get_session(&mut transaction, &namespace, session_id.clone()).await?;
-    if let Some(digest) = first_digest {
-        digests.push(digest);
+    let session = get_session(&mut transaction, namespace, session_id.clone()).await?;
+    if session.digest.is_some() {
+        error!("The session to finish ({session_id}) has already been uploaded to");
+        return Err(RegistryError::InvalidState);
     }
 
-    let mut curr_id = previous_id;
-    while let Some(id) = curr_id {
-        let (previous_id, digest) = get_session(&mut transaction, &namespace, id.clone()).await?;
-
-        let Some(digest) = digest else {
-            error!("Upload session with ID {:?} has digest set to none!", id);
-            return Err(RegistryError::InvalidState);
-        };
-
-        digests.push(digest);
-        curr_id = previous_id;
+    let staging_path = get_blob_upload_path(config, session_id.clone().into())?;
+    if !staging_path.exists() {
+        info!("No data has been uploaded for session {session_id}, creating empty blob");
+        File::create(&staging_path).await?;
     }
 
-    let mut data = vec![];
-    for digest in digests.into_iter().rev() {
-        let mut chunk = get_file_data(config, &digest)?;
-        data.append(&mut chunk);
-    }
+    let algorithm = received_digest.algorithm();
+    let calculated_digest = match algorithm {
+        DigestAlgorithm::Sha256 => resume_sha256(
+            &mut transaction,
+            session.id,
+            session.starting_byte_index as u64,
+            &staging_path,
+        )
+        .await?
+        .finalize(),
+        _ => hash_file(&staging_path, algorithm).await?,
+    };
 
-    let received_digest = digest
-        .strip_prefix("sha256:")
-        .ok_or(RegistryError::UnsupportedDigest)?;
-    let calculated_digest = sha256::digest(data.as_slice());
-    if received_digest != calculated_digest {
+    if *received_digest != calculated_digest {
         error!("Received digest {received_digest} does not match the calculated digest {calculated_digest}");
         return Err(RegistryError::InvalidDigest);
     }
 
     upload_session_repository::set_finished(&mut transaction, session_id.into(), namespace).await?;
 
-    let prefixed_digest = format!("sha256:{}", calculated_digest);
-    let blob = blob_repository::insert(&mut transaction, namespace, &prefixed_digest).await?;
-    save_blob_file(config, &calculated_digest, data.as_slice()).map_err(|err| {
-        error!("Failed to save combined data to blob file, err: {err:?}");
-        err
-    })?;
+    db::lock_digest(&mut transaction, &calculated_digest.to_string()).await?;
+
+    let size = fs::metadata(&staging_path)?.len() as i64;
+    let existing = blob_repository::find_by_repository_and_digest(
+        &mut transaction,
+        namespace,
+        &calculated_digest.to_string(),
+    )
+    .await?;
+    let blob = match existing {
+        Some(blob) => {
+            info!("Blob {calculated_digest} already exists in {namespace}");
+            blob
+        }
+        None => {
+            blob_repository::insert(
+                &mut transaction,
+                namespace,
+                &calculated_digest.to_string(),
+                size,
+                None,
+            )
+            .await?
+        }
+    };
+    move_to_blob_file(storage, &staging_path, &calculated_digest)
+        .await
+        .map_err(|err| {
+            error!("Failed to move uploaded data to blob file, err: {err:?}");
+            err
+        })?;
 
     transaction.commit().await?;
 
     Ok(blob.id)
 }
 
-fn get_blob_upload_dir(config: &Config) -> RegistryResult<PathBuf> {
-    let file_name = format!("{}/uploads/blobs/sha256", config.storage_directory);
-    let path = Path::new(&file_name);
-    fs::create_dir_all(path)?;
+/// Stores the content read from `reader` as the blob `digest` in `namespace`, going through the
+/// same upload session as a monolithic upload so that the digest is verified.
+pub async fn import_blob(
+    db_pool: &Pool<DB>,
+    config: &Config,
+    storage: &Storage,
+    username: &str,
+    namespace: &str,
+    digest: &Digest,
+    mut reader: StorageReader,
+) -> RegistryResult<Uuid> {
+    let session_id = create_session(db_pool, username, namespace).await?;
 
-    Ok(path.into())
+    let staging_path = get_blob_upload_path(config, session_id.clone().into())?;
+    let mut file = File::create(&staging_path).await?;
+    tokio::io::copy(&mut reader, &mut file).await?;
+    file.flush().await?;
+
+    finish_blob_upload(db_pool, config, storage, namespace, session_id, digest).await
 }
 
-fn get_blob_upload_filename(config: &Config, digest: &str) -> RegistryResult<PathBuf> {
-    let dir = get_blob_upload_dir(config)?;
-    let mut path = dir.join(digest);
-    path.set_extension("tar.gz");
+/// Records blobs that manifests in `namespace` refer to before their content is available, so
+/// that the manifests can be stored. The content is added later by uploading the blob.
+pub async fn declare_blobs(
+    db_pool: &Pool<DB>,
+    username: &str,
+    namespace: &str,
+    descriptors: &[(&str, i64, &str)],
+) -> RegistryResult<()> {
+    let (mut transaction, repository) =
+        find_or_create_repository(db_pool, username, namespace).await?;
+
+    // Digests are locked in a fixed order, so that concurrent declarations can't deadlock.
+    let mut descriptors = descriptors.to_vec();
+    descriptors.sort();
+    descriptors.dedup_by_key(|(digest, _, _)| *digest);
+
+    for (digest, size, media_type) in descriptors {
+        db::lock_digest(&mut transaction, digest).await?;
+
+        let existing = blob_repository::find_by_repository_and_digest(
+            &mut transaction,
+            &repository.namespace_name,
+            digest,
+        )
+        .await?;
+        if existing.is_none() {
+            info!("Declaring blob {digest} in {namespace}");
+            blob_repository::insert(
+                &mut transaction,
+                &repository.namespace_name,
+                digest,
+                size,
+                Some(media_type),
+            )
+            .await?;
+        }
+    }
 
-    Ok(path)
+    transaction.commit().await?;
+
+    Ok(())
 }
 
-fn save_file(config: &Config, digest: &str, blob: Vec<u8>) -> RegistryResult<()> {
-    let file_path = get_blob_upload_filename(config, digest)?;
-    let mut file = fs::File::create(file_path)?;
+async fn open_staging_file(path: &Path, starting_byte_index: u64) -> RegistryResult<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .await?;
+
+    let length = file.metadata().await?.len();
+    if length < starting_byte_index {
+        error!("Staging file {path:?} is shorter ({length}) than the upload session claims ({starting_byte_index})");
+        return Err(RegistryError::InvalidState);
+    }
+
+    // Discard whatever might be left from a previously failed attempt at uploading the chunk.
+    file.set_len(starting_byte_index).await?;
+    file.seek(SeekFrom::Start(starting_byte_index)).await?;
+
+    Ok(file)
+}
 
-    file.write_all(&blob)?;
+async fn hash_file(path: &Path, algorithm: DigestAlgorithm) -> RegistryResult<Digest> {
+    let mut file = File::open(path).await?;
+    let mut writer = HashingWriter::new(tokio::io::sink(), algorithm.hasher());
+    tokio::io::copy(&mut file, &mut writer).await?;
 
-    Ok(())
+    Ok(writer.into_hasher().finalize())
 }
 
-fn get_file_data(config: &Config, digest: &str) -> RegistryResult<Vec<u8>> {
-    let file_path = get_blob_upload_filename(config, digest)?;
-    let mut file = File::open(file_path)?;
+/// Resumes the SHA-256 state saved for the upload session starting at `starting_byte_index` and
+/// hashes the rest of the staging file. That is less than a block, unless the session has no
+/// saved state and the file is hashed from the start.
+async fn resume_sha256(
+    transaction: &mut Transaction<'_, DB>,
+    session_id: Uuid,
+    starting_byte_index: u64,
+    staging_path: &Path,
+) -> RegistryResult<ResumableSha256> {
+    let hashed_length = starting_byte_index - starting_byte_index % SHA256_BLOCK_SIZE;
+    let hasher = upload_session_repository::find_sha256_state(transaction, session_id)
+        .await?
+        .and_then(|state| ResumableSha256::resume(&state, hashed_length));
+    let (hasher, offset) = match hasher {
+        Some(hasher) => (hasher, hashed_length),
+        None => (ResumableSha256::new(), 0),
+    };
+
+    let mut file = File::open(staging_path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut writer = HashingWriter::new(tokio::io::sink(), hasher);
+    tokio::io::copy(&mut file, &mut writer).await?;
+
+    Ok(writer.into_hasher())
+}
+
+fn get_blob_upload_dir(config: &Config) -> RegistryResult<PathBuf> {
+    let file_name = format!("{}/uploads/blobs", config.storage_directory);
+    let path = Path::new(&file_name);
+    fs::create_dir_all(path)?;
 
-    let mut buf = vec![];
-    file.read_to_end(&mut buf)?;
+    Ok(path.into())
+}
 
-    Ok(buf)
+pub fn get_blob_upload_path(config: &Config, session_id: Uuid) -> RegistryResult<PathBuf> {
+    let dir = get_blob_upload_dir(config)?;
+
+    Ok(dir.join(session_id.to_string()))
 }
 
 async fn get_session(
     transaction: &mut Transaction<'_, DB>,
     namespace: &str,
     session_id: SessionId,
-) -> RegistryResult<(Option<SessionId>, Option<String>)> {
+) -> RegistryResult<UploadSession> {
     let Some(session) = upload_session_repository::find_by_repository_and_id(
         transaction,
         namespace,
-        session_id.into(),
+        session_id.clone().into(),
     )
     .await?
     else {
-        error!("Previous session not found!");
-        return Err(RegistryError::InvalidState);
+        warn!("Upload session {session_id} not found in {namespace}");
+        return Err(RegistryError::SessionNotFound);
     };
 
-    Ok((session.previous_session.map(|s| s.into()), session.digest))
+    Ok(session)
 }
 
-fn get_blob_path_dir(config: &Config) -> PathBuf {
-    Path::new(&config.storage_directory).join("blobs/sha256")
+async fn move_to_blob_file(
+    storage: &Storage,
+    staging_path: &Path,
+    digest: &Digest,
+) -> RegistryResult<()> {
+    let path = blob_path(digest);
+
+    if storage.stat(&path).await?.is_some() {
+        info!("Blob already exists at path {path}");
+        fs::remove_file(staging_path)?;
+        return Ok(());
+    }
+
+    info!("Moving blob to {path}");
+    storage.put_file(&path, staging_path).await?;
+
+    Ok(())
 }
 
-fn to_file_path(dir_path: PathBuf, digest: &str) -> PathBuf {
-    let mut file_path = dir_path.join(digest);
-    file_path.set_extension("tar.gz");
-    file_path
+trait Hasher {
+    fn update(&mut self, data: &[u8]);
 }
 
-pub fn get_blob_file_path(config: &Config, digest: &str) -> PathBuf {
-    let dir = get_blob_path_dir(config);
-    to_file_path(dir, digest)
+impl Hasher for DigestHasher {
+    fn update(&mut self, data: &[u8]) {
+        DigestHasher::update(self, data)
+    }
 }
 
-fn save_blob_file(config: &Config, digest: &str, data: &[u8]) -> RegistryResult<()> {
-    let dir_path = get_blob_path_dir(config);
+impl Hasher for ResumableSha256 {
+    fn update(&mut self, data: &[u8]) {
+        ResumableSha256::update(self, data)
+    }
+}
 
-    if !dir_path.exists() {
-        info!("Blob dir path doesn't exist, creating at {dir_path:?}");
-        fs::create_dir_all(dir_path.as_path())?;
+/// Writer that hashes everything written through it.
+struct HashingWriter<W, H> {
+    inner: W,
+    hasher: H,
+}
+
+impl<W, H> HashingWriter<W, H> {
+    fn new(inner: W, hasher: H) -> Self {
+        Self { inner, hasher }
     }
 
-    let file_path = to_file_path(dir_path, digest);
+    fn into_hasher(self) -> H {
+        self.hasher
+    }
+}
 
-    if file_path.exists() {
-        info!("Blob already exists at path {file_path:?}");
-        return Ok(());
+impl<W: AsyncWrite + Unpin, H: Hasher + Unpin> AsyncWrite for HashingWriter<W, H> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.hasher.update(&buf[..written]);
+        }
+        poll
     }
 
-    info!("Saving blob to file {file_path:?}");
-    let mut file = File::create_new(file_path)?;
-    file.write_all(data)?;
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
 
-    Ok(())
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
 }
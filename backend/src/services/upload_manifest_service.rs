@@ -1,78 +1,234 @@
 use rocket::http::ContentType;
 use sqlx::{Pool, Transaction};
-use std::{
-    fs,
-    io::Write,
-    path::{Path, PathBuf},
-};
+use std::iter;
 use uuid::Uuid;
 
 use crate::{
-    config::Config,
-    db::{self, blob_repository, manifest_layer_repository, manifest_repository, DB},
-    models::manifest::Manifest,
+    db::{
+        self, blob_repository, manifest_child_repository, manifest_layer_repository,
+        manifest_referrer_repository, manifest_repository, tag_repository, DB,
+    },
+    models::{blob::Blob, manifest::Manifest},
     registry_error::{RegistryError, RegistryResult},
-    types::manifest::{DockerImageManifestV2, APPLICATION_CONTENT_TYPE_TOP},
+    storage::{blob_path, manifest_path, Storage},
+    types::{
+        digest::{Digest, DigestAlgorithm},
+        manifest::{
+            media_type, DockerImageManifestV2, FatManifest, ParsedManifest,
+            APPLICATION_CONTENT_TYPE_TOP,
+        },
+        reference::Reference,
+    },
 };
 
 pub async fn upload_manifest(
     db_pool: &Pool<DB>,
-    config: &Config,
+    storage: &Storage,
     namespace: &str,
-    reference: &str,
+    reference: &Reference,
     manifest_type: &ContentType,
     data: Vec<u8>,
-) -> RegistryResult<(Uuid, String, Option<String>)> {
-    let calculated_digest = format!("sha256:{}", sha256::digest(data.as_slice()));
+) -> RegistryResult<(Digest, Option<String>)> {
+    // Manifests pushed by digest are addressed with the algorithm chosen by the client.
+    let algorithm = match reference {
+        Reference::Digest(digest) => digest.algorithm(),
+        Reference::Tag(_) => DigestAlgorithm::Sha256,
+    };
+    let calculated_digest = algorithm.digest(&data);
 
-    let image_manifest = DockerImageManifestV2::parse(manifest_type, data.clone())?;
+    let parsed_manifest = ParsedManifest::parse(manifest_type, &data)?;
 
     let mut transaction = db::new_transaction(db_pool).await?;
 
-    let image_blob = blob_repository::find_by_repository_and_digest(
+    verify_references(&mut transaction, storage, namespace, &parsed_manifest).await?;
+
+    let config_blob_id = match &parsed_manifest {
+        ParsedManifest::Image(image_manifest) => {
+            let config_blob = blob_repository::find_by_repository_and_digest(
+                &mut transaction,
+                namespace,
+                &image_manifest.config.digest,
+            )
+            .await?
+            .ok_or_else(|| {
+                RegistryError::ManifestBlobUnknown(vec![image_manifest.config.digest.clone()])
+            })?;
+            record_media_type(
+                &mut transaction,
+                &config_blob,
+                &image_manifest.config.media_type,
+            )
+            .await?;
+            Some(config_blob.id)
+        }
+        ParsedManifest::Index(_) => None,
+    };
+
+    let manifest = save_manifest(
         &mut transaction,
         namespace,
-        &image_manifest.config.digest,
+        manifest_type,
+        config_blob_id,
+        &calculated_digest,
     )
-    .await?
-    .ok_or(RegistryError::InvalidDigest)?;
+    .await?;
 
-    let manifest = if reference.starts_with("sha256:") {
-        info!("Reference assumed to be digest: {reference}");
-        save_manifest_by_digest(
-            &mut transaction,
-            namespace,
-            manifest_type,
-            image_blob.id,
-            &calculated_digest,
-        )
-        .await?
-    } else {
-        info!("Reference assumed to be tag: {reference}");
-        save_manifest_by_tag(
+    match reference {
+        Reference::Digest(digest) => {
+            info!("Reference identified as digest: {digest}");
+            if *digest != calculated_digest {
+                error!("Manifest pushed as {digest} has digest {calculated_digest}");
+                return Err(RegistryError::InvalidDigest);
+            }
+        }
+        Reference::Tag(tag) => {
+            info!("Reference identified as tag: {tag}");
+            tag_repository::upsert(&mut transaction, namespace, tag, manifest.id).await?;
+        }
+    }
+
+    match &parsed_manifest {
+        ParsedManifest::Image(image_manifest) => {
+            save_layers(&mut transaction, namespace, manifest.id, image_manifest).await?
+        }
+        ParsedManifest::Index(index) => {
+            save_children(&mut transaction, namespace, manifest.id, index).await?
+        }
+    }
+
+    if let Some(subject) = parsed_manifest.subject() {
+        info!(
+            "Manifest {calculated_digest} refers to subject {}",
+            subject.digest
+        );
+        let annotations = parsed_manifest
+            .annotations()
+            .map(serde_json::to_value)
+            .transpose()?;
+        manifest_referrer_repository::upsert(
             &mut transaction,
-            namespace,
-            reference,
-            manifest_type,
-            image_blob.id,
-            &calculated_digest,
+            manifest.id,
+            &subject.digest,
+            &media_type(manifest_type),
+            parsed_manifest.referrer_artifact_type(),
+            data.len() as i64,
+            annotations,
         )
-        .await?
-    };
+        .await?;
+    }
+
+    db::lock_digest(&mut transaction, &calculated_digest.to_string()).await?;
+    save_file(storage, &calculated_digest, data).await?;
+
+    transaction.commit().await?;
+
+    Ok((
+        calculated_digest,
+        parsed_manifest.subject().map(|s| s.digest.clone()),
+    ))
+}
+
+/// Checks that everything the manifest refers to has already been pushed with the size declared
+/// in the manifest, so that broken pushes are rejected instead of producing unpullable images.
+/// All missing blobs and manifests are reported at once.
+async fn verify_references(
+    transaction: &mut Transaction<'_, DB>,
+    storage: &Storage,
+    namespace: &str,
+    parsed_manifest: &ParsedManifest,
+) -> RegistryResult<()> {
+    let mut missing = vec![];
+
+    match parsed_manifest {
+        ParsedManifest::Image(image_manifest) => {
+            let descriptors =
+                iter::once((&image_manifest.config.digest, image_manifest.config.size)).chain(
+                    image_manifest
+                        .layers
+                        .iter()
+                        .map(|layer| (&layer.digest, layer.size)),
+                );
+
+            for (digest, size) in descriptors {
+                let blob =
+                    blob_repository::find_by_repository_and_digest(transaction, namespace, digest)
+                        .await?;
+
+                match blob {
+                    Some(blob) => {
+                        let stored_size = match blob.size {
+                            Some(stored_size) => stored_size as u64,
+                            None => stored_size(storage, &blob_path(&Digest::parse(digest)?))
+                                .await?
+                                .ok_or(RegistryError::BlobFileNotFound)?,
+                        };
+                        verify_size(digest, size, stored_size)?;
+                    }
+                    None => missing.push(digest.clone()),
+                }
+            }
+        }
+        ParsedManifest::Index(index) => {
+            for descriptor in index.manifests.iter() {
+                let child = manifest_repository::find_by_repository_and_digest(
+                    transaction,
+                    namespace,
+                    &descriptor.digest,
+                )
+                .await?;
+
+                match child {
+                    Some(_) => {
+                        let path = manifest_path(&Digest::parse(&descriptor.digest)?);
+                        let stored_size = stored_size(storage, &path)
+                            .await?
+                            .ok_or(RegistryError::ManifestFileNotFound)?;
+                        verify_size(&descriptor.digest, descriptor.size, stored_size)?;
+                    }
+                    None => missing.push(descriptor.digest.clone()),
+                }
+            }
+        }
+    }
+
+    if !missing.is_empty() {
+        warn!("Manifest refers to content that does not exist in {namespace}: {missing:?}");
+        return Err(RegistryError::ManifestBlobUnknown(missing));
+    }
+
+    Ok(())
+}
+
+async fn stored_size(storage: &Storage, path: &str) -> RegistryResult<Option<u64>> {
+    Ok(storage.stat(path).await?.map(|metadata| metadata.size))
+}
+
+fn verify_size(digest: &str, declared_size: i64, size: u64) -> RegistryResult<()> {
+    if size != declared_size as u64 {
+        warn!("Manifest declares size {declared_size} for {digest} which has size {size}");
+        return Err(RegistryError::ManifestBlobSizeMismatch(digest.to_string()));
+    }
+
+    Ok(())
+}
 
+async fn save_layers(
+    transaction: &mut Transaction<'_, DB>,
+    namespace: &str,
+    manifest_id: Uuid,
+    image_manifest: &DockerImageManifestV2,
+) -> RegistryResult<()> {
     for layer in image_manifest.layers.iter() {
-        let blob = blob_repository::find_by_repository_and_digest(
-            &mut transaction,
-            namespace,
-            &layer.digest,
-        )
-        .await?
-        .ok_or(RegistryError::InvalidDigest)?; // TODO: This should probably return BLOB_UNKNOWN
+        let blob =
+            blob_repository::find_by_repository_and_digest(transaction, namespace, &layer.digest)
+                .await?
+                .ok_or_else(|| RegistryError::ManifestBlobUnknown(vec![layer.digest.clone()]))?;
+        record_media_type(transaction, &blob, &layer.media_type).await?;
 
         match manifest_layer_repository::find_by_manifest_and_blob(
-            &mut transaction,
-            manifest.id.clone(),
-            blob.id.clone(),
+            transaction,
+            manifest_id,
+            blob.id,
         )
         .await?
         {
@@ -81,8 +237,8 @@ pub async fn upload_manifest(
             }
             None => {
                 manifest_layer_repository::insert(
-                    &mut transaction,
-                    manifest.id,
+                    transaction,
+                    manifest_id,
                     blob.id,
                     layer.media_type.clone(),
                     layer.size,
@@ -92,77 +248,87 @@ pub async fn upload_manifest(
         }
     }
 
-    save_file(manifest.id, config, data)?;
+    Ok(())
+}
 
-    transaction.commit().await?;
+/// Blobs are uploaded without a media type, the first manifest that refers to a blob tells which
+/// `Content-Type` it is served with.
+async fn record_media_type(
+    transaction: &mut Transaction<'_, DB>,
+    blob: &Blob,
+    media_type: &str,
+) -> RegistryResult<()> {
+    if blob.media_type.is_none() {
+        blob_repository::set_media_type(transaction, blob.id, media_type).await?;
+    }
 
-    Ok((
-        manifest.id,
-        calculated_digest,
-        image_manifest.subject.map(|s| s.digest),
-    ))
+    Ok(())
 }
 
-async fn save_manifest_by_tag(
+/// Links the index to each of the manifests it lists, which must already have been pushed.
+async fn save_children(
     transaction: &mut Transaction<'_, DB>,
     namespace: &str,
-    tag: &str,
-    manifest_type: &ContentType,
-    image_blob_id: Uuid,
-    calculated_digest: &str,
-) -> RegistryResult<Manifest> {
-    let manifest =
-        match manifest_repository::find_by_repository_and_tag(transaction, namespace, Some(tag))
-            .await?
-        {
-            Some(m) => {
-                warn!("Manifest already exists, overwriting");
-                m
-            }
-            None => {
-                let content_type = manifest_type.to_string();
-                let Some(content_type_sub) = content_type.strip_prefix("application/") else {
-                    error!("Media type does not start with `application/`! (Got {manifest_type})");
-                    return Err(RegistryError::InvalidManifestSchema(
-                        "Expected application/".to_string(),
-                    ));
-                };
-                manifest_repository::insert(
-                    transaction,
-                    namespace,
-                    image_blob_id,
-                    Some(tag),
-                    &calculated_digest,
-                    APPLICATION_CONTENT_TYPE_TOP,
-                    content_type_sub,
-                )
-                .await?
-            }
+    index_id: Uuid,
+    index: &FatManifest,
+) -> RegistryResult<()> {
+    for descriptor in index.manifests.iter() {
+        let Some(child) = manifest_repository::find_by_repository_and_digest(
+            transaction,
+            namespace,
+            &descriptor.digest,
+        )
+        .await?
+        else {
+            warn!(
+                "Index refers to manifest {} which does not exist in {namespace}",
+                descriptor.digest
+            );
+            return Err(RegistryError::ManifestBlobUnknown(vec![descriptor
+                .digest
+                .clone()]));
         };
 
-    Ok(manifest)
+        let platform = descriptor
+            .platform
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
+
+        manifest_child_repository::upsert(
+            transaction,
+            index_id,
+            child.id,
+            &descriptor.media_type,
+            descriptor.size,
+            platform,
+        )
+        .await?;
+    }
+
+    Ok(())
 }
 
-async fn save_manifest_by_digest(
+async fn save_manifest(
     transaction: &mut Transaction<'_, DB>,
     namespace: &str,
     manifest_type: &ContentType,
-    image_blob_id: Uuid,
-    calculated_digest: &str,
+    config_blob_id: Option<Uuid>,
+    calculated_digest: &Digest,
 ) -> RegistryResult<Manifest> {
-    let manifest = match manifest_repository::find_first_by_repository_and_digest(
+    let manifest = match manifest_repository::find_by_repository_and_digest(
         transaction,
         namespace,
-        calculated_digest,
+        &calculated_digest.to_string(),
     )
     .await?
     {
         Some(m) => {
-            warn!("Manifest already exists, overwriting");
+            info!("Manifest {calculated_digest} already exists in {namespace}");
             m
         }
         None => {
-            let content_type = manifest_type.to_string();
+            let content_type = media_type(manifest_type);
             let Some(content_type_sub) = content_type.strip_prefix("application/") else {
                 error!("Media type does not start with `application/`! (Got {manifest_type})");
                 return Err(RegistryError::InvalidManifestSchema(
@@ -172,9 +338,8 @@ async fn save_manifest_by_digest(
             manifest_repository::insert(
                 transaction,
                 namespace,
-                image_blob_id,
-                None,
-                &calculated_digest,
+                config_blob_id,
+                &calculated_digest.to_string(),
                 APPLICATION_CONTENT_TYPE_TOP,
                 content_type_sub,
             )
@@ -185,38 +350,22 @@ async fn save_manifest_by_digest(
     Ok(manifest)
 }
 
-fn get_manifests_dir(config: &Config) -> PathBuf {
-    Path::new(&config.storage_directory).join("manifests")
-}
-
-fn to_file_path(dir_path: PathBuf, manifest_id: Uuid) -> PathBuf {
-    let mut file_path = dir_path.join(manifest_id.to_string());
-    file_path.set_extension("json");
-    file_path
-}
-
-pub fn get_manifest_file_path(config: &Config, manifest_id: Uuid) -> PathBuf {
-    let dir = get_manifests_dir(config);
-    to_file_path(dir, manifest_id)
-}
-
-fn save_file(manifest_id: Uuid, config: &Config, data: Vec<u8>) -> RegistryResult<()> {
-    let manifests_dir = get_manifests_dir(config);
+/// Manifest files are immutable and named after their digest, so an existing file only needs to
+/// be replaced if it was corrupted.
+pub async fn save_file(storage: &Storage, digest: &Digest, data: Vec<u8>) -> RegistryResult<()> {
+    let path = manifest_path(digest);
 
-    info!("Creating directories {manifests_dir:?}");
-    fs::create_dir_all(manifests_dir.clone())?;
-
-    let file_path = to_file_path(manifests_dir, manifest_id);
+    if let Some(existing) = storage.read(&path).await? {
+        if digest.algorithm().digest(&existing) == *digest {
+            info!("Manifest already exists at path {path}");
+            return Ok(());
+        }
 
-    if file_path.exists() {
-        info!("Manifest already exists at path {file_path:?}");
-        return Ok(());
+        warn!("Manifest at path {path} does not match digest {digest}, replacing it");
     }
 
-    info!("Creating file manifest file {file_path:?}");
-    let mut file = fs::File::create(file_path)?;
-
-    file.write_all(&data)?;
+    info!("Creating manifest file {path}");
+    storage.put(&path, data).await?;
 
     Ok(())
 }
@@ -0,0 +1,133 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use rocket::tokio::{
+    fs::{self, File},
+    io::{AsyncSeekExt, AsyncWriteExt, SeekFrom},
+};
+use uuid::Uuid;
+
+use crate::registry_error::RegistryResult;
+
+use super::{StorageDriver, StorageMetadata, StorageReader};
+
+/// Stores content as files below a local directory, using the storage paths as file paths.
+pub struct FilesystemDriver {
+    root: PathBuf,
+}
+
+impl FilesystemDriver {
+    pub fn new(root: &str) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn full_path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    async fn create_parent_dir(path: &Path) -> RegistryResult<()> {
+        if let Some(dir_path) = path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl StorageDriver for FilesystemDriver {
+    /// The file is written next to its destination and renamed into place.
+    async fn put(&self, path: &str, data: Vec<u8>) -> RegistryResult<()> {
+        let file_path = self.full_path(path);
+        Self::create_parent_dir(&file_path).await?;
+
+        let staging_path = file_path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+        let mut file = File::create(&staging_path).await?;
+        file.write_all(&data).await?;
+        file.sync_all().await?;
+
+        fs::rename(staging_path, file_path).await?;
+
+        Ok(())
+    }
+
+    async fn put_file(&self, path: &str, source: &Path) -> RegistryResult<()> {
+        let file_path = self.full_path(path);
+        Self::create_parent_dir(&file_path).await?;
+
+        fs::rename(source, file_path).await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, path: &str, offset: u64) -> RegistryResult<Option<StorageReader>> {
+        let mut file = match File::open(self.full_path(path)).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        if offset > 0 {
+            file.seek(SeekFrom::Start(offset)).await?;
+        }
+
+        Ok(Some(Box::pin(file)))
+    }
+
+    async fn stat(&self, path: &str) -> RegistryResult<Option<StorageMetadata>> {
+        match fs::metadata(self.full_path(path)).await {
+            Ok(metadata) => Ok(Some(StorageMetadata {
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            })),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn delete(&self, path: &str) -> RegistryResult<()> {
+        match fs::remove_file(self.full_path(path)).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> RegistryResult<Vec<(String, StorageMetadata)>> {
+        let mut entries = vec![];
+        let mut pending = vec![prefix.trim_end_matches('/').to_string()];
+
+        while let Some(dir) = pending.pop() {
+            let mut read_dir = match fs::read_dir(self.full_path(&dir)).await {
+                Ok(read_dir) => read_dir,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            while let Some(entry) = read_dir.next_entry().await? {
+                let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                    warn!("Skipping file with a non UTF-8 name {:?}", entry.path());
+                    continue;
+                };
+
+                let path = format!("{dir}/{name}");
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    pending.push(path);
+                } else {
+                    entries.push((
+                        path,
+                        StorageMetadata {
+                            size: metadata.len(),
+                            modified: metadata.modified()?,
+                        },
+                    ));
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
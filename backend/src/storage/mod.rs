@@ -0,0 +1,98 @@
+use std::{ops::Deref, path::Path, pin::Pin, sync::Arc, time::SystemTime};
+
+use rocket::tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+    config::{Config, StorageConfig},
+    registry_error::RegistryResult,
+    types::digest::Digest,
+};
+
+use self::{filesystem::FilesystemDriver, s3::S3Driver};
+
+pub mod filesystem;
+pub mod s3;
+
+pub const BLOBS_PREFIX: &str = "blobs";
+pub const MANIFESTS_PREFIX: &str = "manifests";
+
+pub type StorageReader = Pin<Box<dyn AsyncRead + Send>>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct StorageMetadata {
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// A backend that stores blobs and manifests. Paths are `/` separated and relative to the root of
+/// the storage, see [`blob_path`] and [`manifest_path`].
+#[rocket::async_trait]
+pub trait StorageDriver: Send + Sync {
+    /// Stores `data` at `path`, replacing anything stored there. Readers never observe partially
+    /// written content.
+    async fn put(&self, path: &str, data: Vec<u8>) -> RegistryResult<()>;
+
+    /// Moves the local file `source` to `path`, replacing anything stored there.
+    async fn put_file(&self, path: &str, source: &Path) -> RegistryResult<()>;
+
+    /// Streams the content stored at `path`, starting at byte `offset`.
+    async fn get(&self, path: &str, offset: u64) -> RegistryResult<Option<StorageReader>>;
+
+    async fn stat(&self, path: &str) -> RegistryResult<Option<StorageMetadata>>;
+
+    /// Removes the content stored at `path`, removing content that doesn't exist is not an error.
+    async fn delete(&self, path: &str) -> RegistryResult<()>;
+
+    /// Lists everything stored below `prefix`, including nested paths.
+    async fn list(&self, prefix: &str) -> RegistryResult<Vec<(String, StorageMetadata)>>;
+}
+
+/// The storage driver selected by the configuration, shared by all requests.
+#[derive(Clone)]
+pub struct Storage(Arc<dyn StorageDriver>);
+
+impl Storage {
+    pub fn new(config: &Config) -> Self {
+        match &config.storage {
+            StorageConfig::Filesystem => {
+                info!("Storing content in {}", config.storage_directory);
+                Self(Arc::new(FilesystemDriver::new(&config.storage_directory)))
+            }
+            StorageConfig::S3(s3_config) => {
+                info!(
+                    "Storing content in bucket {} of {}",
+                    s3_config.bucket, s3_config.endpoint
+                );
+                Self(Arc::new(S3Driver::new(s3_config.clone())))
+            }
+        }
+    }
+
+    /// Reads everything stored at `path`, only meant for small content such as manifests.
+    pub async fn read(&self, path: &str) -> RegistryResult<Option<Vec<u8>>> {
+        let Some(mut reader) = self.get(path, 0).await? else {
+            return Ok(None);
+        };
+
+        let mut data = vec![];
+        reader.read_to_end(&mut data).await?;
+
+        Ok(Some(data))
+    }
+}
+
+impl Deref for Storage {
+    type Target = dyn StorageDriver;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+pub fn blob_path(digest: &Digest) -> String {
+    digest.storage_path(BLOBS_PREFIX)
+}
+
+pub fn manifest_path(digest: &Digest) -> String {
+    digest.storage_path(MANIFESTS_PREFIX)
+}
@@ -0,0 +1,161 @@
+use std::convert::Infallible;
+
+use rocket::{
+    request::{self, FromRequest},
+    Request,
+};
+
+const ACCEPT_HEADER_NAME: &str = "Accept";
+const WILDCARD: &str = "*";
+
+/// The media types a client accepts. Clients may send several `Accept` headers, each with a comma
+/// separated list of media ranges. A request without any accepts every media type.
+#[derive(Debug, Clone)]
+pub struct AcceptedMediaTypes {
+    /// The media ranges together with whether they are rejected with a quality of zero.
+    ranges: Vec<(String, bool)>,
+}
+
+impl AcceptedMediaTypes {
+    pub fn from_headers<'a>(headers: impl Iterator<Item = &'a str>) -> Self {
+        Self {
+            ranges: headers
+                .flat_map(|header| header.split(','))
+                .filter_map(parse_media_range)
+                .collect(),
+        }
+    }
+
+    /// The most specific range that matches the media type decides, so `application/json` can
+    /// be accepted while `*/*;q=0` rejects everything else. Media types that no range matches
+    /// are only accepted if the client listed nothing but rejections.
+    pub fn accepts(&self, media_type: &str) -> bool {
+        let (top, sub) = media_type.split_once('/').unwrap_or((media_type, ""));
+
+        let best_match = self
+            .ranges
+            .iter()
+            .filter_map(|(range, rejected)| {
+                let (range_top, range_sub) = range.split_once('/').unwrap_or((range, WILDCARD));
+                let specificity = match (range_top, range_sub) {
+                    (WILDCARD, WILDCARD) => 0,
+                    (range_top, WILDCARD) if range_top.eq_ignore_ascii_case(top) => 1,
+                    (range_top, range_sub)
+                        if range_top.eq_ignore_ascii_case(top)
+                            && range_sub.eq_ignore_ascii_case(sub) =>
+                    {
+                        2
+                    }
+                    _ => return None,
+                };
+                // Rejections win over acceptances of the same specificity.
+                Some((specificity, *rejected))
+            })
+            .max();
+
+        match best_match {
+            Some((_, rejected)) => !rejected,
+            None => self.ranges.iter().all(|(_, rejected)| *rejected),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptedMediaTypes {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(Self::from_headers(req.headers().get(ACCEPT_HEADER_NAME)))
+    }
+}
+
+/// Parses a media range such as `application/json;q=0.5`, together with whether the client
+/// explicitly rejects it with a quality of zero.
+fn parse_media_range(range: &str) -> Option<(String, bool)> {
+    let mut parts = range.split(';');
+    let media_type = parts.next()?.trim();
+    if media_type.is_empty() {
+        return None;
+    }
+
+    let rejected = parts.any(|param| {
+        param
+            .trim()
+            .strip_prefix("q=")
+            .and_then(|quality| quality.trim().parse::<f32>().ok())
+            .is_some_and(|quality| quality <= 0.0)
+    });
+
+    Some((media_type.to_string(), rejected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+    const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+    const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+    fn accepted(headers: &[&str]) -> AcceptedMediaTypes {
+        AcceptedMediaTypes::from_headers(headers.iter().copied())
+    }
+
+    #[test]
+    fn accepts_everything_without_headers() {
+        let accepted = accepted(&[]);
+        assert!(accepted.accepts(OCI_INDEX));
+        assert!(accepted.accepts(DOCKER_MANIFEST));
+    }
+
+    #[test]
+    fn accepts_listed_types_of_every_header() {
+        let accepted = accepted(&[
+            &format!("{OCI_MANIFEST}, {DOCKER_MANIFEST};q=0.5"),
+            OCI_INDEX,
+        ]);
+        assert!(accepted.accepts(OCI_MANIFEST));
+        assert!(accepted.accepts(DOCKER_MANIFEST));
+        assert!(accepted.accepts(OCI_INDEX));
+        assert!(!accepted.accepts("application/json"));
+    }
+
+    #[test]
+    fn matches_case_insensitively() {
+        assert!(accepted(&["Application/VND.OCI.Image.Manifest.v1+JSON"]).accepts(OCI_MANIFEST));
+    }
+
+    #[test]
+    fn rejects_types_with_a_quality_of_zero() {
+        let accepted = accepted(&[&format!("*/*, {OCI_INDEX};q=0")]);
+        assert!(!accepted.accepts(OCI_INDEX));
+        assert!(accepted.accepts(OCI_MANIFEST));
+
+        let accepted = self::accepted(&[&format!("{OCI_INDEX}; q=0.0")]);
+        assert!(!accepted.accepts(OCI_INDEX));
+        assert!(accepted.accepts(OCI_MANIFEST));
+    }
+
+    #[test]
+    fn prefers_the_most_specific_range() {
+        let accepted = accepted(&[&format!("{OCI_MANIFEST}, */*;q=0")]);
+        assert!(accepted.accepts(OCI_MANIFEST));
+        assert!(!accepted.accepts(OCI_INDEX));
+
+        let accepted = self::accepted(&["application/*", &format!("{OCI_INDEX};q=0")]);
+        assert!(accepted.accepts(OCI_MANIFEST));
+        assert!(!accepted.accepts(OCI_INDEX));
+        assert!(!accepted.accepts("text/plain"));
+
+        let accepted = self::accepted(&["application/*;q=0", "*/*"]);
+        assert!(!accepted.accepts(OCI_MANIFEST));
+        assert!(accepted.accepts("text/plain"));
+    }
+
+    #[test]
+    fn ignores_empty_ranges() {
+        let accepted = accepted(&[&format!(" , {OCI_MANIFEST},")]);
+        assert!(accepted.accepts(OCI_MANIFEST));
+        assert!(!accepted.accepts(OCI_INDEX));
+    }
+}
@@ -0,0 +1,79 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+const BASIC_PREFIX: &str = "Basic ";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Credentials sent in an `Authorization` header.
+#[derive(Clone)]
+pub enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    pub fn parse(header: &str) -> Option<Self> {
+        if let Some(token) = header.strip_prefix(BEARER_PREFIX) {
+            return Some(Self::Bearer(token.trim().to_string()));
+        }
+
+        let encoded = header.strip_prefix(BASIC_PREFIX)?;
+        let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+
+        Some(Self::Basic {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+
+    #[test]
+    fn parses_bearer_tokens() {
+        assert!(matches!(
+            Credentials::parse("Bearer abc.def.ghi "),
+            Some(Credentials::Bearer(token)) if token == "abc.def.ghi"
+        ));
+    }
+
+    #[test]
+    fn parses_basic_credentials() {
+        assert!(matches!(
+            Credentials::parse(&basic("alice:secret")),
+            Some(Credentials::Basic { username, password }) if username == "alice" && password == "secret"
+        ));
+    }
+
+    #[test]
+    fn keeps_colons_in_basic_passwords() {
+        assert!(matches!(
+            Credentials::parse(&basic("alice:se:cr:et")),
+            Some(Credentials::Basic { username, password }) if username == "alice" && password == "se:cr:et"
+        ));
+        assert!(matches!(
+            Credentials::parse(&basic(":")),
+            Some(Credentials::Basic { username, password }) if username.is_empty() && password.is_empty()
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        for header in [
+            "".to_string(),
+            "Digest username=alice".to_string(),
+            "Basic".to_string(),
+            "Basic not base64!".to_string(),
+            basic("no colon"),
+            format!("Basic {}", STANDARD.encode([0xff, 0xfe, b':', b'a'])),
+        ] {
+            assert!(Credentials::parse(&header).is_none(), "{header}");
+        }
+    }
+}
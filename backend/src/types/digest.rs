@@ -0,0 +1,369 @@
+use std::fmt::Display;
+
+use rocket::request::FromParam;
+use sha2::{compress256, digest::generic_array::GenericArray, Digest as _, Sha256, Sha512};
+
+use crate::registry_error::{RegistryError, RegistryResult};
+
+pub const SHA256_BLOCK_SIZE: u64 = 64;
+
+const SHA256_INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    pub fn parse(algorithm: &str) -> RegistryResult<Self> {
+        match algorithm {
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            _ => {
+                warn!("Got unsupported digest algorithm `{algorithm}`");
+                Err(RegistryError::UnsupportedDigest)
+            }
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    fn hex_length(&self) -> usize {
+        match self {
+            Self::Sha256 => 64,
+            Self::Sha512 => 128,
+        }
+    }
+
+    pub fn hasher(&self) -> DigestHasher {
+        match self {
+            Self::Sha256 => DigestHasher::Sha256(Sha256::new()),
+            Self::Sha512 => DigestHasher::Sha512(Sha512::new()),
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Digest {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// A content digest of the form `algorithm:hex`, following the OCI descriptor grammar for the
+/// registered sha256 and sha512 algorithms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digest {
+    algorithm: DigestAlgorithm,
+    hex: String,
+}
+
+impl Digest {
+    pub fn parse(digest: &str) -> RegistryResult<Self> {
+        let Some((algorithm, hex)) = digest.split_once(':') else {
+            warn!("Digest is missing an algorithm ({digest})");
+            return Err(RegistryError::InvalidDigest);
+        };
+
+        let algorithm = DigestAlgorithm::parse(algorithm)?;
+
+        if hex.len() != algorithm.hex_length()
+            || !hex
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        {
+            warn!(
+                "Digest is not a valid {} digest ({digest})",
+                algorithm.name()
+            );
+            return Err(RegistryError::InvalidDigest);
+        }
+
+        Ok(Self {
+            algorithm,
+            hex: hex.to_string(),
+        })
+    }
+
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        self.algorithm
+    }
+
+    pub fn hex(&self) -> &str {
+        &self.hex
+    }
+
+    /// Where content with this digest is stored below a content-addressed storage prefix.
+    pub fn storage_path(&self, prefix: &str) -> String {
+        format!("{prefix}/{}/{}", self.algorithm.name(), self.hex)
+    }
+}
+
+impl<'a> FromParam<'a> for Digest {
+    type Error = RegistryError;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Self::parse(param)
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex)
+    }
+}
+
+pub enum DigestHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl DigestHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> Digest {
+        let (algorithm, hex) = match self {
+            Self::Sha256(hasher) => (DigestAlgorithm::Sha256, format!("{:x}", hasher.finalize())),
+            Self::Sha512(hasher) => (DigestAlgorithm::Sha512, format!("{:x}", hasher.finalize())),
+        };
+
+        Digest { algorithm, hex }
+    }
+}
+
+/// SHA-256 hasher whose state can be saved and resumed later, so that content arriving over
+/// several requests only has to be hashed once. Only complete blocks are part of the saved state,
+/// the bytes after the last complete block have to be hashed again after resuming.
+#[derive(Clone)]
+pub struct ResumableSha256 {
+    state: [u32; 8],
+    length: u64,
+    pending: Vec<u8>,
+}
+
+impl ResumableSha256 {
+    pub fn new() -> Self {
+        Self {
+            state: SHA256_INITIAL_STATE,
+            length: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Resumes from a state saved by [`Self::saved_state`] after hashing `length` bytes, which
+    /// must be a whole number of blocks.
+    pub fn resume(saved_state: &[u8], length: u64) -> Option<Self> {
+        if saved_state.len() != 32 || !length.is_multiple_of(SHA256_BLOCK_SIZE) {
+            return None;
+        }
+
+        let mut state = [0; 8];
+        for (word, bytes) in state.iter_mut().zip(saved_state.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().ok()?);
+        }
+
+        Some(Self {
+            state,
+            length,
+            pending: Vec::new(),
+        })
+    }
+
+    /// The state after the last complete block hashed so far.
+    pub fn saved_state(&self) -> Vec<u8> {
+        self.state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect()
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+
+        if !self.pending.is_empty() {
+            let missing = SHA256_BLOCK_SIZE as usize - self.pending.len();
+            let (head, rest) = data.split_at(missing.min(data.len()));
+            self.pending.extend_from_slice(head);
+            data = rest;
+
+            if self.pending.len() < SHA256_BLOCK_SIZE as usize {
+                return;
+            }
+            let block = std::mem::take(&mut self.pending);
+            self.compress(&block);
+        }
+
+        let mut blocks = data.chunks_exact(SHA256_BLOCK_SIZE as usize);
+        for block in &mut blocks {
+            self.compress(block);
+        }
+        self.pending.extend_from_slice(blocks.remainder());
+    }
+
+    pub fn finalize(mut self) -> Digest {
+        let mut tail = std::mem::take(&mut self.pending);
+        tail.push(0x80);
+        while tail.len() % SHA256_BLOCK_SIZE as usize != 56 {
+            tail.push(0);
+        }
+        tail.extend_from_slice(&(self.length * 8).to_be_bytes());
+        for block in tail.chunks_exact(SHA256_BLOCK_SIZE as usize) {
+            self.compress(block);
+        }
+
+        Digest {
+            algorithm: DigestAlgorithm::Sha256,
+            hex: self
+                .state
+                .iter()
+                .map(|word| format!("{word:08x}"))
+                .collect(),
+        }
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        compress256(&mut self.state, &[*GenericArray::from_slice(block)]);
+    }
+}
+
+impl Default for ResumableSha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_SHA512: &str = "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                              2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f";
+
+    #[test]
+    fn parses_sha256_and_sha512_digests() {
+        let sha256 = Digest::parse(&format!("sha256:{ABC_SHA256}")).expect("valid sha256");
+        assert_eq!(sha256.algorithm(), DigestAlgorithm::Sha256);
+        assert_eq!(sha256.hex(), ABC_SHA256);
+
+        let sha512 = Digest::parse(&format!("sha512:{ABC_SHA512}")).expect("valid sha512");
+        assert_eq!(sha512.algorithm(), DigestAlgorithm::Sha512);
+        assert_eq!(sha512.hex(), ABC_SHA512);
+        assert_eq!(sha512.to_string(), format!("sha512:{ABC_SHA512}"));
+    }
+
+    #[test]
+    fn rejects_digests_with_the_wrong_length() {
+        assert!(Digest::parse(&format!("sha256:{}", &ABC_SHA256[1..])).is_err());
+        assert!(Digest::parse(&format!("sha256:{ABC_SHA256}0")).is_err());
+        assert!(Digest::parse(&format!("sha512:{ABC_SHA256}")).is_err());
+        assert!(Digest::parse(&format!("sha256:{ABC_SHA512}")).is_err());
+    }
+
+    #[test]
+    fn rejects_uppercase_hex() {
+        assert!(Digest::parse(&format!("sha256:{}", ABC_SHA256.to_uppercase())).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_algorithms_and_missing_algorithms() {
+        assert!(matches!(
+            Digest::parse(&format!("md5:{ABC_SHA256}")),
+            Err(RegistryError::UnsupportedDigest)
+        ));
+        assert!(matches!(
+            Digest::parse(ABC_SHA256),
+            Err(RegistryError::InvalidDigest)
+        ));
+    }
+
+    #[test]
+    fn stores_digests_below_their_algorithm() {
+        let sha256 = Digest::parse(&format!("sha256:{ABC_SHA256}")).unwrap();
+        let sha512 = Digest::parse(&format!("sha512:{ABC_SHA512}")).unwrap();
+
+        assert_eq!(
+            sha256.storage_path("blobs"),
+            format!("blobs/sha256/{ABC_SHA256}")
+        );
+        assert_eq!(
+            sha512.storage_path("blobs"),
+            format!("blobs/sha512/{ABC_SHA512}")
+        );
+    }
+
+    #[test]
+    fn calculates_digests_of_known_content() {
+        assert_eq!(
+            DigestAlgorithm::Sha256.digest(b"abc").to_string(),
+            format!("sha256:{ABC_SHA256}")
+        );
+        assert_eq!(
+            DigestAlgorithm::Sha512.digest(b"abc").to_string(),
+            format!("sha512:{ABC_SHA512}")
+        );
+
+        let mut hasher = DigestAlgorithm::Sha512.hasher();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finalize().hex(), ABC_SHA512);
+    }
+
+    fn content(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn resumable_sha256_matches_sha256() {
+        for length in [0, 1, 55, 56, 63, 64, 65, 119, 128, 1000] {
+            let data = content(length);
+            let mut hasher = ResumableSha256::new();
+            hasher.update(&data);
+
+            assert_eq!(
+                hasher.finalize(),
+                DigestAlgorithm::Sha256.digest(&data),
+                "length {length}"
+            );
+        }
+    }
+
+    #[test]
+    fn resumable_sha256_resumes_across_chunks() {
+        let data = content(1000);
+        let expected = DigestAlgorithm::Sha256.digest(&data);
+
+        for split in [1, 63, 64, 100, 999] {
+            let mut first = ResumableSha256::new();
+            first.update(&data[..split]);
+            first.update(&[]);
+
+            let hashed_length = split as u64 - split as u64 % SHA256_BLOCK_SIZE;
+            let mut resumed = ResumableSha256::resume(&first.saved_state(), hashed_length)
+                .expect("a saved state resumes");
+            resumed.update(&data[hashed_length as usize..]);
+
+            assert_eq!(resumed.finalize(), expected, "split at {split}");
+        }
+    }
+
+    #[test]
+    fn resumable_sha256_rejects_invalid_states() {
+        let state = ResumableSha256::new().saved_state();
+
+        assert!(ResumableSha256::resume(&state[1..], 0).is_none());
+        assert!(ResumableSha256::resume(&state, 1).is_none());
+    }
+}
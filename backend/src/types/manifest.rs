@@ -1,16 +1,15 @@
-use ::serde::Deserialize;
+use std::collections::HashMap;
+
+use ::serde::{Deserialize, Serialize};
 use rocket::http::ContentType;
-use serde_json::value::RawValue;
 
 use crate::registry_error::{RegistryError, RegistryResult};
 
 pub const APPLICATION_CONTENT_TYPE_TOP: &str = "application";
 
-const DOCKER_IMAGE_MANIFEST_V2_CONTENT_TYPE: &str = "vnd.docker.distribution.manifest.v2+json";
-
 const FAT_MANIFEST_CONTENT_TYPE_DOCKER: &str =
     "application/vnd.docker.distribution.manifest.list.v2+json";
-const FAT_MANIFEST_CONTENT_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+pub const FAT_MANIFEST_CONTENT_TYPE: &str = "application/vnd.oci.image.index.v1+json";
 const SUPPORTED_FAT_MANIFEST_TYPES: [&str; 2] =
     [FAT_MANIFEST_CONTENT_TYPE, FAT_MANIFEST_CONTENT_TYPE_DOCKER];
 
@@ -18,21 +17,129 @@ const IMAGE_MANIFEST_DOCKER: &str = "application/vnd.docker.distribution.manifes
 const IMAGE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
 const SUPPORTED_IMAGE_MANIFEST_TYPES: [&str; 2] = [IMAGE_MANIFEST, IMAGE_MANIFEST_DOCKER];
 
-const CONTAINER_CONFIG_DOCKER: &str = "application/vnd.docker.container.image.v1+json";
-const CONTAINER_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+const CONTAINER_CONFIG_DOCKER: &str = "vnd.docker.container.image.v1+json";
+const CONTAINER_CONFIG: &str = "vnd.oci.image.config.v1+json";
 const SUPPORTED_CONTAINER_CONFIG_TYPES: [&str; 2] = [CONTAINER_CONFIG, CONTAINER_CONFIG_DOCKER];
 
+/// The media type of a content type without its parameters, e.g. `; charset=utf-8`, which is what
+/// manifests are matched and stored by.
+pub fn media_type(content_type: &ContentType) -> String {
+    format!("{}/{}", content_type.top(), content_type.sub()).to_ascii_lowercase()
+}
+
+/// Any manifest that can be pushed to the registry.
+#[derive(Debug, Clone)]
+pub enum ParsedManifest {
+    Image(DockerImageManifestV2),
+    Index(FatManifest),
+}
+
+impl ParsedManifest {
+    pub fn parse(content_type: &ContentType, data: &[u8]) -> RegistryResult<Self> {
+        let content_type_str = media_type(content_type);
+
+        if SUPPORTED_FAT_MANIFEST_TYPES.contains(&content_type_str.as_str()) {
+            return Ok(Self::Index(FatManifest::parse(content_type, data)?));
+        }
+
+        Ok(Self::Image(DockerImageManifestV2::parse(
+            content_type,
+            data.to_vec(),
+        )?))
+    }
+
+    pub fn subject(&self) -> Option<&Subject> {
+        match self {
+            ParsedManifest::Image(image) => image.subject.as_ref(),
+            ParsedManifest::Index(index) => index.subject.as_ref(),
+        }
+    }
+
+    pub fn annotations(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            ParsedManifest::Image(image) => image.annotations.as_ref(),
+            ParsedManifest::Index(index) => index.annotations.as_ref(),
+        }
+    }
+
+    /// The artifact type reported for the manifest by the referrers API.
+    pub fn referrer_artifact_type(&self) -> Option<&str> {
+        match self {
+            ParsedManifest::Image(image) => Some(image.referrer_artifact_type()),
+            ParsedManifest::Index(index) => index.artifact_type.as_deref(),
+        }
+    }
+}
+
+/// An OCI image index or a Docker manifest list, pointing to a manifest per platform.
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct FatManifest<'a> {
+pub struct FatManifest {
     pub schema_version: i32,
+    pub media_type: Option<String>,
+    pub artifact_type: Option<String>,
+    pub manifests: Vec<ManifestDescriptor>,
+    pub subject: Option<Subject>,
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestDescriptor {
     pub media_type: String,
-    #[serde(borrow)]
-    manifest: &'a RawValue, // TODO: Implement
+    pub size: i64,
+    pub digest: String,
+    pub platform: Option<Platform>,
+    pub annotations: Option<HashMap<String, String>>,
 }
 
-impl<'a> FatManifest<'a> {
-    pub fn validate(&self) -> RegistryResult<()> {
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    #[serde(rename = "os.version", skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    #[serde(rename = "os.features", skip_serializing_if = "Option::is_none")]
+    pub os_features: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features: Option<Vec<String>>,
+}
+
+impl FatManifest {
+    pub fn is_index_type(media_type: &str) -> bool {
+        SUPPORTED_FAT_MANIFEST_TYPES.contains(&media_type)
+    }
+
+    /// Finds the manifest for the given platform, ignoring manifests whose media type is rejected
+    /// by `accepts`.
+    pub fn find_platform_manifest(
+        &self,
+        os: &str,
+        architecture: &str,
+        accepts: impl Fn(&str) -> bool,
+    ) -> Option<&ManifestDescriptor> {
+        self.manifests.iter().find(|descriptor| {
+            accepts(&descriptor.media_type)
+                && descriptor.platform.as_ref().is_some_and(|platform| {
+                    platform.os == os && platform.architecture == architecture
+                })
+        })
+    }
+
+    pub fn parse(content_type: &ContentType, data: &[u8]) -> RegistryResult<Self> {
+        if !SUPPORTED_FAT_MANIFEST_TYPES.contains(&media_type(content_type).as_str()) {
+            error!("Got unsupported index type {content_type}");
+            return Err(RegistryError::UnsupportedManifestType);
+        }
+
+        let index: Self = serde_json::from_slice(data)?;
+        index.validate(content_type)?;
+        Ok(index)
+    }
+
+    pub fn validate(&self, content_type: &ContentType) -> RegistryResult<()> {
         if self.schema_version != 2 {
             return Err(RegistryError::InvalidManifestSchema(format!(
                 "Expected manifest version 2, got {}",
@@ -40,10 +147,49 @@ impl<'a> FatManifest<'a> {
             )));
         }
 
-        if self.media_type.as_str() != FAT_MANIFEST_CONTENT_TYPE {
+        let content_type = media_type(content_type);
+        match self.media_type.as_ref() {
+            Some(media_type) if media_type != &content_type => {
+                error!(
+                    "Index media type ({media_type}) does not match the provided content type ({content_type})!"
+                );
+                return Err(RegistryError::InvalidManifestSchema(
+                    "Index media type does not match content type".to_string(),
+                ));
+            }
+            // The media type is only optional for OCI image indexes.
+            None if content_type == FAT_MANIFEST_CONTENT_TYPE_DOCKER => {
+                return Err(RegistryError::InvalidManifestSchema(format!(
+                    "Expected media_type {FAT_MANIFEST_CONTENT_TYPE_DOCKER}"
+                )));
+            }
+            _ => {}
+        }
+
+        for descriptor in self.manifests.iter() {
+            descriptor.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ManifestDescriptor {
+    pub fn validate(&self) -> RegistryResult<()> {
+        let media_type = self.media_type.as_str();
+        if !SUPPORTED_IMAGE_MANIFEST_TYPES.contains(&media_type)
+            && !SUPPORTED_FAT_MANIFEST_TYPES.contains(&media_type)
+        {
+            error!("Unexpected media type for index entry '{media_type}'");
             return Err(RegistryError::InvalidManifestSchema(format!(
-                "Expected media_type {FAT_MANIFEST_CONTENT_TYPE}, got {}",
-                self.media_type
+                "Got unsupported manifest type {media_type} in index"
+            )));
+        }
+
+        if self.size < 0 {
+            return Err(RegistryError::InvalidManifestSchema(format!(
+                "Got negative size for manifest {} in index",
+                self.digest
             )));
         }
 
@@ -58,21 +204,24 @@ pub struct DockerImageManifestV2 {
     pub config: ManifestConfig,
     pub layers: Vec<LayerManifest>,
     pub media_type: Option<String>,
+    pub artifact_type: Option<String>,
     pub subject: Option<Subject>,
+    pub annotations: Option<HashMap<String, String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Subject {
+    pub media_type: String,
+    pub size: i64,
     pub digest: String,
-    // TODO
 }
 
 impl DockerImageManifestV2 {
     pub fn parse(content_type: &ContentType, data: Vec<u8>) -> RegistryResult<Self> {
         let slice = data.as_slice();
 
-        if !SUPPORTED_IMAGE_MANIFEST_TYPES.contains(&content_type.to_string().as_str()) {
+        if !SUPPORTED_IMAGE_MANIFEST_TYPES.contains(&media_type(content_type).as_str()) {
             error!("Got unsupported manifest type {content_type}");
             return Err(RegistryError::UnsupportedManifestType);
         }
@@ -82,6 +231,14 @@ impl DockerImageManifestV2 {
         Ok(image_manifest)
     }
 
+    /// The artifact type reported for the manifest by the referrers API, which falls back to the
+    /// config media type when `artifactType` is not set.
+    pub fn referrer_artifact_type(&self) -> &str {
+        self.artifact_type
+            .as_deref()
+            .unwrap_or(&self.config.media_type)
+    }
+
     pub fn validate(&self, content_type: &ContentType) -> RegistryResult<()> {
         if self.schema_version != 2 {
             return Err(RegistryError::InvalidManifestSchema(format!(
@@ -91,23 +248,31 @@ impl DockerImageManifestV2 {
         }
 
         if let Some(ct) = self.media_type.as_ref() {
-            if ct != &content_type.to_string() {
+            let content_type = media_type(content_type);
+            if ct != &content_type {
                 error!(
-                    "Manifest media type ({ct}) does not match the provided content type ({})!",
-                    content_type.to_string()
+                    "Manifest media type ({ct}) does not match the provided content type ({content_type})!"
                 );
-                return Err(RegistryError::InvalidManifestSchema(format!(
-                    "Manifest media type does not match content type"
-                )));
+                return Err(RegistryError::InvalidManifestSchema(
+                    "Manifest media type does not match content type".to_string(),
+                ));
             }
 
-            let Some(media_type) = ct.strip_prefix(&format!("{APPLICATION_CONTENT_TYPE_TOP}/"))
-            else {
+            if !ct.starts_with(&format!("{APPLICATION_CONTENT_TYPE_TOP}/")) {
                 error!("Invalid media type for DockerImageManifestV2 '{ct}'",);
-                return Err(RegistryError::InvalidManifestSchema(format!(
-                    "Invalid media type for image manifest"
-                )));
-            };
+                return Err(RegistryError::InvalidManifestSchema(
+                    "Invalid media type for image manifest".to_string(),
+                ));
+            }
+        }
+
+        self.config.validate()?;
+
+        // Artifacts may store any kind of content in their layers, only images are restricted
+        // to the layer types that container runtimes understand.
+        let is_image = self.config.is_image_config();
+        for layer in self.layers.iter() {
+            layer.validate(is_image)?;
         }
 
         Ok(())
@@ -123,51 +288,40 @@ pub struct ManifestConfig {
 }
 
 impl ManifestConfig {
+    /// The config of an artifact may have any media type, in which case it is also the type of
+    /// the artifact.
     pub fn validate(&self) -> RegistryResult<()> {
-        let Some(media_type) = self
-            .media_type
-            .strip_prefix(&format!("{APPLICATION_CONTENT_TYPE_TOP}/"))
-        else {
-            error!(
-                "Unexpected manifest config media type '{}'",
-                self.media_type
-            );
-            return Err(RegistryError::InvalidManifestSchema(format!(
-                "Got unsupported config format {}",
-                self.media_type
-            )));
-        };
-
-        if !SUPPORTED_CONTAINER_CONFIG_TYPES.contains(&media_type) {
-            error!(
-                "Expected manifest config type to have media type {}",
-                self.media_type
-            );
-            return Err(RegistryError::InvalidManifestSchema(format!(
-                "Got unsupported config format {}",
-                self.media_type
-            )));
-        }
-
-        // TODO: Validate size whenever docker actually uses it...
+        validate_media_type(&self.media_type)?;
+        validate_size(&self.digest, self.size)
+    }
 
-        Ok(())
+    pub fn is_image_config(&self) -> bool {
+        self.media_type
+            .strip_prefix(&format!("{APPLICATION_CONTENT_TYPE_TOP}/"))
+            .is_some_and(|media_type| SUPPORTED_CONTAINER_CONFIG_TYPES.contains(&media_type))
     }
 }
 
 const LAYER_TAR_GZIP: &str = "vnd.docker.image.rootfs.diff.tar.gzip";
+const LAYER_FOREIGN_TAR_GZIP: &str = "vnd.docker.image.rootfs.foreign.diff.tar.gzip";
 const OCI_LAYER_TAR: &str = "vnd.oci.image.layer.v1.tar";
 const OCI_LAYER_TAR_GZIP: &str = "vnd.oci.image.layer.v1.tar+gzip";
+const OCI_LAYER_TAR_ZSTD: &str = "vnd.oci.image.layer.v1.tar+zstd";
 const OCI_LAYER_NONDISTRIBUTABLE_TAR: &str = "vnd.oci.image.layer.nondistributable.v1.tar";
 const OCI_LAYER_NONDISTRIBUTABLE_TAR_GZIP: &str =
     "vnd.oci.image.layer.nondistributable.v1.tar+gzip";
+const OCI_LAYER_NONDISTRIBUTABLE_TAR_ZSTD: &str =
+    "vnd.oci.image.layer.nondistributable.v1.tar+zstd";
 
-const SUPPORTED_LAYER_TYPES: [&str; 5] = [
+const SUPPORTED_LAYER_TYPES: [&str; 8] = [
     LAYER_TAR_GZIP,
+    LAYER_FOREIGN_TAR_GZIP,
     OCI_LAYER_TAR,
     OCI_LAYER_TAR_GZIP,
+    OCI_LAYER_TAR_ZSTD,
     OCI_LAYER_NONDISTRIBUTABLE_TAR,
     OCI_LAYER_NONDISTRIBUTABLE_TAR_GZIP,
+    OCI_LAYER_NONDISTRIBUTABLE_TAR_ZSTD,
 ];
 
 #[derive(Deserialize, Debug, Clone)]
@@ -179,30 +333,163 @@ pub struct LayerManifest {
 }
 
 impl LayerManifest {
-    pub fn validate(&self) -> RegistryResult<()> {
-        let Some(media_type) = self
-            .media_type
-            .strip_prefix(&format!("{APPLICATION_CONTENT_TYPE_TOP}/"))
-        else {
-            error!("Invalid layer manifest media type '{}'", self.media_type);
-            return Err(RegistryError::InvalidManifestSchema(format!(
-                "Invalid media type for schema, expected it to start with `application/`"
-            )));
-        };
+    pub fn validate(&self, is_image: bool) -> RegistryResult<()> {
+        validate_media_type(&self.media_type)?;
 
-        if !SUPPORTED_LAYER_TYPES.contains(&media_type) {
-            error!(
-                "Expected manifest config type to have media type {}",
-                self.media_type
-            );
-            return Err(RegistryError::InvalidManifestSchema(format!(
-                "Expected media_type {LAYER_TAR_GZIP}, got {}",
-                self.media_type
-            )));
+        if is_image {
+            let supported = self
+                .media_type
+                .strip_prefix(&format!("{APPLICATION_CONTENT_TYPE_TOP}/"))
+                .is_some_and(|media_type| SUPPORTED_LAYER_TYPES.contains(&media_type));
+
+            if !supported {
+                error!("Unsupported image layer media type '{}'", self.media_type);
+                return Err(RegistryError::InvalidManifestSchema(format!(
+                    "Got unsupported layer type {} for layer {}",
+                    self.media_type, self.digest
+                )));
+            }
         }
 
-        // TODO: Validate size...
+        validate_size(&self.digest, self.size)
+    }
+}
 
-        Ok(())
+/// Checks that a media type has the `type/subtype` form defined by RFC 6838.
+fn validate_media_type(media_type: &str) -> RegistryResult<()> {
+    let is_valid_part = |part: &str| {
+        !part.is_empty()
+            && part.len() <= 127
+            && part.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+
+    match media_type.split_once('/') {
+        Some((top, sub)) if is_valid_part(top) && is_valid_part(sub) => Ok(()),
+        _ => {
+            error!("Invalid media type '{media_type}'");
+            Err(RegistryError::InvalidManifestSchema(format!(
+                "Invalid media type {media_type}"
+            )))
+        }
+    }
+}
+
+fn validate_size(digest: &str, size: i64) -> RegistryResult<()> {
+    if size < 0 {
+        return Err(RegistryError::InvalidManifestSchema(format!(
+            "Got negative size for {digest}"
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn image_manifest() -> Vec<u8> {
+        format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "{IMAGE_MANIFEST}",
+                "config": {{"mediaType": "application/{CONTAINER_CONFIG}", "size": 0, "digest": "{DIGEST}"}},
+                "layers": [{{"mediaType": "application/{OCI_LAYER_TAR_GZIP}", "size": 0, "digest": "{DIGEST}"}}]
+            }}"#
+        )
+        .into_bytes()
+    }
+
+    fn index() -> Vec<u8> {
+        format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "{FAT_MANIFEST_CONTENT_TYPE}",
+                "manifests": [{{"mediaType": "{IMAGE_MANIFEST}", "size": 0, "digest": "{DIGEST}"}}]
+            }}"#
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn media_type_drops_parameters() {
+        let content_type =
+            ContentType::parse_flexible(&format!("{IMAGE_MANIFEST}; charset=utf-8")).unwrap();
+        assert_eq!(media_type(&content_type), IMAGE_MANIFEST);
+
+        let content_type = ContentType::parse_flexible("Application/JSON").unwrap();
+        assert_eq!(media_type(&content_type), "application/json");
+    }
+
+    #[test]
+    fn parses_manifests_with_content_type_parameters() {
+        let content_type =
+            ContentType::parse_flexible(&format!("{IMAGE_MANIFEST}; charset=utf-8")).unwrap();
+        assert!(matches!(
+            ParsedManifest::parse(&content_type, &image_manifest()),
+            Ok(ParsedManifest::Image(_))
+        ));
+
+        let content_type =
+            ContentType::parse_flexible(&format!("{FAT_MANIFEST_CONTENT_TYPE}; charset=utf-8"))
+                .unwrap();
+        assert!(matches!(
+            ParsedManifest::parse(&content_type, &index()),
+            Ok(ParsedManifest::Index(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_mismatching_and_unsupported_types() {
+        let content_type = ContentType::parse_flexible(IMAGE_MANIFEST_DOCKER).unwrap();
+        assert!(ParsedManifest::parse(&content_type, &image_manifest()).is_err());
+
+        let content_type = ContentType::parse_flexible("application/json").unwrap();
+        assert!(ParsedManifest::parse(&content_type, &image_manifest()).is_err());
+    }
+
+    #[test]
+    fn finds_the_accepted_manifest_of_a_platform() {
+        let data = format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "{FAT_MANIFEST_CONTENT_TYPE}",
+                "manifests": [
+                    {{"mediaType": "{IMAGE_MANIFEST}", "size": 0, "digest": "sha256:arm", "platform": {{"os": "linux", "architecture": "arm64"}}}},
+                    {{"mediaType": "{IMAGE_MANIFEST}", "size": 0, "digest": "sha256:oci", "platform": {{"os": "linux", "architecture": "amd64"}}}},
+                    {{"mediaType": "{IMAGE_MANIFEST_DOCKER}", "size": 0, "digest": "sha256:docker", "platform": {{"os": "linux", "architecture": "amd64"}}}},
+                    {{"mediaType": "{IMAGE_MANIFEST}", "size": 0, "digest": "sha256:attestation"}}
+                ]
+            }}"#
+        );
+        let index: FatManifest = serde_json::from_str(&data).unwrap();
+
+        let find = |os, architecture, accepted: &[&str]| {
+            index
+                .find_platform_manifest(os, architecture, |media_type| {
+                    accepted.contains(&media_type)
+                })
+                .map(|descriptor| descriptor.digest.as_str())
+        };
+
+        assert_eq!(
+            find("linux", "amd64", &[IMAGE_MANIFEST]),
+            Some("sha256:oci")
+        );
+        assert_eq!(
+            find("linux", "amd64", &[IMAGE_MANIFEST_DOCKER]),
+            Some("sha256:docker")
+        );
+        assert_eq!(
+            find("linux", "arm64", &[IMAGE_MANIFEST]),
+            Some("sha256:arm")
+        );
+        assert_eq!(find("linux", "arm64", &[IMAGE_MANIFEST_DOCKER]), None);
+        assert_eq!(find("windows", "amd64", &[IMAGE_MANIFEST]), None);
     }
 }
@@ -1,2 +1,10 @@
+pub mod accepted_media_types;
+pub mod credentials;
+pub mod digest;
 pub mod manifest;
+pub mod notification_event;
+pub mod reference;
+pub mod repository_name;
+pub mod resource_scope;
 pub mod session_id;
+pub mod visibility;
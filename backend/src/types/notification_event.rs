@@ -0,0 +1,99 @@
+use std::convert::Infallible;
+
+use rocket::{
+    request::{self, FromRequest},
+    Request,
+};
+use serde::Serialize;
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+const USER_AGENT_HEADER_NAME: &str = "User-Agent";
+
+/// Media type of the envelope events are delivered in, as defined by the Docker distribution
+/// notification format.
+pub const EVENTS_MEDIA_TYPE: &str = "application/vnd.docker.distribution.events.v1+json";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Envelope {
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub action: EventAction,
+    pub target: EventTarget,
+    pub request: EventRequest,
+    pub actor: EventActor,
+    pub source: EventSource,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventAction {
+    Push,
+    Pull,
+    Delete,
+}
+
+/// The manifest, tag or blob the event is about. Deletions only identify the target by digest or
+/// tag.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    pub repository: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+/// The request that caused an event, collected from every request so that handlers can pass it
+/// on when they emit events.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRequest {
+    pub id: Uuid,
+    pub addr: String,
+    pub host: String,
+    pub method: String,
+    pub useragent: String,
+}
+
+/// The user who made the request, anonymous pulls have no actor name.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventActor {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventSource {
+    pub addr: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for EventRequest {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(EventRequest {
+            id: Uuid::new_v4(),
+            addr: req.client_ip().map(|ip| ip.to_string()).unwrap_or_default(),
+            host: req.host().map(|host| host.to_string()).unwrap_or_default(),
+            method: req.method().as_str().to_string(),
+            useragent: req
+                .headers()
+                .get_one(USER_AGENT_HEADER_NAME)
+                .unwrap_or_default()
+                .to_string(),
+        })
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt::Display;
+
+use rocket::request::FromParam;
+
+use crate::registry_error::{RegistryError, RegistryResult};
+
+use super::digest::Digest;
+
+const MAX_TAG_LENGTH: usize = 128;
+
+/// A manifest reference, which is either a tag or a digest. Tags can't contain a `:`, so any
+/// reference that does is parsed as a digest.
+#[derive(Debug, Clone)]
+pub enum Reference {
+    Tag(String),
+    Digest(Digest),
+}
+
+impl Reference {
+    pub fn parse(reference: &str) -> RegistryResult<Self> {
+        if reference.contains(':') {
+            Ok(Self::Digest(Digest::parse(reference)?))
+        } else if is_valid_tag(reference) {
+            Ok(Self::Tag(reference.to_string()))
+        } else {
+            warn!("Tag does not match the OCI tag grammar ({reference})");
+            Err(RegistryError::InvalidTag(reference.to_string()))
+        }
+    }
+}
+
+/// Tags follow the OCI distribution grammar `[a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}`.
+fn is_valid_tag(tag: &str) -> bool {
+    let mut chars = tag.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    tag.len() <= MAX_TAG_LENGTH
+        && (first.is_ascii_alphanumeric() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+impl<'a> FromParam<'a> for Reference {
+    type Error = RegistryError;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Self::parse(param)
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tag(tag) => write!(f, "{tag}"),
+            Self::Digest(digest) => write!(f, "{digest}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tags() {
+        for tag in ["latest", "v1.2.3", "_internal", "1.0-rc_1", "A"] {
+            assert!(
+                matches!(Reference::parse(tag), Ok(Reference::Tag(_))),
+                "{tag}"
+            );
+        }
+
+        let longest = "a".repeat(MAX_TAG_LENGTH);
+        assert!(matches!(Reference::parse(&longest), Ok(Reference::Tag(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        for tag in ["", ".hidden", "-v1", "../../x", "a/b", "v1 ", "v1?x", "ä"] {
+            assert!(
+                matches!(Reference::parse(tag), Err(RegistryError::InvalidTag(_))),
+                "{tag}"
+            );
+        }
+
+        let too_long = "a".repeat(MAX_TAG_LENGTH + 1);
+        assert!(Reference::parse(&too_long).is_err());
+    }
+
+    #[test]
+    fn parses_digests() {
+        let digest = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert!(matches!(Reference::parse(digest), Ok(Reference::Digest(_))));
+        assert!(Reference::parse("sha256:../x").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::{fmt::Display, ops::Deref};
+
+use rocket::request::FromParam;
+
+use crate::registry_error::{RegistryError, RegistryResult};
+
+const MAX_NAME_LENGTH: usize = 255;
+
+/// A repository name following the OCI distribution grammar
+/// `[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*(/[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*)*`.
+#[derive(Debug, Clone)]
+pub struct RepositoryName(String);
+
+impl RepositoryName {
+    pub fn parse(name: &str) -> RegistryResult<Self> {
+        if name.len() > MAX_NAME_LENGTH {
+            warn!(
+                "Repository name is longer than {MAX_NAME_LENGTH} characters ({})",
+                name.len()
+            );
+            return Err(RegistryError::InvalidName(name.to_string()));
+        }
+
+        if !name.split('/').all(is_valid_component) {
+            warn!("Repository name does not match the OCI name grammar ({name})");
+            return Err(RegistryError::InvalidName(name.to_string()));
+        }
+
+        Ok(Self(name.to_string()))
+    }
+}
+
+/// Alphanumeric runs joined by `.`, `_`, `__` or any number of `-`.
+fn is_valid_component(component: &str) -> bool {
+    let mut separator = String::new();
+    let mut after_alphanumeric = false;
+
+    for c in component.chars() {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            if !separator.is_empty() && !is_valid_separator(&separator) {
+                return false;
+            }
+            separator.clear();
+            after_alphanumeric = true;
+        } else if after_alphanumeric && matches!(c, '.' | '_' | '-') {
+            separator.push(c);
+        } else {
+            return false;
+        }
+    }
+
+    after_alphanumeric && separator.is_empty()
+}
+
+fn is_valid_separator(separator: &str) -> bool {
+    matches!(separator, "." | "_" | "__") || separator.chars().all(|c| c == '-')
+}
+
+impl<'a> FromParam<'a> for RepositoryName {
+    type Error = RegistryError;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Self::parse(param)
+    }
+}
+
+impl Deref for RepositoryName {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for RepositoryName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_names() {
+        for name in [
+            "alpine",
+            "library/alpine",
+            "team/service/api",
+            "my-app",
+            "my_app",
+            "my__app",
+            "my--app",
+            "my.app",
+            "a0/b1.c2-d3_e4",
+        ] {
+            assert!(RepositoryName::parse(name).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        for name in [
+            "",
+            "Alpine",
+            "library/",
+            "/alpine",
+            "library//alpine",
+            "-app",
+            "app-",
+            "my..app",
+            "my.-app",
+            "my___app",
+            "my_-app",
+            "my app",
+            "my:app",
+            "../etc",
+        ] {
+            assert!(RepositoryName::parse(name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn limits_names_to_255_characters() {
+        let longest = format!("{}/{}", "a".repeat(127), "b".repeat(127));
+        assert_eq!(longest.len(), MAX_NAME_LENGTH);
+        assert!(RepositoryName::parse(&longest).is_ok());
+
+        let too_long = format!("{longest}c");
+        assert!(RepositoryName::parse(&too_long).is_err());
+    }
+}
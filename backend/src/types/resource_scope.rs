@@ -0,0 +1,156 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+pub const REPOSITORY_RESOURCE_TYPE: &str = "repository";
+pub const REGISTRY_RESOURCE_TYPE: &str = "registry";
+pub const CATALOG_RESOURCE_NAME: &str = "catalog";
+
+pub const PULL_ACTION: &str = "pull";
+pub const PUSH_ACTION: &str = "push";
+pub const DELETE_ACTION: &str = "delete";
+pub const ALL_ACTIONS: &str = "*";
+
+/// Access to a resource as requested in a token scope (`repository:team/app:pull,push`) and
+/// granted in the `access` claim of a token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceScope {
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    pub name: String,
+    pub actions: Vec<String>,
+}
+
+impl ResourceScope {
+    pub fn repository(name: &str, actions: &[&str]) -> Self {
+        Self {
+            resource_type: REPOSITORY_RESOURCE_TYPE.to_string(),
+            name: name.to_string(),
+            actions: actions.iter().map(|action| action.to_string()).collect(),
+        }
+    }
+
+    pub fn catalog() -> Self {
+        Self {
+            resource_type: REGISTRY_RESOURCE_TYPE.to_string(),
+            name: CATALOG_RESOURCE_NAME.to_string(),
+            actions: vec![ALL_ACTIONS.to_string()],
+        }
+    }
+
+    /// Parses `type:name:actions`. Names may contain a `:` when they include a registry port, so
+    /// the type and the actions are split off from either end.
+    pub fn parse(scope: &str) -> Option<Self> {
+        let (resource_type, rest) = scope.split_once(':')?;
+        let (name, actions) = rest.rsplit_once(':')?;
+        if resource_type.is_empty() || name.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            resource_type: resource_type.to_string(),
+            name: name.to_string(),
+            actions: actions
+                .split(',')
+                .filter(|action| !action.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    /// Whether this grants every action of `required` on the same resource.
+    pub fn covers(&self, required: &ResourceScope) -> bool {
+        self.resource_type == required.resource_type
+            && self.name == required.name
+            && required.actions.iter().all(|action| {
+                self.actions
+                    .iter()
+                    .any(|granted| granted == action || granted == ALL_ACTIONS)
+            })
+    }
+}
+
+impl Display for ResourceScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.resource_type,
+            self.name,
+            self.actions.join(",")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scopes() {
+        let scope = ResourceScope::parse("repository:team/app:pull,push").unwrap();
+        assert_eq!(
+            scope,
+            ResourceScope::repository("team/app", &["pull", "push"])
+        );
+        assert_eq!(scope.to_string(), "repository:team/app:pull,push");
+
+        assert_eq!(
+            ResourceScope::parse("registry:catalog:*"),
+            Some(ResourceScope::catalog())
+        );
+    }
+
+    #[test]
+    fn parses_names_with_a_port() {
+        let scope = ResourceScope::parse("repository:localhost:5000/team/app:pull").unwrap();
+        assert_eq!(scope.resource_type, "repository");
+        assert_eq!(scope.name, "localhost:5000/team/app");
+        assert_eq!(scope.actions, vec!["pull"]);
+    }
+
+    #[test]
+    fn parses_empty_actions() {
+        for scope in ["repository:team/app:", "repository:team/app:,"] {
+            let scope = ResourceScope::parse(scope).unwrap();
+            assert_eq!(scope.name, "team/app");
+            assert!(scope.actions.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_scopes() {
+        for scope in [
+            "",
+            "repository",
+            "repository:team/app",
+            ":team/app:pull",
+            "repository::pull",
+        ] {
+            assert_eq!(ResourceScope::parse(scope), None, "{scope}");
+        }
+    }
+
+    #[test]
+    fn covers_granted_actions() {
+        let granted = ResourceScope::repository("team/app", &["pull", "push"]);
+
+        assert!(granted.covers(&ResourceScope::repository("team/app", &["pull"])));
+        assert!(granted.covers(&ResourceScope::repository("team/app", &["pull", "push"])));
+        assert!(granted.covers(&ResourceScope::repository("team/app", &[])));
+        assert!(!granted.covers(&ResourceScope::repository("team/app", &["delete"])));
+        assert!(!granted.covers(&ResourceScope::repository("team/other", &["pull"])));
+        assert!(!granted.covers(&ResourceScope::repository("team/app", &["*"])));
+    }
+
+    #[test]
+    fn all_actions_cover_every_action() {
+        let granted = ResourceScope::repository("team/app", &["*"]);
+
+        assert!(granted.covers(&ResourceScope::repository("team/app", &["pull", "delete"])));
+        assert!(granted.covers(&ResourceScope::repository("team/app", &["*"])));
+        assert!(!granted.covers(&ResourceScope::repository("team/other", &["pull"])));
+        assert!(!ResourceScope::catalog()
+            .covers(&ResourceScope::repository(CATALOG_RESOURCE_NAME, &["pull"])));
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// Who may pull from a repository: everyone, including anonymous users, or only the users that
+/// are authorized for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+impl Visibility {
+    pub fn is_public(self) -> bool {
+        self == Visibility::Public
+    }
+}
+
+impl From<bool> for Visibility {
+    fn from(is_public: bool) -> Self {
+        if is_public {
+            Visibility::Public
+        } else {
+            Visibility::Private
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    tokio::{self, time::MissedTickBehavior},
+    Orbit, Rocket,
+};
+use sqlx::Pool;
+
+use crate::{config::Config, db::DB, services::delete_upload_session_service};
+
+/// Periodically expires uploads that were abandoned by their clients, so that neither the
+/// `upload_session` table nor the upload directory grow without bound.
+pub struct UploadSessionSweeper;
+
+#[rocket::async_trait]
+impl Fairing for UploadSessionSweeper {
+    fn info(&self) -> Info {
+        Info {
+            name: "Upload session sweeper",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(db_pool), Some(config)) = (rocket.state::<Pool<DB>>(), rocket.state::<Config>())
+        else {
+            error!("Upload session sweeper is missing the database pool or config!");
+            return;
+        };
+
+        let db_pool = db_pool.clone();
+        let config = config.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.upload_session_sweep_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+
+                match delete_upload_session_service::expire_upload_sessions(&db_pool, &config).await
+                {
+                    Ok(0) => {}
+                    Ok(expired) => info!("Expired {expired} abandoned uploads"),
+                    Err(err) => error!("Failed to expire abandoned uploads, err: {err:?}"),
+                }
+            }
+        });
+    }
+}
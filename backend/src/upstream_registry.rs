@@ -0,0 +1,600 @@
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::{header, Client, Method, RequestBuilder, Response, StatusCode};
+use rocket::futures::TryStreamExt;
+use serde::Deserialize;
+use tokio_util::io::StreamReader;
+
+use crate::{
+    config::{Config, ProxyConfig},
+    registry_error::{RegistryError, RegistryResult},
+    storage::StorageReader,
+    types::digest::Digest,
+};
+
+/// Every manifest type the registry can store, so that the upstream never has to convert.
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+const DOCKER_CONTENT_DIGEST_HEADER_NAME: &str = "Docker-Content-Digest";
+
+/// The proxy namespace, if one is configured, shared by all requests.
+#[derive(Clone)]
+pub struct Proxy(Option<Arc<UpstreamRegistry>>);
+
+impl Proxy {
+    pub fn new(config: &Config) -> Self {
+        Self(config.proxy.as_ref().map(|proxy| {
+            info!(
+                "Proxying repositories in {}/ to {}",
+                proxy.namespace, proxy.upstream_url
+            );
+            Arc::new(UpstreamRegistry::new(proxy.clone()))
+        }))
+    }
+
+    /// The namespace that is proxied, if any.
+    pub fn namespace(&self) -> Option<&str> {
+        self.0.as_ref().map(|upstream| upstream.namespace())
+    }
+
+    /// The upstream that `namespace` is proxied from and the name of the repository on the
+    /// upstream, or `None` if the repository is a regular one.
+    pub fn upstream_for<'a>(&self, namespace: &'a str) -> Option<(&UpstreamRegistry, &'a str)> {
+        let upstream = self.0.as_ref()?;
+        let name = namespace
+            .strip_prefix(upstream.config.namespace.as_str())?
+            .strip_prefix('/')?;
+
+        Some((upstream, name))
+    }
+}
+
+pub struct UpstreamManifest {
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+pub struct UpstreamBlob {
+    pub size: u64,
+    pub media_type: Option<String>,
+}
+
+/// Pulls content from the registry that a proxy namespace mirrors. Registries such as Docker Hub
+/// answer anonymous requests with a `WWW-Authenticate` challenge that names the token service to
+/// get a pull token from, tokens are kept per scope until the upstream rejects them.
+pub struct UpstreamRegistry {
+    config: ProxyConfig,
+    client: Client,
+    tokens: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+impl UpstreamRegistry {
+    fn new(config: ProxyConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.config.namespace
+    }
+
+    pub fn tag_ttl(&self) -> Duration {
+        self.config.tag_ttl
+    }
+
+    /// Returns `None` if the upstream does not have the manifest. Manifests pulled by tag are
+    /// checked against the digest the upstream reports for them.
+    pub async fn fetch_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+    ) -> RegistryResult<Option<UpstreamManifest>> {
+        let path = format!("{name}/manifests/{reference}");
+        let Some(response) = self
+            .request(Method::GET, name, &path, Some(MANIFEST_ACCEPT))
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let Some(media_type) = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_string())
+        else {
+            return Err(upstream_error(format!(
+                "manifest {name}:{reference} has no content type"
+            )));
+        };
+
+        let reported_digest = response
+            .headers()
+            .get(DOCKER_CONTENT_DIGEST_HEADER_NAME)
+            .and_then(|value| value.to_str().ok())
+            .map(Digest::parse)
+            .transpose()?;
+
+        let data = response.bytes().await.map_err(upstream_error)?.to_vec();
+
+        if let Some(digest) = reported_digest {
+            let calculated_digest = digest.algorithm().digest(&data);
+            if calculated_digest != digest {
+                error!("Upstream manifest {name}:{reference} has digest {calculated_digest}, upstream reported {digest}");
+                return Err(RegistryError::InvalidDigest);
+            }
+        }
+
+        Ok(Some(UpstreamManifest { media_type, data }))
+    }
+
+    /// Streams the blob and returns its size if the upstream tells it, returns `None` if the
+    /// upstream does not have it. The content is not verified here, it is stored through a
+    /// regular upload which checks the digest.
+    pub async fn fetch_blob(
+        &self,
+        name: &str,
+        digest: &Digest,
+    ) -> RegistryResult<Option<(StorageReader, Option<u64>)>> {
+        let path = format!("{name}/blobs/{digest}");
+        let Some(response) = self.request(Method::GET, name, &path, None).await? else {
+            return Ok(None);
+        };
+
+        let size = response.content_length();
+        let stream = response.bytes_stream().map_err(io::Error::other);
+        Ok(Some((Box::pin(StreamReader::new(stream)), size)))
+    }
+
+    /// Looks the blob up without downloading it, returns `None` if the upstream does not have it.
+    pub async fn stat_blob(
+        &self,
+        name: &str,
+        digest: &Digest,
+    ) -> RegistryResult<Option<UpstreamBlob>> {
+        let path = format!("{name}/blobs/{digest}");
+        let Some(response) = self.request(Method::HEAD, name, &path, None).await? else {
+            return Ok(None);
+        };
+
+        // The length of a HEAD response's body is 0, the blob's size is only in the header.
+        let Some(size) = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+        else {
+            return Err(upstream_error(format!(
+                "blob {name}@{digest} has no length"
+            )));
+        };
+
+        let media_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        Ok(Some(UpstreamBlob { size, media_type }))
+    }
+
+    /// Sends a request for `/v2/{path}`, authenticating if the upstream asks for it. Returns
+    /// `None` if the upstream answers 404.
+    async fn request(
+        &self,
+        method: Method,
+        name: &str,
+        path: &str,
+        accept: Option<&str>,
+    ) -> RegistryResult<Option<Response>> {
+        let url = format!("{}/v2/{path}", self.config.upstream_url);
+        let scope = format!("repository:{name}:pull");
+        let request = || {
+            let request = self.client.request(method.clone(), &url);
+            match accept {
+                Some(accept) => request.header(header::ACCEPT, accept),
+                None => request,
+            }
+        };
+
+        let token = self.tokens.lock().unwrap().get(&scope).cloned();
+        let mut response = send(match &token {
+            Some(token) => request().bearer_auth(token),
+            None => request(),
+        })
+        .await?;
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let challenge = response
+                .headers()
+                .get(header::WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+
+            response = match self.authenticate(&challenge, &scope).await? {
+                Some(token) => send(request().bearer_auth(token)).await?,
+                None => match &self.config.username {
+                    Some(username) => {
+                        send(request().basic_auth(username, self.config.password.as_ref())).await?
+                    }
+                    None => response,
+                },
+            };
+        }
+
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                info!("Upstream does not have {path}");
+                Ok(None)
+            }
+            status if status.is_success() => Ok(Some(response)),
+            status => Err(upstream_error(format!("{method} {path} returned {status}"))),
+        }
+    }
+
+    /// Requests a token from the service named by a `Bearer` challenge. Returns `None` for other
+    /// challenges, which are answered with the configured credentials instead.
+    async fn authenticate(&self, challenge: &str, scope: &str) -> RegistryResult<Option<String>> {
+        let Some(parameters) = challenge
+            .strip_prefix("Bearer ")
+            .or_else(|| challenge.strip_prefix("bearer "))
+        else {
+            return Ok(None);
+        };
+        let parameters = parse_challenge_parameters(parameters);
+
+        let Some(realm) = parameters.get("realm") else {
+            return Err(upstream_error(format!(
+                "challenge without realm: {challenge}"
+            )));
+        };
+
+        let mut query = vec![(
+            "scope",
+            parameters.get("scope").map(String::as_str).unwrap_or(scope),
+        )];
+        if let Some(service) = parameters.get("service") {
+            query.push(("service", service));
+        }
+
+        info!("Requesting upstream token for {scope} from {realm}");
+        let mut request = self.client.get(realm).query(&query);
+        if let Some(username) = &self.config.username {
+            request = request.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let response = send(request).await?;
+        if !response.status().is_success() {
+            return Err(upstream_error(format!(
+                "token request returned {}",
+                response.status()
+            )));
+        }
+
+        let body: TokenResponse = response.json().await.map_err(upstream_error)?;
+        let Some(token) = body.token.or(body.access_token) else {
+            return Err(upstream_error("token response without a token"));
+        };
+
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(scope.to_string(), token.clone());
+
+        Ok(Some(token))
+    }
+}
+
+async fn send(request: RequestBuilder) -> RegistryResult<Response> {
+    request.send().await.map_err(upstream_error)
+}
+
+fn upstream_error(err: impl ToString) -> RegistryError {
+    RegistryError::UpstreamError(err.to_string())
+}
+
+/// Parses the `key="value"` pairs of an authentication challenge, values may contain commas.
+fn parse_challenge_parameters(parameters: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let mut rest = parameters.trim();
+
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().to_lowercase();
+        let value = value.trim_start();
+
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+
+        result.insert(key, value.to_string());
+        rest = remainder.trim_start_matches([',', ' ']);
+    }
+
+    result
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rocket::tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::types::digest::DigestAlgorithm;
+
+    use super::*;
+
+    pub(crate) const IMAGE_MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+    const TOKEN: &str = "stand-in-token";
+
+    /// A registry that serves canned manifests and blobs over plain HTTP on a local port. With
+    /// `requires_token` it answers requests without its token with a `Bearer` challenge, like
+    /// Docker Hub does.
+    #[derive(Clone, Default)]
+    pub(crate) struct StandInUpstream {
+        pub manifests: HashMap<String, (String, Vec<u8>)>,
+        pub blobs: HashMap<String, Vec<u8>>,
+        pub requires_token: bool,
+        /// Digests that are reported wrongly for the manifest with the key's reference.
+        pub wrong_digests: HashMap<String, String>,
+        /// The method and path of every request that was answered.
+        pub requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StandInUpstream {
+        pub fn add_manifest(&mut self, tag: &str, media_type: &str, data: &[u8]) -> Digest {
+            let digest = DigestAlgorithm::Sha256.digest(data);
+            for reference in [tag.to_string(), digest.to_string()] {
+                self.manifests
+                    .insert(reference, (media_type.to_string(), data.to_vec()));
+            }
+            digest
+        }
+
+        pub fn add_blob(&mut self, data: &[u8]) -> Digest {
+            let digest = DigestAlgorithm::Sha256.digest(data);
+            self.blobs.insert(digest.to_string(), data.to_vec());
+            digest
+        }
+
+        /// Serves the content until the test ends, returns the URL of the registry.
+        pub async fn serve(self) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+
+            let realm = format!("{url}/token");
+            rocket::tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut request = vec![];
+                    let mut buffer = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => break,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+
+                    let response = self.respond(&String::from_utf8_lossy(&request), &realm);
+                    stream.write_all(&response).await.ok();
+                }
+            });
+
+            url
+        }
+
+        fn respond(&self, request: &str, realm: &str) -> Vec<u8> {
+            let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+            let method = request_line.next().unwrap_or_default();
+            let path = request_line.next().unwrap_or_default();
+            let authorized = request
+                .to_lowercase()
+                .contains(&format!("authorization: bearer {TOKEN}"));
+            self.requests
+                .lock()
+                .unwrap()
+                .push(format!("{method} {path}"));
+
+            let (status, headers, body) = if path.starts_with("/token") {
+                (
+                    200,
+                    vec![],
+                    format!("{{\"token\": \"{TOKEN}\"}}").into_bytes(),
+                )
+            } else if self.requires_token && !authorized {
+                let challenge = format!(
+                    "WWW-Authenticate: Bearer realm=\"{realm}\",service=\"stand-in\",scope=\"repository:library/app:pull\""
+                );
+                (401, vec![challenge], vec![])
+            } else if let Some((_, reference)) = path.split_once("/manifests/") {
+                match self.manifests.get(reference) {
+                    Some((media_type, data)) => {
+                        let digest = self
+                            .wrong_digests
+                            .get(reference)
+                            .cloned()
+                            .unwrap_or_else(|| DigestAlgorithm::Sha256.digest(data).to_string());
+                        let headers = vec![
+                            format!("Content-Type: {media_type}"),
+                            format!("{DOCKER_CONTENT_DIGEST_HEADER_NAME}: {digest}"),
+                        ];
+                        (200, headers, data.clone())
+                    }
+                    None => (404, vec![], vec![]),
+                }
+            } else if let Some((_, digest)) = path.split_once("/blobs/") {
+                match self.blobs.get(digest) {
+                    Some(data) => (
+                        200,
+                        vec!["Content-Type: application/octet-stream".to_string()],
+                        data.clone(),
+                    ),
+                    None => (404, vec![], vec![]),
+                }
+            } else {
+                (404, vec![], vec![])
+            };
+
+            let mut response = format!(
+                "HTTP/1.1 {status} Stand-in\r\nConnection: close\r\nContent-Length: {}\r\n",
+                body.len()
+            );
+            for header in headers {
+                response.push_str(&header);
+                response.push_str("\r\n");
+            }
+            response.push_str("\r\n");
+
+            let mut response = response.into_bytes();
+            if method != "HEAD" {
+                response.extend_from_slice(&body);
+            }
+            response
+        }
+    }
+
+    pub(crate) fn proxy_config(namespace: &str, upstream_url: String) -> ProxyConfig {
+        ProxyConfig {
+            namespace: namespace.to_string(),
+            upstream_url,
+            tag_ttl: Duration::from_secs(60),
+            username: None,
+            password: None,
+        }
+    }
+
+    async fn upstream_registry(stand_in: StandInUpstream) -> UpstreamRegistry {
+        UpstreamRegistry::new(proxy_config("hub", stand_in.serve().await))
+    }
+
+    #[rocket::async_test]
+    async fn fetches_a_manifest_after_a_bearer_challenge() {
+        let mut stand_in = StandInUpstream {
+            requires_token: true,
+            ..Default::default()
+        };
+        let digest = stand_in.add_manifest("latest", IMAGE_MANIFEST_TYPE, b"{}");
+        let requests = stand_in.requests.clone();
+        let upstream = upstream_registry(stand_in).await;
+
+        for reference in ["latest".to_string(), digest.to_string()] {
+            let manifest = upstream
+                .fetch_manifest("library/app", &reference)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(manifest.media_type, IMAGE_MANIFEST_TYPE);
+            assert_eq!(manifest.data, b"{}");
+        }
+
+        // The token is kept for the second request.
+        let token_requests = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with("GET /token"))
+            .count();
+        assert_eq!(token_requests, 1);
+    }
+
+    #[rocket::async_test]
+    async fn rejects_a_manifest_that_does_not_match_its_digest() {
+        let mut stand_in = StandInUpstream::default();
+        stand_in.add_manifest("latest", IMAGE_MANIFEST_TYPE, b"{}");
+        let other_digest = DigestAlgorithm::Sha256.digest(b"other").to_string();
+        stand_in
+            .wrong_digests
+            .insert("latest".to_string(), other_digest);
+        let upstream = upstream_registry(stand_in).await;
+
+        let result = upstream.fetch_manifest("library/app", "latest").await;
+        assert!(matches!(result, Err(RegistryError::InvalidDigest)));
+    }
+
+    #[rocket::async_test]
+    async fn returns_none_for_missing_content() {
+        let upstream = upstream_registry(StandInUpstream::default()).await;
+        let digest = DigestAlgorithm::Sha256.digest(b"missing");
+
+        assert!(upstream
+            .fetch_manifest("library/app", "latest")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(upstream
+            .fetch_blob("library/app", &digest)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(upstream
+            .stat_blob("library/app", &digest)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[rocket::async_test]
+    async fn streams_a_blob() {
+        let mut stand_in = StandInUpstream::default();
+        let digest = stand_in.add_blob(b"layer content");
+        let upstream = upstream_registry(stand_in).await;
+
+        let mut data = vec![];
+        let (mut reader, size) = upstream
+            .fetch_blob("library/app", &digest)
+            .await
+            .unwrap()
+            .unwrap();
+        reader.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"layer content");
+        assert_eq!(size, Some(13));
+    }
+
+    #[rocket::async_test]
+    async fn stats_a_blob_without_downloading_it() {
+        let mut stand_in = StandInUpstream::default();
+        let digest = stand_in.add_blob(b"layer content");
+        let requests = stand_in.requests.clone();
+        let upstream = upstream_registry(stand_in).await;
+
+        let blob = upstream
+            .stat_blob("library/app", &digest)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(blob.size, 13);
+        assert_eq!(blob.media_type.as_deref(), Some("application/octet-stream"));
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![format!("HEAD /v2/library/app/blobs/{digest}")]
+        );
+    }
+
+    #[test]
+    fn parses_challenge_parameters() {
+        let parameters = parse_challenge_parameters(
+            "realm=\"https://auth.docker.io/token\",service=\"registry.docker.io\",scope=\"repository:library/alpine:pull,push\"",
+        );
+        assert_eq!(parameters["realm"], "https://auth.docker.io/token");
+        assert_eq!(parameters["service"], "registry.docker.io");
+        assert_eq!(parameters["scope"], "repository:library/alpine:pull,push");
+    }
+}
@@ -13,7 +13,7 @@ export const Api = {
       return get<Repositories>("/repositories");
     },
     getOne: (name: string) => {
-      return get<Repository>(`/repositories/${name}`);
+      return get<Repository>(`/repositories/${encodeURIComponent(name)}`);
     },
   },
 };
@@ -2,15 +2,19 @@ export interface Repositories {
   repositories: ListRepository[];
 }
 
+export type Visibility = "public" | "private";
+
 export interface ListRepository {
   name: string;
   author: string;
+  visibility: Visibility;
   lastModified: string;
 }
 
 export interface Repository {
   name: string;
   author: string;
+  visibility: Visibility;
   tags: Tag[];
 }
 
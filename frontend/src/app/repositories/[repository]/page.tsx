@@ -8,7 +8,9 @@ export default async function Page({
 }) {
   return (
     <main className="main">
-      <RepositoryView repositoryName={params.repository} />
+      <RepositoryView
+        repositoryName={decodeURIComponent(params.repository)}
+      />
     </main>
   );
 }
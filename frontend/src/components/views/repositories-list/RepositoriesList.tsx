@@ -70,7 +70,7 @@ const RepositoryRow = ({ repo }: { repo: ListRepository }) => {
           <p>{repo.author}</p>
         </div>
       </div>
-      <Link href={`/repositories/${repo.name}`}>
+      <Link href={`/repositories/${encodeURIComponent(repo.name)}`}>
         <IconButton className={"margin-left margin-right"}>
           <FontAwesomeIcon icon={faAngleRight} />
         </IconButton>